@@ -1,6 +1,7 @@
 use codspeed_criterion_compat::{criterion_group, criterion_main, Criterion};
-use oxhttp::model::{Body, Method, Request, Response, Status};
+use oxhttp::model::{Body, HeaderName, HeaderValue, Headers, Method, Request, Response, Status};
 use oxhttp::{Client, Server};
+use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::net::{Ipv4Addr, SocketAddrV4};
@@ -72,11 +73,96 @@ fn client_server_chunked_body(c: &mut Criterion) {
     });
 }
 
+fn client_server_many_headers(c: &mut Criterion) {
+    Server::new(|_| Response::builder(Status::OK).build())
+        .bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3459))
+        .spawn()
+        .unwrap();
+
+    let client = Client::new();
+    let url = Url::parse("http://localhost:3459").unwrap();
+    let mut headers = Headers::new();
+    for i in 0..50 {
+        headers.set(
+            HeaderName::try_from(format!("x-header-{i}")).unwrap(),
+            HeaderValue::try_from(format!("some value {i}")).unwrap(),
+        );
+    }
+
+    c.bench_function("client_server_many_headers", |b| {
+        b.iter(|| {
+            let mut request = Request::builder(Method::GET, url.clone());
+            *request.headers_mut() = headers.clone();
+            client.request(request.build()).unwrap();
+        })
+    });
+}
+
+fn client_server_large_response_headers(c: &mut Criterion) {
+    let mut headers = Headers::new();
+    for i in 0..200 {
+        headers.set(
+            HeaderName::try_from(format!("x-header-{i}")).unwrap(),
+            HeaderValue::try_from(format!("some value {i}")).unwrap(),
+        );
+    }
+    Server::new(move |_| {
+        let mut response = Response::builder(Status::OK);
+        *response.headers_mut() = headers.clone();
+        response.build()
+    })
+    .bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3460))
+    .spawn()
+    .unwrap();
+
+    let client = Client::new();
+    let url = Url::parse("http://localhost:3460").unwrap();
+
+    c.bench_function("client_server_large_response_headers", |b| {
+        b.iter(|| {
+            client
+                .request(Request::builder(Method::GET, url.clone()).build())
+                .unwrap();
+        })
+    });
+}
+
+fn client_server_file_body(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(format!("oxhttp-bench-{}", std::process::id()));
+    std::fs::write(&path, vec![16u8; 10 * 1024 * 1024]).unwrap();
+    Server::new(move |_| {
+        Response::builder(Status::OK)
+            .with_body(Body::from_file(File::open(&path).unwrap()).unwrap())
+    })
+    .bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3461))
+    .spawn()
+    .unwrap();
+
+    let client = Client::new();
+    let url = Url::parse("http://localhost:3461").unwrap();
+
+    c.bench_function("client_server_file_body", |b| {
+        b.iter(|| {
+            io::copy(
+                client
+                    .request(Request::builder(Method::GET, url.clone()).build())
+                    .unwrap()
+                    .body_mut(),
+                &mut io::sink(),
+            )
+            .unwrap();
+        })
+    });
+}
+
 criterion_group!(
     client_server,
     client_server_no_body,
     client_server_fixed_body,
-    client_server_chunked_body
+    client_server_chunked_body,
+    client_server_many_headers,
+    client_server_large_response_headers,
+    client_server_file_body
 );
 
 criterion_main!(client_server);
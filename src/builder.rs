@@ -0,0 +1,34 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Error returned when building a [`Client`](crate::Client) or a [`Server`](crate::Server) from an invalid configuration.
+#[derive(Debug, Clone)]
+pub struct BuildError {
+    message: String,
+}
+
+impl BuildError {
+    #[inline]
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for BuildError {}
+
+impl From<BuildError> for io::Error {
+    #[inline]
+    fn from(error: BuildError) -> Self {
+        Self::new(io::ErrorKind::InvalidInput, error)
+    }
+}
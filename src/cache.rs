@@ -0,0 +1,336 @@
+use crate::model::date::parse_http_date;
+use crate::model::{Body, HeaderName, HeaderValue, Headers, Method, Request, Response, Status};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Result};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Maximal size of a response body stored in the cache.
+const MAX_CACHED_BODY_SIZE: u64 = 1024 * 1024;
+
+/// Statuses that are [cacheable by default](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#rfc.section.15.1).
+const CACHEABLE_STATUSES: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// In-memory [private cache](https://www.rfc-editor.org/rfc/rfc9111) of responses to `GET` and `HEAD` requests.
+pub struct ResponseCache {
+    capacity: usize,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct CacheKey {
+    method: Method,
+    url: String,
+}
+
+struct CacheEntry {
+    status: Status,
+    headers: Headers,
+    body: Vec<u8>,
+    /// The request header values selected by the `Vary` response header
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    /// When the response has been received or revalidated
+    stored_at: Instant,
+    /// The response age when it has been received
+    initial_age: Duration,
+    freshness_lifetime: Duration,
+    last_used: Instant,
+}
+
+/// Result of a cache lookup.
+pub enum CacheLookup {
+    /// A fresh response that can be used as is
+    Fresh(Box<Response>),
+    /// A stale response that must be revalidated with the given conditional headers
+    Stale(Headers),
+    Miss,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Looks for a response to the request.
+    pub fn lookup(&self, request: &Request) -> CacheLookup {
+        if !is_cacheable_request(request) {
+            return CacheLookup::Miss;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(&CacheKey::new(request)) else {
+            return CacheLookup::Miss;
+        };
+        if !entry.matches_vary(request.headers()) {
+            return CacheLookup::Miss;
+        }
+        entry.last_used = Instant::now();
+        if entry.current_age() < entry.freshness_lifetime
+            && !has_directive(request.headers(), "no-cache")
+        {
+            return CacheLookup::Fresh(Box::new(entry.to_response()));
+        }
+        let mut conditions = Headers::new();
+        if let Some(etag) = entry.headers.get(&HeaderName::ETAG) {
+            conditions.set(HeaderName::IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = entry.headers.get(&HeaderName::LAST_MODIFIED) {
+            conditions.set(HeaderName::IF_MODIFIED_SINCE, last_modified.clone());
+        }
+        if conditions.is_empty() {
+            CacheLookup::Miss
+        } else {
+            CacheLookup::Stale(conditions)
+        }
+    }
+
+    /// Returns the stored response updated with the headers of the `304 Not Modified` revalidation response.
+    pub fn revalidated(&self, request: &Request, not_modified: &Response) -> Option<Response> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&CacheKey::new(request))?;
+        for (name, value) in not_modified.headers() {
+            if *name != HeaderName::CONTENT_LENGTH && *name != HeaderName::TRANSFER_ENCODING {
+                entry.headers.set(name.clone(), value.clone());
+            }
+        }
+        entry.stored_at = Instant::now();
+        entry.initial_age = age(not_modified.headers());
+        entry.freshness_lifetime = freshness_lifetime(&entry.headers).unwrap_or_default();
+        Some(entry.to_response())
+    }
+
+    /// Stores the response if it is cacheable and returns it.
+    ///
+    /// The response body is buffered if it is not too large.
+    pub fn store(&self, request: &Request, mut response: Response) -> Result<Response> {
+        if !request.method().is_safe() {
+            // The unsafe methods invalidate the stored responses
+            let url = request.url().to_string();
+            self.entries.lock().unwrap().retain(|key, _| key.url != url);
+            return Ok(response);
+        }
+        if !is_cacheable_request(request)
+            || !is_cacheable_response(&response)
+            || response
+                .body()
+                .len()
+                .is_some_and(|len| len > MAX_CACHED_BODY_SIZE)
+        {
+            return Ok(response);
+        }
+        let Some(freshness_lifetime) = freshness_lifetime(response.headers()).or_else(|| {
+            // Responses without explicit freshness are only useful if they can be revalidated
+            (response.headers().contains(&HeaderName::ETAG)
+                || response.headers().contains(&HeaderName::LAST_MODIFIED))
+            .then_some(Duration::ZERO)
+        }) else {
+            return Ok(response);
+        };
+        let vary = match vary(&response, request.headers()) {
+            Some(vary) => vary,
+            None => return Ok(response),
+        };
+        let mut body = Vec::new();
+        response
+            .body_mut()
+            .take(MAX_CACHED_BODY_SIZE + 1)
+            .read_to_end(&mut body)?;
+        if u64::try_from(body.len()).unwrap_or(u64::MAX) > MAX_CACHED_BODY_SIZE {
+            // Too large, we give back the body without storing it
+            let rest = std::mem::take(response.body_mut());
+            *response.body_mut() = Body::from_read(Cursor::new(body).chain(rest));
+            return Ok(response);
+        }
+        let now = Instant::now();
+        let entry = CacheEntry {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body,
+            vary,
+            stored_at: now,
+            initial_age: age(response.headers()),
+            freshness_lifetime,
+            last_used: now,
+        };
+        let response = entry.to_response();
+        let mut entries = self.entries.lock().unwrap();
+        let key = CacheKey::new(request);
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            // We evict the least recently used entry
+            if let Some(evicted) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&evicted);
+            }
+        }
+        entries.insert(key, entry);
+        Ok(response)
+    }
+}
+
+impl CacheKey {
+    fn new(request: &Request) -> Self {
+        Self {
+            method: request.method().clone(),
+            url: request.url().to_string(),
+        }
+    }
+}
+
+impl CacheEntry {
+    fn current_age(&self) -> Duration {
+        self.initial_age + self.stored_at.elapsed()
+    }
+
+    fn matches_vary(&self, headers: &Headers) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| headers.get(name) == value.as_ref())
+    }
+
+    fn to_response(&self) -> Response {
+        let mut response = Response::builder(self.status).with_body(self.body.clone());
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+fn is_cacheable_request(request: &Request) -> bool {
+    (*request.method() == Method::GET || *request.method() == Method::HEAD)
+        && !has_directive(request.headers(), "no-store")
+        // The caller handles the validation itself
+        && ![
+            HeaderName::IF_MATCH,
+            HeaderName::IF_NONE_MATCH,
+            HeaderName::IF_MODIFIED_SINCE,
+            HeaderName::IF_UNMODIFIED_SINCE,
+            HeaderName::IF_RANGE,
+            HeaderName::RANGE,
+        ]
+        .iter()
+        .any(|name| request.headers().contains(name))
+}
+
+fn is_cacheable_response(response: &Response) -> bool {
+    CACHEABLE_STATUSES.contains(&u16::from(response.status()))
+        && !has_directive(response.headers(), "no-store")
+}
+
+/// Returns the request header values selected by the `Vary` header or `None` if the response can't be reused
+fn vary(
+    response: &Response,
+    request_headers: &Headers,
+) -> Option<Vec<(HeaderName, Option<HeaderValue>)>> {
+    let Some(vary) = response.header(&HeaderName::VARY) else {
+        return Some(Vec::new());
+    };
+    vary.to_str()
+        .ok()?
+        .split(',')
+        .map(|name| {
+            let name = HeaderName::try_from(name.trim().to_owned()).ok()?; // Also rejects "*"
+            let value = request_headers.get(&name).cloned();
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// Computes the freshness lifetime from the `Cache-Control` or `Expires` headers
+fn freshness_lifetime(headers: &Headers) -> Option<Duration> {
+    if has_directive(headers, "no-cache") {
+        return Some(Duration::ZERO);
+    }
+    if let Some(max_age) = directive_value(headers, "max-age") {
+        return Some(Duration::from_secs(max_age.parse().ok()?));
+    }
+    let expires = headers.get(&HeaderName::new_unchecked("expires"))?;
+    let Some(expires) = parse_http_date(expires) else {
+        return Some(Duration::ZERO); // Invalid dates mean already expired
+    };
+    let date = headers
+        .get(&HeaderName::DATE)
+        .and_then(parse_http_date)
+        .unwrap_or_else(SystemTime::now);
+    Some(expires.duration_since(date).unwrap_or_default())
+}
+
+fn age(headers: &Headers) -> Duration {
+    headers
+        .get(&HeaderName::new_unchecked("age"))
+        .and_then(|v| v.to_str().ok()?.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default()
+}
+
+fn cache_control_directives(headers: &Headers) -> impl Iterator<Item = (&str, Option<&str>)> {
+    headers
+        .get(&HeaderName::new_unchecked("cache-control"))
+        .and_then(|v| v.to_str().ok())
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .map(|directive| {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name, Some(value.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            (name.trim(), value)
+        })
+}
+
+fn has_directive(headers: &Headers, directive: &str) -> bool {
+    cache_control_directives(headers).any(|(name, _)| name.eq_ignore_ascii_case(directive))
+}
+
+fn directive_value<'a>(headers: &'a Headers, directive: &str) -> Option<&'a str> {
+    cache_control_directives(headers)
+        .find(|(name, _)| name.eq_ignore_ascii_case(directive))?
+        .1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(entries: &[(&'static str, &'static str)]) -> Headers {
+        let mut headers = Headers::new();
+        for (name, value) in entries {
+            headers.set(
+                HeaderName::try_from(*name).unwrap(),
+                HeaderValue::try_from(*value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn freshness_lifetime_from_headers() {
+        assert_eq!(
+            freshness_lifetime(&headers(&[("cache-control", "public, max-age=60")])),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            freshness_lifetime(&headers(&[("cache-control", "no-cache, max-age=60")])),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            freshness_lifetime(&headers(&[
+                ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                ("expires", "Sun, 06 Nov 1994 08:50:37 GMT")
+            ])),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            freshness_lifetime(&headers(&[("expires", "0")])),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(freshness_lifetime(&headers(&[])), None);
+    }
+}
@@ -3,24 +3,60 @@ use crate::model::{
     RequestBuilder, Response, Status, Url,
 };
 use crate::utils::invalid_data_error;
+#[cfg(any(feature = "client", feature = "server"))]
+use std::cell::Cell;
 use std::cmp::min;
 use std::io::{BufRead, Error, ErrorKind, Read, Result};
+#[cfg(any(feature = "client", feature = "server"))]
+use std::rc::Rc;
 use std::str::{self, FromStr};
 
 const DEFAULT_SIZE: usize = 1024;
-const MAX_HEADER_SIZE: u64 = 8 * 1024;
+/// Default maximal size in bytes of the message headers and of the chunked encoding trailers.
+pub const MAX_HEADER_SIZE: u64 = 8 * 1024;
 
+/// Decodes the request line and the headers of a request.
+///
+/// The URL scheme is `https` if `is_connection_secure` is set and `http` otherwise.
+/// Requests whose headers are larger than `max_header_size` bytes are rejected.
+/// The body is then decoded with [`decode_request_body`].
 pub fn decode_request_headers(
     reader: &mut impl BufRead,
     is_connection_secure: bool,
+    max_header_size: u64,
+) -> Result<RequestBuilder> {
+    decode_request_head(reader, is_connection_secure, max_header_size, false)
+}
+
+/// Same as [`decode_request_headers`], the request target path is normalized with [`normalize_target_path`] if `normalize_path` is set.
+pub(crate) fn decode_request_head(
+    reader: &mut impl BufRead,
+    is_connection_secure: bool,
+    max_header_size: u64,
+    normalize_path: bool,
 ) -> Result<RequestBuilder> {
     // Let's read the headers
-    let buffer = read_header_bytes(reader)?;
+    let buffer = read_header_bytes(reader, max_header_size)?;
+    if buffer.starts_with(b"PRI * HTTP/2.0\n") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "The HTTP/2 connection preface has been received but this server only supports HTTP/1.1",
+        ));
+    }
     let mut headers = [httparse::EMPTY_HEADER; DEFAULT_SIZE];
     let mut parsed_request = httparse::Request::new(&mut headers);
     if parsed_request
         .parse(&buffer)
-        .map_err(invalid_data_error)?
+        .map_err(|e| {
+            if e == httparse::Error::Version {
+                Error::new(
+                    ErrorKind::Unsupported,
+                    "Unsupported HTTP version, only HTTP/1.0 and HTTP/1.1 are supported",
+                )
+            } else {
+                invalid_data_error(e)
+            }
+        })?
         .is_partial()
     {
         return Err(invalid_data_error(
@@ -38,6 +74,13 @@ pub fn decode_request_headers(
     let path = parsed_request
         .path
         .ok_or_else(|| invalid_data_error("No path in the HTTP request"))?;
+    let normalized_path;
+    let path = if normalize_path && path != "*" {
+        normalized_path = normalize_target_path(path)?;
+        normalized_path.as_str()
+    } else {
+        path
+    };
     let url = if let Some(host) = parsed_request.headers.iter().find_map(|header| {
         if header.name.eq_ignore_ascii_case("host") {
             Some(header.value)
@@ -81,6 +124,9 @@ pub fn decode_request_headers(
     }
 
     let mut request = Request::builder(method, url);
+    if path == "*" {
+        request = request.with_asterisk_form();
+    }
     for header in parsed_request.headers {
         request.headers_mut().append(
             HeaderName::new_unchecked(header.name.to_ascii_lowercase()),
@@ -89,27 +135,134 @@ pub fn decode_request_headers(
     }
     if parsed_request.version == Some(0) {
         // Hack to fallback to default HTTP 1.0 behavior of closing connections
-        if !request.headers().contains(&HeaderName::CONNECTION) {
-            request.headers_mut().append(
+        // unless the client explicitly asks for a persistent connection
+        if !request
+            .header(&HeaderName::CONNECTION)
+            .is_some_and(|connection| connection.contains_token(b"keep-alive"))
+        {
+            request.headers_mut().set(
                 HeaderName::CONNECTION,
                 HeaderValue::new_unchecked("close".as_bytes()),
             )
         }
+        // HTTP 1.0 clients do not support interim responses, the 100-continue expectation must be ignored
+        if request
+            .header(&HeaderName::EXPECT)
+            .is_some_and(|expect| expect.contains_token(b"100-continue"))
+        {
+            request.headers_mut().remove(&HeaderName::EXPECT);
+        }
     }
     Ok(request)
 }
 
+/// Removes the `.` and `..` segments from the path of a request target, keeping the query string as is.
+///
+/// The segments are compared after percent-decoding (e.g. `%2e%2E` is a `..` segment).
+/// Targets with a `..` segment going above the root, or with a segment containing an invalid percent-encoding
+/// or an encoded `/`, `\` or NUL character are rejected.
+/// Targets with a raw `\` in their path are also rejected because URL parsers may consider it as a `/`.
+fn normalize_target_path(target: &str) -> Result<String> {
+    // The path of absolute-form targets starts after the authority
+    let path_start = match target.find("://") {
+        Some(scheme_end) if !target.starts_with('/') => target[scheme_end + 3..]
+            .find(['/', '?'])
+            .map_or(target.len(), |i| scheme_end + 3 + i),
+        _ => 0,
+    };
+    let (prefix, rest) = target.split_at(path_start);
+    let (path, query) = rest.split_at(rest.find('?').unwrap_or(rest.len()));
+    let Some(path) = path.strip_prefix('/') else {
+        return Ok(target.into());
+    };
+    let mut segments = Vec::new();
+    let mut is_directory = false;
+    for segment in path.split('/') {
+        is_directory = true;
+        match decode_path_segment(segment)?.as_slice() {
+            b"." => (),
+            b".." => {
+                if segments.pop().is_none() {
+                    return Err(invalid_data_error(format!(
+                        "The request target '{target}' goes above the root"
+                    )));
+                }
+            }
+            _ => {
+                segments.push(segment);
+                is_directory = false;
+            }
+        }
+    }
+    let mut normalized = format!("{prefix}/{}", segments.join("/"));
+    if is_directory && !segments.is_empty() {
+        normalized.push('/');
+    }
+    normalized.push_str(query);
+    Ok(normalized)
+}
+
+fn decode_path_segment(segment: &str) -> Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(segment.len());
+    let mut bytes = segment.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'\\' {
+            return Err(invalid_data_error(format!(
+                "The request path segment '{segment}' contains a backslash"
+            )));
+        }
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let decoded_byte = match (bytes.next(), bytes.next()) {
+            (Some(high), Some(low)) => str::from_utf8(&[high, low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .filter(|_| high.is_ascii_hexdigit()),
+            _ => None,
+        };
+        match decoded_byte {
+            Some(b'/' | b'\\' | b'\0') => {
+                return Err(invalid_data_error(format!(
+                    "The request path segment '{segment}' contains an encoded separator or NUL character"
+                )))
+            }
+            Some(decoded_byte) => decoded.push(decoded_byte),
+            None => {
+                return Err(invalid_data_error(format!(
+                    "The request path segment '{segment}' contains an invalid percent-encoding"
+                )))
+            }
+        }
+    }
+    Ok(decoded)
+}
+
+/// Decodes the body of a request whose head has been decoded with [`decode_request_headers`].
+///
+/// The framing is given by the `Content-Length` or `Transfer-Encoding` headers.
+/// The body is read lazily from `reader`.
 pub fn decode_request_body(
     request: RequestBuilder,
     reader: impl BufRead + 'static,
+    max_header_size: u64,
 ) -> Result<Request> {
-    let body = decode_body(request.headers(), reader)?;
+    let body = decode_body(request.headers(), reader, max_header_size)?;
     Ok(request.with_body(body))
 }
 
-pub fn decode_response(mut reader: impl BufRead + 'static) -> Result<Response> {
+/// Decodes a response.
+///
+/// Responses whose headers are larger than `max_header_size` bytes are rejected.
+/// HTTP/1.0 responses without a `Connection: keep-alive` header get a `Connection: close` header.
+/// The body is read lazily from `reader`.
+pub fn decode_response(
+    mut reader: impl BufRead + 'static,
+    max_header_size: u64,
+) -> Result<Response> {
     // Let's read the headers
-    let buffer = read_header_bytes(&mut reader)?;
+    let buffer = read_header_bytes(&mut reader, max_header_size)?;
     let mut headers = [httparse::EMPTY_HEADER; DEFAULT_SIZE];
     let mut parsed_response = httparse::Response::new(&mut headers);
     if parsed_response
@@ -137,15 +290,31 @@ pub fn decode_response(mut reader: impl BufRead + 'static) -> Result<Response> {
             HeaderValue::new_unchecked(header.value.to_vec()),
         );
     }
+    if parsed_response.version == Some(0)
+        && !response
+            .header(&HeaderName::CONNECTION)
+            .is_some_and(|connection| connection.contains_token(b"keep-alive"))
+    {
+        // HTTP 1.0 servers close the connection unless they explicitly keep it alive
+        response.headers_mut().set(
+            HeaderName::CONNECTION,
+            HeaderValue::new_unchecked("close".as_bytes()),
+        )
+    }
 
-    let body = decode_body(response.headers(), reader)?;
+    let body = decode_body(response.headers(), reader, max_header_size)?;
     Ok(response.with_body(body))
 }
 
-fn read_header_bytes(reader: impl BufRead) -> Result<Vec<u8>> {
-    let mut reader = reader.take(2 * MAX_HEADER_SIZE); // Makes sure we do not buffer too much
-    let mut buffer = Vec::with_capacity(DEFAULT_SIZE);
+fn read_header_bytes(reader: impl BufRead, max_header_size: u64) -> Result<Vec<u8>> {
+    let mut reader = reader.take(max_header_size.saturating_mul(2)); // Makes sure we do not buffer too much
+    let max_header_size = usize::try_from(max_header_size).unwrap_or(usize::MAX);
+    // We size the buffer after the already received bytes to avoid reallocations when all headers are there
+    let available = reader.fill_buf()?.len();
+    let mut buffer =
+        Vec::with_capacity(available.clamp(DEFAULT_SIZE, max_header_size.max(DEFAULT_SIZE)));
     loop {
+        let line_start = buffer.len();
         if reader.read_until(b'\n', &mut buffer)? == 0 {
             return Err(Error::new(
                 ErrorKind::ConnectionAborted,
@@ -158,21 +327,26 @@ fn read_header_bytes(reader: impl BufRead) -> Result<Vec<u8>> {
         }
         // We normalize line ends to plain \n
         if buffer.ends_with(b"\r\n") {
-            buffer.pop();
-            buffer.pop();
+            buffer.truncate(buffer.len() - 2);
             buffer.push(b'\n')
         }
-        if buffer.len() > (MAX_HEADER_SIZE as usize) {
-            return Err(invalid_data_error("The headers size should fit in 8kb"));
+        if buffer.len() > max_header_size {
+            return Err(invalid_data_error(format!(
+                "The headers size should fit in {max_header_size} bytes"
+            )));
         }
-        if buffer.ends_with(b"\n\n") {
-            break; //end of buffer
+        if line_start > 0 && buffer.len() == line_start + 1 {
+            break; // Empty line: end of the headers
         }
     }
     Ok(buffer)
 }
 
-fn decode_body(headers: &Headers, reader: impl BufRead + 'static) -> Result<Body> {
+fn decode_body(
+    headers: &Headers,
+    reader: impl BufRead + 'static,
+    max_header_size: u64,
+) -> Result<Body> {
     let content_length = headers.get(&HeaderName::CONTENT_LENGTH);
     let transfer_encoding = headers.get(&HeaderName::TRANSFER_ENCODING);
     if transfer_encoding.is_some() && content_length.is_some() {
@@ -187,23 +361,43 @@ fn decode_body(headers: &Headers, reader: impl BufRead + 'static) -> Result<Body
             .map_err(invalid_data_error)?
             .parse::<u64>()
             .map_err(invalid_data_error)?;
-        Body::from_read_and_len(reader, len)
+        Body::from_read_and_len(
+            SizedBodyReader {
+                reader: (len > 0).then_some(reader),
+                remaining: len,
+            },
+            len,
+        )
     } else if let Some(transfer_encoding) = transfer_encoding {
-        if transfer_encoding.as_ref().eq_ignore_ascii_case(b"chunked") {
-            Body::from_chunked_transfer_payload(ChunkedDecoder {
-                reader,
-                buffer: Vec::with_capacity(DEFAULT_SIZE),
-                is_start: true,
-                chunk_position: 0,
-                chunk_size: 0,
-                trailers: None,
-            })
-        } else {
+        let transfer_encoding = transfer_encoding.to_str().map_err(invalid_data_error)?;
+        let mut codings = transfer_encoding.split(',').map(str::trim).rev();
+        // The chunked coding must be the last one to delimit the body
+        if !codings
+            .next()
+            .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
+        {
             return Err(invalid_data_error(format!(
-                "Transfer-Encoding: {} is not supported",
-                transfer_encoding.to_str().map_err(invalid_data_error)?
+                "Transfer-Encoding: {transfer_encoding} is not supported, the last coding must be chunked"
             )));
         }
+        let mut body = Body::from_chunked_transfer_payload(ChunkedDecoder {
+            reader: Some(reader),
+            buffer: Vec::with_capacity(DEFAULT_SIZE),
+            is_start: true,
+            chunk_position: 0,
+            chunk_size: 0,
+            max_trailers_size: usize::try_from(max_header_size).unwrap_or(usize::MAX),
+            trailers: None,
+        });
+        // The other codings are removed in the reverse order of their application
+        for coding in codings {
+            body = decode_transfer_coding(body, coding).ok_or_else(|| {
+                invalid_data_error(format!(
+                    "Transfer-Encoding: {transfer_encoding} is not supported"
+                ))
+            })?;
+        }
+        body
     } else {
         Body::default()
     };
@@ -211,6 +405,19 @@ fn decode_body(headers: &Headers, reader: impl BufRead + 'static) -> Result<Body
     decode_content_encoding(body, headers)
 }
 
+/// Removes a [transfer coding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#transfer.codings)
+/// applied on top of the chunked one, returns `None` if it is not supported
+#[cfg_attr(not(feature = "flate2"), allow(unused_variables))]
+fn decode_transfer_coding(body: Body, coding: &str) -> Option<Body> {
+    match coding.to_ascii_lowercase().as_str() {
+        #[cfg(feature = "flate2")]
+        "gzip" | "x-gzip" => Some(body.decode_gzip()),
+        #[cfg(feature = "flate2")]
+        "deflate" => Some(body.decode_deflate()),
+        _ => None,
+    }
+}
+
 fn decode_content_encoding(body: Body, headers: &Headers) -> Result<Body> {
     let Some(content_encoding) = headers.get(&HeaderName::CONTENT_ENCODING) else {
         return Ok(body);
@@ -225,21 +432,49 @@ fn decode_content_encoding(body: Body, headers: &Headers) -> Result<Body> {
     }
 }
 
+/// Reads a body of known length and releases the underlying reader as soon as the body is fully read
+struct SizedBodyReader<R: Read> {
+    reader: Option<R>,
+    remaining: u64,
+}
+
+impl<R: Read> Read for SizedBodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let Some(reader) = &mut self.reader else {
+            return Ok(0);
+        };
+        let max_len = usize::try_from(self.remaining)
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+        let read = reader.read(&mut buf[..max_len])?;
+        self.remaining -= u64::try_from(read).map_err(invalid_data_error)?;
+        if self.remaining == 0 {
+            self.reader = None; // We release the reader, the message is fully read
+        }
+        Ok(read)
+    }
+}
+
+/// Reads a chunked body and releases the underlying reader as soon as the trailers are read
 struct ChunkedDecoder<R: BufRead> {
-    reader: R,
+    reader: Option<R>,
     buffer: Vec<u8>,
     is_start: bool,
     chunk_position: usize,
     chunk_size: usize,
+    max_trailers_size: usize,
     trailers: Option<Headers>,
 }
 
 impl<R: BufRead> Read for ChunkedDecoder<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let Some(reader) = &mut self.reader else {
+            return Ok(0); // We already read the trailers, it means we have finished reading
+        };
         loop {
             // In case we still have data
             if self.chunk_position < self.chunk_size {
-                let inner_buf = self.reader.fill_buf()?;
+                let inner_buf = reader.fill_buf()?;
                 if inner_buf.is_empty() {
                     return Err(invalid_data_error(
                         "Unexpected stream end in the middle of a chunked content",
@@ -250,7 +485,7 @@ impl<R: BufRead> Read for ChunkedDecoder<R> {
                     self.chunk_size - self.chunk_position,
                 );
                 buf[..size].copy_from_slice(&inner_buf[..size]);
-                self.reader.consume(size);
+                reader.consume(size);
                 self.chunk_position += size;
                 return Ok(size);
             }
@@ -262,7 +497,7 @@ impl<R: BufRead> Read for ChunkedDecoder<R> {
             } else {
                 // chunk end
                 self.buffer.clear();
-                self.reader.read_until(b'\n', &mut self.buffer)?;
+                reader.read_until(b'\n', &mut self.buffer)?;
                 if self.buffer != b"\r\n" && self.buffer != b"\n" {
                     return Err(invalid_data_error("Invalid chunked element end"));
                 }
@@ -270,7 +505,7 @@ impl<R: BufRead> Read for ChunkedDecoder<R> {
 
             // We load a new chunk
             self.buffer.clear();
-            self.reader.read_until(b'\n', &mut self.buffer)?;
+            reader.read_until(b'\n', &mut self.buffer)?;
             self.chunk_position = 0;
             let Ok(httparse::Status::Complete((read, chunk_size))) =
                 httparse::parse_chunk_size(&self.buffer)
@@ -287,11 +522,14 @@ impl<R: BufRead> Read for ChunkedDecoder<R> {
                 self.buffer.clear();
                 self.buffer.push(b'\n');
                 loop {
-                    if self.reader.read_until(b'\n', &mut self.buffer)? == 0 {
+                    if reader.read_until(b'\n', &mut self.buffer)? == 0 {
                         return Err(invalid_data_error("Missing chunked encoding end"));
                     }
-                    if self.buffer.len() > 8 * 1024 {
-                        return Err(invalid_data_error("The trailers size should fit in 8kb"));
+                    if self.buffer.len() > self.max_trailers_size {
+                        return Err(invalid_data_error(format!(
+                            "The trailers size should fit in {} bytes",
+                            self.max_trailers_size
+                        )));
                     }
 
                     if self.buffer.ends_with(b"\r\n") {
@@ -319,12 +557,16 @@ impl<R: BufRead> Read for ChunkedDecoder<R> {
                 }
                 let mut trailers = Headers::new();
                 for trailer in parsed_trailers {
-                    trailers.append(
-                        HeaderName::new_unchecked(trailer.name.to_ascii_lowercase()),
-                        HeaderValue::new_unchecked(trailer.value.to_vec()),
-                    );
+                    let name = HeaderName::new_unchecked(trailer.name.to_ascii_lowercase());
+                    if is_forbidden_trailer_name(&name) {
+                        return Err(invalid_data_error(format!(
+                            "The header {name} is not allowed in the trailer section"
+                        )));
+                    }
+                    trailers.append(name, HeaderValue::new_unchecked(trailer.value.to_vec()));
                 }
                 self.trailers = Some(trailers);
+                self.reader = None; // We release the reader, the message is fully read
                 return Ok(0);
             }
         }
@@ -335,30 +577,253 @@ impl<R: BufRead> ChunkedTransferPayload for ChunkedDecoder<R> {
     fn trailers(&self) -> Option<&Headers> {
         self.trailers.as_ref()
     }
+
+    fn read_chunk(&mut self, max_size: usize) -> Result<Option<Vec<u8>>> {
+        // An empty read loads the next chunk header if the current chunk is finished
+        let read = self.read(&mut [])?;
+        debug_assert_eq!(read, 0);
+        if self.reader.is_none() || self.chunk_position >= self.chunk_size {
+            return Ok(None);
+        }
+        // The buffer grows with the received bytes and not with the announced chunk size
+        let len = min(self.chunk_size - self.chunk_position, max_size);
+        let mut chunk = Vec::new();
+        self.take(u64::try_from(len).unwrap_or(u64::MAX))
+            .read_to_end(&mut chunk)?;
+        if chunk.len() < len {
+            return Err(invalid_data_error(
+                "The chunked body has been interrupted in the middle of a chunk",
+            ));
+        }
+        Ok(Some(chunk))
+    }
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+/// Gets back a connection reader lent to a message body with [`ReaderSlot::lend`].
+///
+/// The reader is kept between the successive messages of a connection
+/// so that the bytes of the next messages it has already buffered are not lost.
+/// It is given back as soon as the message body is fully read or dropped.
+pub(crate) struct ReaderSlot<R>(Rc<Cell<Option<R>>>);
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl<R: BufRead> ReaderSlot<R> {
+    /// Lends the reader: it is given back to the slot when the returned [`LentReader`] is dropped.
+    pub fn lend(reader: R) -> (Self, LentReader<R>) {
+        let slot = Rc::new(Cell::new(None));
+        (
+            Self(Rc::clone(&slot)),
+            LentReader {
+                reader: Some(reader),
+                slot,
+            },
+        )
+    }
+
+    /// Returns the reader if it has been given back, i.e. if the [`LentReader`] has been dropped.
+    pub fn take(&self) -> Option<R> {
+        self.0.take()
+    }
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+/// A reader lent with [`ReaderSlot::lend`].
+pub(crate) struct LentReader<R> {
+    reader: Option<R>,
+    slot: Rc<Cell<Option<R>>>,
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl<R: BufRead> LentReader<R> {
+    fn reader(&mut self) -> &mut R {
+        self.reader
+            .as_mut()
+            .expect("The reader is only removed on drop")
+    }
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl<R: BufRead> Read for LentReader<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.reader().read(buf)
+    }
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl<R: BufRead> BufRead for LentReader<R> {
+    #[inline]
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        self.reader().fill_buf()
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.reader().consume(amt)
+    }
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl<R> Drop for LentReader<R> {
+    fn drop(&mut self) {
+        self.slot.set(self.reader.take());
+    }
+}
+
+/// Checks if the header is not allowed in a [trailer section](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#trailer.limitations)
+///
+/// They are the headers controlling the message framing, the routing and the connection.
+fn is_forbidden_trailer_name(name: &HeaderName) -> bool {
+    *name == HeaderName::CONNECTION
+        || *name == HeaderName::CONTENT_ENCODING
+        || *name == HeaderName::CONTENT_LENGTH
+        || *name == HeaderName::HOST
+        || name.as_ref() == "keep-alive"
+        || name.as_ref() == "proxy-connection"
+        || *name == HeaderName::TE
+        || *name == HeaderName::TRAILER
+        || *name == HeaderName::TRANSFER_ENCODING
+        || *name == HeaderName::UPGRADE
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{copy, sink, BufReader, Cursor};
     use std::ops::Deref;
 
+    #[test]
+    fn read_header_bytes_does_not_reallocate() -> Result<()> {
+        let mut request = b"GET / HTTP/1.1\r\nHost: www.example.org\r\n".to_vec();
+        for i in 0..50 {
+            request.extend_from_slice(format!("X-Header-{i}: some value {i}\r\n").as_bytes());
+        }
+        request.extend_from_slice(b"\r\n");
+        let mut reader = BufReader::new(request.as_slice());
+        let buffer = read_header_bytes(&mut reader, MAX_HEADER_SIZE)?;
+        assert_eq!(buffer.capacity(), request.len());
+        assert!(buffer.ends_with(b"some value 49\n\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_with_max_header_size() -> Result<()> {
+        let request = format!(
+            "GET / HTTP/1.1\nhost: example.com\nx-large: {}\n\n",
+            "a".repeat(10 * 1024)
+        );
+        assert!(decode_request_headers(&mut request.as_bytes(), false, MAX_HEADER_SIZE).is_err());
+        let request = decode_request_headers(&mut request.as_bytes(), false, 16 * 1024)?;
+        assert_eq!(
+            request
+                .header(&HeaderName::new_unchecked("x-large"))
+                .unwrap()
+                .as_ref()
+                .len(),
+            10 * 1024
+        );
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n3\r\nfoo\r\n0\r\nx-large: {}\r\n\r\n",
+            "a".repeat(100)
+        );
+        let mut body = decode_response(Cursor::new(response.clone().into_bytes()), 64)?.into_body();
+        assert!(copy(&mut body, &mut sink()).is_err());
+        let mut body = decode_response(Cursor::new(response.into_bytes()), 1024)?.into_body();
+        copy(&mut body, &mut sink())?;
+        assert!(body.trailers().is_some());
+        Ok(())
+    }
+
+    #[cfg(any(feature = "client", feature = "server"))]
+    #[test]
+    fn decode_pipelined_responses() -> Result<()> {
+        let mut reader = BufReader::new(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nfooHTTP/1.1 201 Created\r\ncontent-length: 3\r\n\r\nbar".as_slice(),
+        );
+        for (status, body) in [(Status::OK, "foo"), (Status::CREATED, "bar")] {
+            let (slot, lent_reader) = ReaderSlot::lend(reader);
+            let response = decode_response(lent_reader, MAX_HEADER_SIZE)?;
+            assert_eq!(response.status(), status);
+            assert_eq!(response.into_body().to_string()?, body);
+            reader = slot.take().unwrap();
+        }
+        assert!(reader.fill_buf()?.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn decode_request_target_origin_form() -> Result<()> {
         let request = decode_request_headers(
             &mut b"GET /where?q=now HTTP/1.1\nHost: www.example.org\n\n".as_slice(),
             false,
+            MAX_HEADER_SIZE,
         )?;
         assert_eq!(request.url().as_str(), "http://www.example.org/where?q=now");
         Ok(())
     }
 
+    #[test]
+    fn decode_http_1_0_request_ignores_100_continue() -> Result<()> {
+        let request = decode_request_headers(
+            &mut b"POST / HTTP/1.0\nHost: example.com\nExpect: 100-continue\n\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
+        )?;
+        assert!(request.header(&HeaderName::EXPECT).is_none());
+        let request = decode_request_headers(
+            &mut b"POST / HTTP/1.0\nHost: example.com\nExpect: foo, 100-Continue\n\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
+        )?;
+        assert!(request.header(&HeaderName::EXPECT).is_none());
+        let request = decode_request_headers(
+            &mut b"POST / HTTP/1.1\nHost: example.com\nExpect: 100-continue\n\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
+        )?;
+        assert!(request.header(&HeaderName::EXPECT).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_http2_preface() {
+        let Err(error) = decode_request_headers(
+            &mut b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
+        ) else {
+            panic!("The HTTP/2 preface should not be parsed")
+        };
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert!(error.to_string().contains("HTTP/2"));
+    }
+
+    #[test]
+    fn decode_request_target_ipv6_host() -> Result<()> {
+        let request = decode_request_headers(
+            &mut b"GET /where HTTP/1.1\nHost: [fe80::1]:8080\n\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
+        )?;
+        assert_eq!(request.url().as_str(), "http://[fe80::1]:8080/where");
+        assert!(decode_request_headers(
+            &mut b"GET /where HTTP/1.1\nHost: fe80::1:8080\n\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
+        )
+        .is_err());
+        Ok(())
+    }
+
     #[test]
     fn decode_request_target_absolute_form_with_host() -> Result<()> {
         let request = decode_request_headers(
             &mut
               b"GET http://www.example.org/pub/WWW/TheProject.html HTTP/1.1\nHost: example.com\n\n".as_slice()
             ,
-            false,
+            false, MAX_HEADER_SIZE,
         )?;
         assert_eq!(
             request.url().as_str(),
@@ -372,6 +837,7 @@ mod tests {
         let request = decode_request_headers(
             &mut b"GET http://www.example.org/pub/WWW/TheProject.html HTTP/1.1\n\n".as_slice(),
             false,
+            MAX_HEADER_SIZE,
         )?;
         assert_eq!(
             request.url().as_str(),
@@ -385,6 +851,7 @@ mod tests {
         assert!(decode_request_headers(
             &mut b"GET /pub/WWW/TheProject.html HTTP/1.1\n\n".as_slice(),
             false,
+            MAX_HEADER_SIZE,
         )
         .is_err());
     }
@@ -394,11 +861,13 @@ mod tests {
         assert!(decode_request_headers(
             &mut b"GET https://www.example.org/pub/WWW/TheProject.html HTTP/1.1\n\n".as_slice(),
             false,
+            MAX_HEADER_SIZE,
         )
         .is_err());
         assert!(decode_request_headers(
             &mut b"GET http://www.example.org/pub/WWW/TheProject.html HTTP/1.1\n\n".as_slice(),
             true,
+            MAX_HEADER_SIZE,
         )
         .is_err());
     }
@@ -408,17 +877,88 @@ mod tests {
         assert!(decode_request_headers(
             &mut b"GET /foo<bar HTTP/1.1\nhost: www.example.com\n\n".as_slice(),
             false,
+            MAX_HEADER_SIZE,
         )
         .is_err());
     }
 
+    #[test]
+    fn normalize_request_target_paths() -> Result<()> {
+        for (target, expected) in [
+            ("/", "/"),
+            ("/a/b", "/a/b"),
+            ("/a/./b/../c", "/a/c"),
+            ("/a/b/..", "/a/"),
+            ("/a/.", "/a/"),
+            ("/a/%2e%2E/b/%2E", "/b/"),
+            ("/a/%C3%A9/./b", "/a/%C3%A9/b"),
+            ("/a/../?x=/../y", "/?x=/../y"),
+            ("http://example.com/a/../b?c", "http://example.com/b?c"),
+            ("http://example.com?c", "http://example.com?c"),
+        ] {
+            assert_eq!(normalize_target_path(target)?, expected, "{target}");
+        }
+        for target in [
+            "/..",
+            "/a/../..",
+            "/a/%2e%2e/%2E%2E/b",
+            "/a%2Fb",
+            "/a%5cb",
+            "/a\\b",
+            "/a/..\\..\\etc/passwd",
+            "/..\\etc/passwd",
+            "/a%00",
+            "/a%2",
+            "/a%+1",
+            "/a%zz",
+            "http://example.com/../a",
+        ] {
+            assert_eq!(
+                normalize_target_path(target).unwrap_err().kind(),
+                ErrorKind::InvalidData,
+                "{target}"
+            );
+        }
+
+        let request = decode_request_head(
+            &mut b"GET /a/../b?c=.. HTTP/1.1\nHost: www.example.com\n\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
+            true,
+        )?;
+        assert_eq!(request.url().as_str(), "http://www.example.com/b?c=..");
+        for target in ["/../b", "/a/..\\..\\etc/passwd", "/..\\etc/passwd"] {
+            assert!(
+                decode_request_head(
+                    &mut format!("GET {target} HTTP/1.1\nHost: www.example.com\n\n").as_bytes(),
+                    false,
+                    MAX_HEADER_SIZE,
+                    true,
+                )
+                .is_err(),
+                "{target}"
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn decode_request_target_asterisk_form() -> Result<()> {
         let request = decode_request_headers(
             &mut b"OPTIONS * HTTP/1.1\nHost: www.example.org:8001\n\n".as_slice(),
             false,
+            MAX_HEADER_SIZE,
+        )?;
+        assert_eq!(request.url().as_str(), "http://www.example.org:8001/");
+        assert!(request.is_asterisk_form());
+
+        let request = decode_request_headers(
+            &mut b"OPTIONS / HTTP/1.1\nHost: www.example.org:8001\n\n".as_slice(),
+            false,
+            MAX_HEADER_SIZE,
         )?;
-        assert_eq!(request.url().as_str(), "http://www.example.org:8001/"); //TODO: should be http://www.example.org:8001
+        assert_eq!(request.url().as_str(), "http://www.example.org:8001/");
+        assert!(!request.is_asterisk_form());
         Ok(())
     }
 
@@ -428,6 +968,7 @@ mod tests {
             &mut b"GET / HTTP/1.1\nHost: www.example.org:8001\nFoo: v1\nbar: vbar\nfoo: v2\n\n"
                 .as_slice(),
             true,
+            MAX_HEADER_SIZE,
         )?;
         assert_eq!(request.url().as_str(), "https://www.example.org:8001/");
         assert_eq!(
@@ -452,7 +993,11 @@ mod tests {
         let mut read =
             b"GET / HTTP/1.1\nHost: www.example.org:8001\ncontent-length: 9\n\nfoobarbar"
                 .as_slice();
-        let request = decode_request_body(decode_request_headers(&mut read, false)?, read)?;
+        let request = decode_request_body(
+            decode_request_headers(&mut read, false, MAX_HEADER_SIZE)?,
+            read,
+            MAX_HEADER_SIZE,
+        )?;
         assert_eq!(request.into_body().to_string()?, "foobarbar");
         Ok(())
     }
@@ -461,7 +1006,8 @@ mod tests {
     fn decode_request_empty_header_name() {
         assert!(decode_request_headers(
             &mut b"GET / HTTP/1.1\nHost: www.example.org:8001\n: foo".as_slice(),
-            false
+            false,
+            MAX_HEADER_SIZE
         )
         .is_err());
     }
@@ -470,7 +1016,8 @@ mod tests {
     fn decode_request_invalid_header_name_char() {
         assert!(decode_request_headers(
             &mut b"GET / HTTP/1.1\nHost: www.example.org:8001\nCont\xE9: foo".as_slice(),
-            false
+            false,
+            MAX_HEADER_SIZE
         )
         .is_err());
     }
@@ -480,7 +1027,8 @@ mod tests {
         assert!(decode_request_headers(
             &mut b"GET / HTTP/1.1\nHost: www.example.org:8001\nCont\t: foo\rbar\r\nTest: test"
                 .as_slice(),
-            false
+            false,
+            MAX_HEADER_SIZE
         )
         .is_err());
     }
@@ -488,7 +1036,7 @@ mod tests {
     #[test]
     fn decode_request_empty() {
         assert_eq!(
-            decode_request_headers(&mut b"".as_slice(), false)
+            decode_request_headers(&mut b"".as_slice(), false, MAX_HEADER_SIZE)
                 .err()
                 .map(|e| e.kind()),
             Some(ErrorKind::ConnectionAborted)
@@ -498,7 +1046,7 @@ mod tests {
     #[test]
     fn decode_request_stop_in_header() {
         assert_eq!(
-            decode_request_headers(&mut b"GET /\r\n".as_slice(), false)
+            decode_request_headers(&mut b"GET /\r\n".as_slice(), false, MAX_HEADER_SIZE)
                 .err()
                 .map(|e| e.kind()),
             Some(ErrorKind::ConnectionAborted)
@@ -510,11 +1058,15 @@ mod tests {
         let mut read =
             b"POST / HTTP/1.1\r\nhost: example.com\r\ncontent-length: 12\r\n\r\nfoobar".as_slice();
         assert_eq!(
-            decode_request_body(decode_request_headers(&mut read, false)?, read)?
-                .into_body()
-                .to_vec()
-                .err()
-                .map(|e| e.kind()),
+            decode_request_body(
+                decode_request_headers(&mut read, false, MAX_HEADER_SIZE)?,
+                read,
+                MAX_HEADER_SIZE
+            )?
+            .into_body()
+            .to_vec()
+            .err()
+            .map(|e| e.kind()),
             Some(ErrorKind::ConnectionAborted)
         );
         Ok(())
@@ -524,7 +1076,11 @@ mod tests {
     fn decode_request_http_1_0() -> Result<()> {
         let mut read =
             b"POST http://example.com/foo HTTP/1.0\r\ncontent-length: 12\r\n\r\nfoobar".as_slice();
-        let request = decode_request_body(decode_request_headers(&mut read, false)?, read)?;
+        let request = decode_request_body(
+            decode_request_headers(&mut read, false, MAX_HEADER_SIZE)?,
+            read,
+            MAX_HEADER_SIZE,
+        )?;
         assert_eq!(request.url().as_str(), "http://example.com/foo");
         assert_eq!(
             request.header(&HeaderName::CONNECTION).unwrap().deref(),
@@ -536,13 +1092,21 @@ mod tests {
     #[test]
     fn decode_request_unsupported_transfer_encoding() -> Result<()> {
         let mut read = b"POST / HTTP/1.1\r\nhost: example.com\r\ncontent-length: 12\r\ntransfer-encoding: foo\r\n\r\nfoobar".as_slice();
-        assert!(decode_request_body(decode_request_headers(&mut read, false)?, read).is_err());
+        assert!(decode_request_body(
+            decode_request_headers(&mut read, false, MAX_HEADER_SIZE)?,
+            read,
+            MAX_HEADER_SIZE
+        )
+        .is_err());
         Ok(())
     }
 
     #[test]
     fn decode_response_without_payload() -> Result<()> {
-        let response = decode_response(b"HTTP/1.1 404 Not Found\r\n\r\n".as_slice())?;
+        let response = decode_response(
+            b"HTTP/1.1 404 Not Found\r\n\r\n".as_slice(),
+            MAX_HEADER_SIZE,
+        )?;
         assert_eq!(response.status(), Status::NOT_FOUND);
         assert_eq!(response.body().len(), Some(0));
         Ok(())
@@ -553,6 +1117,7 @@ mod tests {
         let response = decode_response(
             b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length:12\r\n\r\ntestbodybody"
                 .as_slice(),
+            MAX_HEADER_SIZE,
         )?;
         assert_eq!(response.status(), Status::OK);
         assert_eq!(
@@ -571,7 +1136,7 @@ mod tests {
     fn decode_response_with_chunked_payload() -> Result<()> {
         let response = decode_response(
             b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ntransfer-encoding:chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\n".as_slice()
-        )?;
+        , MAX_HEADER_SIZE)?;
         assert_eq!(response.status(), Status::OK);
         assert_eq!(
             response
@@ -588,11 +1153,72 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn decode_response_with_chunked_payload_chunks() -> Result<()> {
+        let response = decode_response(
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding:chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\ntest: foo\r\n\r\n".as_slice()
+        , MAX_HEADER_SIZE)?;
+        let mut body = response.into_body();
+        let chunks = body.chunks().collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            chunks,
+            [b"Wiki".as_slice(), b"pedia", b" in\r\n\r\nchunks."]
+        );
+        assert!(body.trailers().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_request_with_chunked_payload_frames() -> Result<()> {
+        // The large chunk must not be split
+        let large = "a".repeat(100_000);
+        let mut input =
+            b"POST / HTTP/1.1\r\nhost: example.com\r\ntransfer-encoding: chunked\r\n\r\n3\r\nfoo\r\n"
+                .to_vec();
+        input.extend_from_slice(format!("{:X}\r\n{large}\r\n", large.len()).as_bytes());
+        input.extend_from_slice(b"1\r\nb\r\n0\r\n\r\n");
+        let input = Box::leak(input.into_boxed_slice());
+        let mut reader = &input[..];
+        let request = decode_request_headers(&mut reader, false, MAX_HEADER_SIZE)?;
+        let frames = decode_request_body(request, reader, MAX_HEADER_SIZE)?
+            .into_body()
+            .frames()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(frames, [b"foo".as_slice(), large.as_bytes(), b"b"]);
+
+        // The size limit keeps the frames
+        let mut reader = &input[..];
+        let request = decode_request_headers(&mut reader, false, MAX_HEADER_SIZE)?;
+        let mut frames = decode_request_body(request, reader, MAX_HEADER_SIZE)?
+            .into_body()
+            .with_size_limit(10)
+            .frames();
+        assert_eq!(frames.next().transpose()?, Some(b"foo".to_vec()));
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_response_with_truncated_chunked_payload_frames() -> Result<()> {
+        // The announced chunk size must not be allocated upfront
+        let response = decode_response(
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding:chunked\r\n\r\nFFFFFFFFFF\r\nfoo".as_slice(),
+            MAX_HEADER_SIZE,
+        )?;
+        let mut frames = response.into_body().frames();
+        assert_eq!(
+            frames.next().unwrap().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        Ok(())
+    }
+
     #[test]
     fn decode_response_with_trailer() -> Result<()> {
         let response = decode_response(
             b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ntransfer-encoding:chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\ntest: foo\r\n\r\n".as_slice()
-        )?;
+        , MAX_HEADER_SIZE)?;
         assert_eq!(response.status(), Status::OK);
         assert_eq!(
             response
@@ -620,7 +1246,7 @@ mod tests {
     #[test]
     #[cfg(feature = "flate2")]
     fn decode_gzip_response() -> Result<()> {
-        let response = decode_response(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-encoding: gzip\r\ncontent-length: 23\r\n\r\n\x1f\x8b\x08\x00\xac\x94\xdfd\x02\xffK\xcb\xcf\x07\x00!es\x8c\x03\x00\x00\x00".as_slice())?;
+        let response = decode_response(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-encoding: gzip\r\ncontent-length: 23\r\n\r\n\x1f\x8b\x08\x00\xac\x94\xdfd\x02\xffK\xcb\xcf\x07\x00!es\x8c\x03\x00\x00\x00".as_slice(), MAX_HEADER_SIZE)?;
         assert_eq!(response.into_body().to_string()?, "foo");
         Ok(())
     }
@@ -628,14 +1254,42 @@ mod tests {
     #[test]
     #[cfg(feature = "flate2")]
     fn decode_deflate_response() -> Result<()> {
-        let response = decode_response(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-encoding: deflate\r\ncontent-length: 5\r\n\r\nK\xcb\xcf\x07\x00".as_slice())?;
+        let response = decode_response(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-encoding: deflate\r\ncontent-length: 5\r\n\r\nK\xcb\xcf\x07\x00".as_slice(), MAX_HEADER_SIZE)?;
+        assert_eq!(response.into_body().to_string()?, "foo");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn decode_gzip_chunked_response() -> Result<()> {
+        let response = decode_response(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ntransfer-encoding: GZIP , chunked\r\n\r\n17\r\n\x1f\x8b\x08\x00\xac\x94\xdfd\x02\xffK\xcb\xcf\x07\x00!es\x8c\x03\x00\x00\x00\r\n0\r\n\r\n".as_slice(), MAX_HEADER_SIZE)?;
         assert_eq!(response.into_body().to_string()?, "foo");
         Ok(())
     }
 
+    #[test]
+    fn decode_request_invalid_transfer_codings() -> Result<()> {
+        for transfer_encoding in [
+            "gzip",
+            "chunked, gzip",
+            "foo, chunked",
+            "chunked, chunked",
+            "",
+        ] {
+            let request = format!("POST / HTTP/1.1\r\nhost: example.com\r\ntransfer-encoding: {transfer_encoding}\r\n\r\n3\r\nfoo\r\n0\r\n\r\n");
+            let mut read = Cursor::new(request);
+            let head = decode_request_headers(&mut read, false, MAX_HEADER_SIZE)?;
+            assert!(
+                decode_request_body(head, read, MAX_HEADER_SIZE).is_err(),
+                "{transfer_encoding}"
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn decode_unknown_response() -> Result<()> {
-        let response = decode_response(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-encoding: foo\r\ncontent-length: 5\r\n\r\nfoooo".as_slice())?;
+        let response = decode_response(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-encoding: foo\r\ncontent-length: 5\r\n\r\nfoooo".as_slice(), MAX_HEADER_SIZE)?;
         assert_eq!(
             response.headers().get(&HeaderName::CONTENT_ENCODING),
             Some(&HeaderValue::new_unchecked("foo".as_bytes()))
@@ -649,6 +1303,7 @@ mod tests {
         let response = decode_response(
             b"HTTP/1.1 200 OK\r\ntransfer-encoding:chunked\r\n\r\nh\r\nWiki\r\n0\r\n\r\n"
                 .as_slice(),
+            MAX_HEADER_SIZE,
         )?;
         assert!(response.into_body().to_string().is_err());
         Ok(())
@@ -658,15 +1313,36 @@ mod tests {
     fn decode_response_with_invalid_trailer() -> Result<()> {
         let response = decode_response(
             b"HTTP/1.1 200 OK\r\ntransfer-encoding:chunked\r\n\r\nf\r\nWiki\r\n0\r\ntest\n: foo\r\n\r\n"
-        .as_slice())?;
+        .as_slice(), MAX_HEADER_SIZE)?;
         assert!(response.into_body().to_string().is_err());
         Ok(())
     }
 
+    #[test]
+    fn decode_response_with_forbidden_trailer() -> Result<()> {
+        for trailer in [
+            "Transfer-Encoding: chunked",
+            "content-length: 4",
+            "Host: example.com",
+        ] {
+            let response = decode_response(
+                Cursor::new(format!("HTTP/1.1 200 OK\r\ntransfer-encoding:chunked\r\n\r\n4\r\nWiki\r\n0\r\n{trailer}\r\n\r\n")),
+                MAX_HEADER_SIZE,
+            )?;
+            assert_eq!(
+                response.into_body().to_string().unwrap_err().kind(),
+                ErrorKind::InvalidData,
+                "{trailer}"
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn decode_response_with_not_ended_trailer() -> Result<()> {
         let response = decode_response(
             b"HTTP/1.1 200 OK\r\ntransfer-encoding:chunked\r\n\r\nf\r\nWiki".as_slice(),
+            MAX_HEADER_SIZE,
         )?;
         assert!(response.into_body().to_string().is_err());
         Ok(())
@@ -674,16 +1350,18 @@ mod tests {
 
     #[test]
     fn decode_response_empty_header_name() {
-        assert!(
-            decode_response(b"HTTP/1.1 200 OK\nHost: www.example.org:8001\n: foo".as_slice())
-                .is_err()
-        );
+        assert!(decode_response(
+            b"HTTP/1.1 200 OK\nHost: www.example.org:8001\n: foo".as_slice(),
+            MAX_HEADER_SIZE
+        )
+        .is_err());
     }
 
     #[test]
     fn decode_response_invalid_header_name_char() {
         assert!(decode_response(
-            b"HTTP/1.1 200 OK\nHost: www.example.org:8001\nCont\xE9: foo".as_slice()
+            b"HTTP/1.1 200 OK\nHost: www.example.org:8001\nCont\xE9: foo".as_slice(),
+            MAX_HEADER_SIZE
         )
         .is_err());
     }
@@ -692,25 +1370,29 @@ mod tests {
     fn decode_response_invalid_header_value_char() {
         assert!(decode_response(
             b"HTTP/1.1 200 OK\nHost: www.example.org:8001\nCont\t: foo\rbar\r\nTest: test"
-                .as_slice()
+                .as_slice(),
+            MAX_HEADER_SIZE
         )
         .is_err());
     }
 
     #[test]
     fn decode_response_empty() {
-        assert!(decode_response(b"".as_slice()).is_err());
+        assert!(decode_response(b"".as_slice(), MAX_HEADER_SIZE).is_err());
     }
 
     #[test]
     fn decode_response_stop_in_header() {
-        assert!(decode_response(b"HTTP/1.1 404 Not Found\r\n".as_slice()).is_err());
+        assert!(
+            decode_response(b"HTTP/1.1 404 Not Found\r\n".as_slice(), MAX_HEADER_SIZE).is_err()
+        );
     }
 
     #[test]
     fn decode_response_stop_in_body() -> Result<()> {
         assert!(decode_response(
-            b"HTTP/1.1 200 OK\r\ncontent-length: 12\r\n\r\nfoobar".as_slice()
+            b"HTTP/1.1 200 OK\r\ncontent-length: 12\r\n\r\nfoobar".as_slice(),
+            MAX_HEADER_SIZE
         )?
         .into_body()
         .to_vec()
@@ -720,14 +1402,14 @@ mod tests {
 
     #[test]
     fn decode_response_content_length_and_transfer_encoding() {
-        assert!(decode_response( b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ntransfer-encoding:chunked\r\ncontent-length: 222\r\n\r\n".as_slice()).is_err());
+        assert!(decode_response( b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ntransfer-encoding:chunked\r\ncontent-length: 222\r\n\r\n".as_slice(), MAX_HEADER_SIZE).is_err());
     }
 
     #[test]
     fn decode_response_with_chunked_payload_read_after_end() -> Result<()> {
         let response = decode_response(
             b"HTTP/1.1 200 OK\r\ntransfer-encoding:chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\n".as_slice()
-        )?;
+        , MAX_HEADER_SIZE)?;
         assert_eq!(response.status(), Status::OK);
         let mut body = response.into_body();
         body.read_to_end(&mut Vec::new())?;
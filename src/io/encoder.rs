@@ -1,8 +1,111 @@
-use crate::model::{Body, HeaderName, Headers, Method, Request, Response, Status};
+use crate::model::{Body, HeaderName, HeaderValue, Headers, Method, Request, Response, Status};
 use crate::utils::invalid_input_error;
-use std::io::{copy, Read, Result, Write};
+use std::fs::File;
+use std::io::{copy, Error, ErrorKind, IoSlice, Read, Result, Write};
+use url::Position;
 
-pub fn encode_request<W: Write>(request: &mut Request, mut writer: W) -> Result<W> {
+/// Function copying up to the given number of bytes from the current position of the file to the connection.
+///
+/// It returns the number of copied bytes, `0` if it can't copy anything.
+pub(crate) type SendFile<'a> = dyn FnMut(&File, u64) -> Result<u64> + 'a;
+
+/// Default size of the buffer used to build the chunks of the chunked transfer encoding.
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+/// Options of [`encode_request_with_options`].
+#[derive(Clone)]
+pub(crate) struct RequestEncodingOptions {
+    /// Writes `content-length: 0` for empty bodies whatever the method is.
+    pub always_send_content_length: bool,
+    /// Writes the full URL in the request line (absolute form) as expected by proxies.
+    pub absolute_form: bool,
+    /// Maximal size of the chunks if the body is sent using chunked transfer encoding.
+    pub chunk_size: usize,
+    /// Value of the `Proxy-Authorization` header sent to the proxy.
+    pub proxy_authorization: Option<HeaderValue>,
+}
+
+impl Default for RequestEncodingOptions {
+    fn default() -> Self {
+        Self {
+            always_send_content_length: false,
+            absolute_form: false,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            proxy_authorization: None,
+        }
+    }
+}
+
+/// Encodes the request.
+pub fn encode_request<W: Write>(request: &mut Request, writer: W) -> Result<W> {
+    encode_request_with_options(request, RequestEncodingOptions::default(), writer)
+}
+
+/// Encodes the request using the given options.
+pub(crate) fn encode_request_with_options<W: Write>(
+    request: &mut Request,
+    options: RequestEncodingOptions,
+    mut writer: W,
+) -> Result<W> {
+    let framing = encode_request_head(request, &options, &mut writer)?;
+    encode_body(
+        request.body_mut(),
+        &mut writer,
+        framing,
+        true,
+        options.chunk_size,
+        None,
+    )?;
+    Ok(writer)
+}
+
+/// Renders the request as [`encode_request`] would write it, without consuming its body.
+///
+/// Bodies that are not stored in memory are replaced by a `<streamed body>` placeholder.
+pub fn dump_request(request: &Request) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let framing = encode_request_head(request, &RequestEncodingOptions::default(), &mut buffer)?;
+    dump_body(request.body(), framing, &mut buffer);
+    Ok(buffer)
+}
+
+/// Encodes the request line and the headers of a request whose body is then written with a [`ChunkedWriter`].
+///
+/// The request body is ignored.
+#[cfg(feature = "client")]
+pub(crate) fn encode_chunked_request_head<W: Write>(
+    request: &Request,
+    options: RequestEncodingOptions,
+    mut writer: W,
+) -> Result<ChunkedWriter<W>> {
+    encode_request_line_and_headers(request, &options, &mut writer)?;
+    BodyFraming::Chunked.encode_header(&mut writer)?;
+    write!(writer, "\r\n")?;
+    Ok(ChunkedWriter { writer })
+}
+
+/// Writes the request line and the headers including the framing one
+fn encode_request_head(
+    request: &Request,
+    options: &RequestEncodingOptions,
+    writer: &mut impl Write,
+) -> Result<BodyFraming> {
+    encode_request_line_and_headers(request, options, writer)?;
+
+    // body with content-length if existing
+    let framing = BodyFraming::new(
+        request.body(),
+        options.always_send_content_length || does_request_must_include_body(request.method()),
+    );
+    framing.encode_header(writer)?;
+    Ok(framing)
+}
+
+fn encode_request_line_and_headers(
+    request: &Request,
+    options: &RequestEncodingOptions,
+    writer: &mut impl Write,
+) -> Result<()> {
     if !request.url().username().is_empty() || request.url().password().is_some() {
         return Err(invalid_input_error(
             "Username and password are not allowed in HTTP URLs",
@@ -13,9 +116,18 @@ pub fn encode_request<W: Write>(request: &mut Request, mut writer: W) -> Result<
         .host_str()
         .ok_or_else(|| invalid_input_error("No host provided"))?;
 
-    if let Some(query) = request.url().query() {
+    if options.absolute_form {
         write!(
-            &mut writer,
+            writer,
+            "{} {} HTTP/1.1\r\n",
+            request.method(),
+            &request.url()[..Position::AfterQuery]
+        )?;
+    } else if request.is_asterisk_form() {
+        write!(writer, "{} * HTTP/1.1\r\n", request.method())?;
+    } else if let Some(query) = request.url().query() {
+        write!(
+            writer,
             "{} {}?{} HTTP/1.1\r\n",
             request.method(),
             request.url().path(),
@@ -23,7 +135,7 @@ pub fn encode_request<W: Write>(request: &mut Request, mut writer: W) -> Result<
         )?;
     } else {
         write!(
-            &mut writer,
+            writer,
             "{} {} HTTP/1.1\r\n",
             request.method(),
             request.url().path(),
@@ -38,71 +150,342 @@ pub fn encode_request<W: Write>(request: &mut Request, mut writer: W) -> Result<
     }
 
     // headers
-    encode_headers(request.headers(), &mut writer)?;
+    encode_headers(request.headers(), writer)?;
+    if let Some(proxy_authorization) = &options.proxy_authorization {
+        write_header_block(
+            [(
+                &HeaderName::new_unchecked("proxy-authorization"),
+                proxy_authorization,
+            )],
+            writer,
+        )?;
+    }
 
-    // body with content-length if existing
-    let must_include_body = does_request_must_include_body(request.method());
-    encode_body(request.body_mut(), &mut writer, must_include_body)?;
+    // The other connection options are hop-by-hop and are not forwarded
+    if request
+        .header(&HeaderName::CONNECTION)
+        .is_some_and(|connection| connection.contains_token(b"close"))
+    {
+        write!(writer, "connection: close\r\n")?;
+    }
+    Ok(())
+}
 
+/// Where the framing header (`Content-Length` or `Transfer-Encoding`) is written in a response.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub(crate) enum FramingHeaderPosition {
+    /// Just after the status line, before the other headers.
+    First,
+    /// After all the other headers.
+    #[default]
+    Last,
+}
+
+/// Encodes the response.
+///
+/// Chunked bodies are written with their trailers.
+pub fn encode_response<W: Write>(response: &mut Response, writer: W) -> Result<W> {
+    encode_response_with_options(
+        response,
+        &Headers::new(),
+        FramingHeaderPosition::default(),
+        true,
+        DEFAULT_CHUNK_SIZE,
+        None,
+        writer,
+    )
+}
+
+/// Encodes the response using the given options.
+///
+/// The status line is followed by the response headers and then by the `connection_headers`.
+/// The framing header (`Content-Length` or `Transfer-Encoding`) is written at `framing_header_position`.
+///
+/// `connection_headers` are written verbatim.
+/// It allows the server to emit connection management headers like `Connection` or `Keep-Alive`
+/// that are otherwise filtered out of the response headers.
+///
+/// The trailers of a chunked body are only written if `send_trailers` is set,
+/// i.e. if the client announced it supports them with `TE: trailers`.
+///
+/// If `send_file` is set, it is used to write the bodies built with [`Body::from_file`]
+/// after having flushed `writer`.
+pub(crate) fn encode_response_with_options<W: Write>(
+    response: &mut Response,
+    connection_headers: &Headers,
+    framing_header_position: FramingHeaderPosition,
+    send_trailers: bool,
+    chunk_size: usize,
+    send_file: Option<&mut SendFile<'_>>,
+    mut writer: W,
+) -> Result<W> {
+    let framing = encode_response_head(
+        response,
+        connection_headers,
+        framing_header_position,
+        &mut writer,
+    )?;
+    encode_body(
+        response.body_mut(),
+        &mut writer,
+        framing,
+        send_trailers,
+        chunk_size,
+        send_file,
+    )?;
     Ok(writer)
 }
 
-pub fn encode_response<W: Write>(response: &mut Response, mut writer: W) -> Result<W> {
-    write!(&mut writer, "HTTP/1.1 {}\r\n", response.status())?;
+/// Writes an [informational response](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.1xx)
+/// sent before the final one like `103 Early Hints`.
+///
+/// Only the status line and the headers are written.
+/// `100 Continue` is written by the server itself and `101 Switching Protocols` is not supported.
+pub fn encode_interim_response<W: Write>(response: &Response, mut writer: W) -> Result<W> {
+    let status = response.status();
+    if !status.is_informational()
+        || status == Status::CONTINUE
+        || status == Status::SWITCHING_PROTOCOLS
+    {
+        return Err(invalid_input_error(format!(
+            "{status} is not a valid interim response status"
+        )));
+    }
+    write!(writer, "HTTP/1.1 {status}\r\n")?;
     encode_headers(response.headers(), &mut writer)?;
-    let must_include_body = does_response_must_include_body(response.status());
-    encode_body(response.body_mut(), &mut writer, must_include_body)?;
+    write!(writer, "\r\n")?;
     Ok(writer)
 }
 
+/// Renders the response as [`encode_response`] would write it, without consuming its body.
+///
+/// Bodies that are not stored in memory are replaced by a `<streamed body>` placeholder.
+pub fn dump_response(response: &Response) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let framing = encode_response_head(
+        response,
+        &Headers::new(),
+        FramingHeaderPosition::default(),
+        &mut buffer,
+    )?;
+    dump_body(response.body(), framing, &mut buffer);
+    Ok(buffer)
+}
+
+/// Writes the status line and the headers including the framing one
+fn encode_response_head(
+    response: &Response,
+    connection_headers: &Headers,
+    framing_header_position: FramingHeaderPosition,
+    writer: &mut impl Write,
+) -> Result<BodyFraming> {
+    write!(writer, "HTTP/1.1 {}\r\n", response.status())?;
+    let framing = BodyFraming::new(
+        response.body(),
+        does_response_must_include_body(response.status()),
+    );
+    if framing_header_position == FramingHeaderPosition::First {
+        framing.encode_header(writer)?;
+    }
+    encode_headers(response.headers(), writer)?;
+    write_header_block(connection_headers, writer)?;
+    if framing_header_position == FramingHeaderPosition::Last {
+        framing.encode_header(writer)?;
+    }
+    Ok(framing)
+}
+
 fn encode_headers(headers: &Headers, writer: &mut impl Write) -> Result<()> {
+    write_header_block(
+        headers.iter().filter(|(name, _)| !is_forbidden_name(name)),
+        writer,
+    )
+}
+
+/// Writes the headers using vectored writes to avoid formatting them one by one
+fn write_header_block<'a>(
+    headers: impl IntoIterator<Item = (&'a HeaderName, &'a HeaderValue)>,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let mut parts = Vec::new();
     for (name, value) in headers {
-        if !is_forbidden_name(name) {
-            write!(writer, "{name}: ")?;
-            writer.write_all(value)?;
-            write!(writer, "\r\n")?;
+        parts.extend([name.as_bytes(), b": ", value.as_ref(), b"\r\n"]);
+    }
+    write_all_vectored(writer, &parts)
+}
+
+/// Same as the unstable [`Write::write_all_vectored`]
+///
+/// Writers not supporting vectored writes get the slices one by one.
+/// The slices are built once and advanced in place after each write.
+fn write_all_vectored<'a>(writer: &mut impl Write, parts: &[&'a [u8]]) -> Result<()> {
+    let parts = parts
+        .iter()
+        .copied()
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>();
+    let mut slices = parts.iter().map(|p| IoSlice::new(p)).collect::<Vec<_>>();
+    // The first part not fully written and the number of its bytes already written
+    let mut start = 0;
+    let mut offset = 0;
+    while start < parts.len() {
+        let mut written = match writer.write_vectored(&slices[start..]) {
+            Ok(0) => {
+                return Err(Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ))
+            }
+            Ok(written) => written,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        // We skip the written parts
+        while written > 0 && start < parts.len() {
+            let part: &'a [u8] = parts[start];
+            if written >= part.len() - offset {
+                written -= part.len() - offset;
+                start += 1;
+                offset = 0;
+            } else {
+                offset += written;
+                slices[start] = IoSlice::new(&part[offset..]);
+                break;
+            }
         }
     }
     Ok(())
 }
 
-fn encode_body(body: &mut Body, writer: &mut impl Write, must_include_body: bool) -> Result<()> {
-    if let Some(length) = body.len() {
-        if must_include_body || length > 0 {
-            write!(writer, "content-length: {length}\r\n\r\n")?;
+/// How the end of the body is signaled
+#[derive(Clone, Copy)]
+enum BodyFraming {
+    NoBody,
+    ContentLength(u64),
+    Chunked,
+}
+
+impl BodyFraming {
+    fn new(body: &Body, must_include_body: bool) -> Self {
+        match body.len() {
+            Some(length) if must_include_body || length > 0 => Self::ContentLength(length),
+            Some(_) => Self::NoBody,
+            None => Self::Chunked,
+        }
+    }
+
+    fn encode_header(self, writer: &mut impl Write) -> Result<()> {
+        match self {
+            Self::NoBody => Ok(()),
+            Self::ContentLength(length) => write!(writer, "content-length: {length}\r\n"),
+            Self::Chunked => write!(writer, "transfer-encoding: chunked\r\n"),
+        }
+    }
+}
+
+/// Ends the header block and writes the body
+fn encode_body(
+    body: &mut Body,
+    writer: &mut impl Write,
+    framing: BodyFraming,
+    send_trailers: bool,
+    chunk_size: usize,
+    send_file: Option<&mut SendFile<'_>>,
+) -> Result<()> {
+    write!(writer, "\r\n")?;
+    match framing {
+        BodyFraming::NoBody => (),
+        BodyFraming::ContentLength(_) => {
+            if let Some(send_file) = send_file.filter(|_| body.is_file()) {
+                writer.flush()?; // The head must be written before the file content
+                body.send_file(send_file)?;
+            }
+            // The rest of the body if the file can't be fully sent with `send_file`
             copy(body, writer)?;
-        } else {
-            write!(writer, "\r\n")?;
         }
-    } else {
-        write!(writer, "transfer-encoding: chunked\r\n\r\n")?;
-        let mut buffer = vec![b'\0'; 4096];
-        loop {
-            let mut read = 0;
-            while read < 1024 {
-                // We try to avoid too small chunks
-                let new_read = body.read(&mut buffer[read..])?;
-                if new_read == 0 {
-                    break; // EOF
+        BodyFraming::Chunked => {
+            let mut buffer = vec![b'\0'; chunk_size];
+            // We try to avoid too small chunks
+            let min_chunk_size = (chunk_size / 4).max(1);
+            loop {
+                let mut read = 0;
+                while read < min_chunk_size {
+                    let new_read = body.read(&mut buffer[read..])?;
+                    if new_read == 0 {
+                        break; // EOF
+                    }
+                    read += new_read;
+                }
+                write!(writer, "{read:X}\r\n")?;
+                writer.write_all(&buffer[..read])?;
+                if read == 0 {
+                    break; // Done
+                } else {
+                    write!(writer, "\r\n")?;
                 }
-                read += new_read;
             }
-            write!(writer, "{read:X}\r\n")?;
-            writer.write_all(&buffer[..read])?;
-            if read == 0 {
-                break; // Done
-            } else {
-                write!(writer, "\r\n")?;
+            if send_trailers {
+                if let Some(trailers) = body.trailers() {
+                    encode_headers(trailers, writer)?;
+                }
             }
+            write!(writer, "\r\n")?;
         }
-        if let Some(trailers) = body.trailers() {
-            encode_headers(trailers, writer)?;
-        }
-        write!(writer, "\r\n")?;
     }
     Ok(())
 }
 
+/// Writes a body using chunked transfer encoding, each non-empty write being a chunk.
+///
+/// [`finish`](Self::finish) must be called to write the last chunk.
+#[cfg(feature = "client")]
+pub(crate) struct ChunkedWriter<W: Write> {
+    writer: W,
+}
+
+#[cfg(feature = "client")]
+impl<W: Write> ChunkedWriter<W> {
+    /// Writes the last chunk and the trailers and returns the inner writer.
+    pub fn finish(mut self, trailers: Option<&Headers>) -> Result<W> {
+        write!(self.writer, "0\r\n")?;
+        if let Some(trailers) = trailers {
+            encode_headers(trailers, &mut self.writer)?;
+        }
+        write!(self.writer, "\r\n")?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(feature = "client")]
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0); // An empty chunk would end the body
+        }
+        write!(self.writer, "{:X}\r\n", buf.len())?;
+        self.writer.write_all(buf)?;
+        write!(self.writer, "\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Ends the header block and writes the in-memory body content or a placeholder
+fn dump_body(body: &Body, framing: BodyFraming, buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(b"\r\n");
+    if matches!(framing, BodyFraming::NoBody) {
+        return;
+    }
+    if let Some(content) = body.in_memory_content() {
+        buffer.extend_from_slice(content);
+    } else {
+        buffer.extend_from_slice(b"<streamed body>");
+    }
+}
+
 /// Checks if it is a [forbidden header name](https://fetch.spec.whatwg.org/#forbidden-header-name)
 ///
 /// We removed some of them not managed by this library (`Access-Control-Request-Headers`, `Access-Control-Request-Method`, `DNT`, `Cookie`, `Cookie2`, `Referer`, `Proxy-`, `Sec-`, `Via`...)
@@ -174,6 +557,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encode_request_connection_close() -> Result<()> {
+        for (connection, expected) in [
+            ("keep-alive, Close", "connection: close\r\n"),
+            ("keep-alive, upgrade", ""),
+        ] {
+            let mut request = Request::builder(Method::GET, "http://example.com".parse().unwrap())
+                .with_header(HeaderName::CONNECTION, connection)
+                .unwrap()
+                .build();
+            let buffer = encode_request(&mut request, Vec::new())?;
+            assert_eq!(
+                str::from_utf8(&buffer).unwrap(),
+                format!("GET / HTTP/1.1\r\nhost: example.com\r\n{expected}\r\n")
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn encode_asterisk_form_request() -> Result<()> {
+        let mut request = Request::builder(Method::OPTIONS, "http://example.com".parse().unwrap())
+            .with_asterisk_form()
+            .build();
+        let buffer = encode_request(&mut request, Vec::new())?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "OPTIONS * HTTP/1.1\r\nhost: example.com\r\n\r\n"
+        );
+        Ok(())
+    }
+
     #[test]
     fn encode_post_request() -> Result<()> {
         let mut request = Request::builder(
@@ -206,6 +621,85 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encode_get_request_with_forced_content_length() -> Result<()> {
+        let mut request =
+            Request::builder(Method::GET, "http://example.com/foo".parse().unwrap()).build();
+        let buffer = encode_request_with_options(
+            &mut request,
+            RequestEncodingOptions {
+                always_send_content_length: true,
+                ..RequestEncodingOptions::default()
+            },
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "GET /foo HTTP/1.1\r\nhost: example.com\r\ncontent-length: 0\r\n\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dump_response_matches_encoding() -> Result<()> {
+        let mut response = Response::builder(Status::OK)
+            .with_header(HeaderName::CONTENT_TYPE, "text/plain")
+            .unwrap()
+            .with_body("foo");
+        let dump = dump_response(&response)?;
+        let buffer = encode_response_with_options(
+            &mut response,
+            &Headers::new(),
+            FramingHeaderPosition::Last,
+            false,
+            DEFAULT_CHUNK_SIZE,
+            None,
+            Vec::new(),
+        )?;
+        assert_eq!(dump, buffer);
+        Ok(())
+    }
+
+    #[test]
+    fn encode_request_in_absolute_form() -> Result<()> {
+        let mut request = Request::builder(
+            Method::GET,
+            "http://example.com:81/foo?bar#baz".parse().unwrap(),
+        )
+        .build();
+        let buffer = encode_request_with_options(
+            &mut request,
+            RequestEncodingOptions {
+                absolute_form: true,
+                ..RequestEncodingOptions::default()
+            },
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "GET http://example.com:81/foo?bar HTTP/1.1\r\nhost: example.com:81\r\n\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encode_request_to_ipv6_literal() -> Result<()> {
+        let mut request =
+            Request::builder(Method::GET, "http://[fe80::1]:8080/foo".parse().unwrap()).build();
+        let buffer = encode_request(&mut request, Vec::new())?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "GET /foo HTTP/1.1\r\nhost: [fe80::1]:8080\r\n\r\n"
+        );
+        let mut request = Request::builder(Method::GET, "http://[::1]/".parse().unwrap()).build();
+        let buffer = encode_request(&mut request, Vec::new())?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "GET / HTTP/1.1\r\nhost: [::1]\r\n\r\n"
+        );
+        Ok(())
+    }
+
     #[test]
     fn encode_post_request_with_chunked() -> Result<()> {
         let mut trailers = Headers::new();
@@ -227,6 +721,56 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encode_request_with_chunk_size() -> Result<()> {
+        let mut request = Request::builder(Method::POST, "http://example.com".parse().unwrap())
+            .with_body(Body::from_read(b"testbodybody".as_slice()));
+        let buffer = encode_request_with_options(
+            &mut request,
+            RequestEncodingOptions {
+                chunk_size: 5,
+                ..RequestEncodingOptions::default()
+            },
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "POST / HTTP/1.1\r\nhost: example.com\r\ntransfer-encoding: chunked\r\n\r\n5\r\ntestb\r\n5\r\nodybo\r\n2\r\ndy\r\n0\r\n\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encode_response_trailers_only_if_requested() -> Result<()> {
+        for (send_trailers, expected_end) in [
+            (true, "0\r\ncontent-language: foo\r\n\r\n"),
+            (false, "0\r\n\r\n"),
+        ] {
+            let mut trailers = Headers::new();
+            trailers.append(HeaderName::CONTENT_LANGUAGE, "foo".parse().unwrap());
+            let mut response = Response::builder(Status::OK).with_body(
+                Body::from_chunked_transfer_payload(SimpleTrailers {
+                    read: b"test".as_slice(),
+                    trailers,
+                }),
+            );
+            let buffer = encode_response_with_options(
+                &mut response,
+                &Headers::new(),
+                FramingHeaderPosition::Last,
+                send_trailers,
+                DEFAULT_CHUNK_SIZE,
+                None,
+                Vec::new(),
+            )?;
+            assert_eq!(
+                str::from_utf8(&buffer).unwrap(),
+                format!("HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n4\r\ntest\r\n{expected_end}")
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn encode_response_ok() -> Result<()> {
         let mut response = Response::builder(Status::OK)
@@ -263,6 +807,203 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encode_interim_response_early_hints() -> Result<()> {
+        let response = Response::builder(Status::EARLY_HINTS)
+            .with_header("link", "</style.css>; rel=preload")
+            .unwrap()
+            .build();
+        let buffer = encode_interim_response(&response, Vec::new())?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload\r\n\r\n"
+        );
+        for status in [Status::CONTINUE, Status::SWITCHING_PROTOCOLS, Status::OK] {
+            encode_interim_response(&Response::builder(status).build(), Vec::new()).unwrap_err();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn encode_response_with_connection_headers() -> Result<()> {
+        let mut response = Response::builder(Status::OK)
+            .with_header("keep-alive", "timeout=1")
+            .unwrap()
+            .with_body("test");
+        let mut connection_headers = Headers::new();
+        connection_headers.set(
+            HeaderName::try_from("keep-alive").unwrap(),
+            "timeout=5, max=10".parse().unwrap(),
+        );
+        let buffer = encode_response_with_options(
+            &mut response,
+            &connection_headers,
+            FramingHeaderPosition::Last,
+            true,
+            DEFAULT_CHUNK_SIZE,
+            None,
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "HTTP/1.1 200 OK\r\nkeep-alive: timeout=5, max=10\r\ncontent-length: 4\r\n\r\ntest"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encode_response_framing_header_last() -> Result<()> {
+        let mut connection_headers = Headers::new();
+        connection_headers.set(HeaderName::CONNECTION, "close".parse().unwrap());
+        let mut response = Response::builder(Status::OK)
+            .with_header(HeaderName::CONTENT_TYPE, "text/plain")
+            .unwrap()
+            .with_body("test");
+        let buffer = encode_response_with_options(
+            &mut response,
+            &connection_headers,
+            FramingHeaderPosition::Last,
+            true,
+            DEFAULT_CHUNK_SIZE,
+            None,
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nconnection: close\r\ncontent-length: 4\r\n\r\ntest"
+        );
+        let mut response = Response::builder(Status::OK)
+            .with_header(HeaderName::CONTENT_TYPE, "text/plain")
+            .unwrap()
+            .with_body(Body::from_read(b"test".as_slice()));
+        let buffer = encode_response_with_options(
+            &mut response,
+            &connection_headers,
+            FramingHeaderPosition::Last,
+            true,
+            DEFAULT_CHUNK_SIZE,
+            None,
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nconnection: close\r\ntransfer-encoding: chunked\r\n\r\n4\r\ntest\r\n0\r\n\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encode_response_framing_header_first() -> Result<()> {
+        let mut connection_headers = Headers::new();
+        connection_headers.set(HeaderName::CONNECTION, "close".parse().unwrap());
+        let mut response = Response::builder(Status::OK)
+            .with_header(HeaderName::CONTENT_TYPE, "text/plain")
+            .unwrap()
+            .with_body("test");
+        let buffer = encode_response_with_options(
+            &mut response,
+            &connection_headers,
+            FramingHeaderPosition::First,
+            true,
+            DEFAULT_CHUNK_SIZE,
+            None,
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "HTTP/1.1 200 OK\r\ncontent-length: 4\r\ncontent-type: text/plain\r\nconnection: close\r\n\r\ntest"
+        );
+        let mut response = Response::builder(Status::OK)
+            .with_header(HeaderName::CONTENT_TYPE, "text/plain")
+            .unwrap()
+            .with_body(Body::from_read(b"test".as_slice()));
+        let buffer = encode_response_with_options(
+            &mut response,
+            &connection_headers,
+            FramingHeaderPosition::First,
+            true,
+            DEFAULT_CHUNK_SIZE,
+            None,
+            Vec::new(),
+        )?;
+        assert_eq!(
+            str::from_utf8(&buffer).unwrap(),
+            "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\ncontent-type: text/plain\r\nconnection: close\r\n\r\n4\r\ntest\r\n0\r\n\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encode_headers_with_partial_writes() -> Result<()> {
+        let mut headers = Headers::new();
+        for i in 0..100 {
+            headers.set(
+                HeaderName::try_from(format!("x-header-{i}")).unwrap(),
+                format!("value {i}").parse().unwrap(),
+            );
+        }
+        headers.set(
+            HeaderName::try_from("x-empty").unwrap(),
+            HeaderValue::default(),
+        );
+        let mut expected = String::new();
+        for (name, value) in &headers {
+            expected.push_str(&format!("{name}: {}\r\n", value.to_str().unwrap()));
+        }
+
+        let mut vectored = Vec::new();
+        encode_headers(&headers, &mut vectored)?;
+        assert_eq!(str::from_utf8(&vectored).unwrap(), expected);
+
+        let mut partial = PartialWriter(Vec::new());
+        encode_headers(&headers, &mut partial)?;
+        assert_eq!(str::from_utf8(&partial.0).unwrap(), expected);
+
+        let mut partial_vectored = PartialVectoredWriter(Vec::new());
+        encode_headers(&headers, &mut partial_vectored)?;
+        assert_eq!(str::from_utf8(&partial_vectored.0).unwrap(), expected);
+        Ok(())
+    }
+
+    /// Writer with vectored write support that writes at most 5 bytes at a time, possibly across slices
+    struct PartialVectoredWriter(Vec<u8>);
+
+    impl Write for PartialVectoredWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.write_vectored(&[IoSlice::new(buf)])
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+            let mut written = 0;
+            for buf in bufs {
+                let len = buf.len().min(5 - written);
+                self.0.extend_from_slice(&buf[..len]);
+                written += len;
+                if written == 5 {
+                    break;
+                }
+            }
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writer without vectored write support that writes at most 3 bytes at a time
+    struct PartialWriter(Vec<u8>);
+
+    impl Write for PartialWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.write(&buf[..buf.len().min(3)])
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
     struct SimpleTrailers {
         read: &'static [u8],
         trailers: Headers,
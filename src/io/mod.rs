@@ -1,10 +1,52 @@
+//! Encoding and decoding of the HTTP/1.1 messages, to send them over any transport.
+//!
+//! The client and the server of this crate are built on top of these functions.
+//! The message bodies are decoded lazily: they are read from the underlying reader while the [`Body`](crate::model::Body) is read.
+//!
+//! ```
+//! use oxhttp::io::{decode_request_body, decode_request_headers, encode_request, MAX_HEADER_SIZE};
+//! use oxhttp::model::{Method, Request};
+//! use std::io::Cursor;
+//!
+//! let mut request = Request::builder(Method::POST, "http://example.com/foo".parse()?)
+//!     .with_body("bar");
+//! let encoded = encode_request(&mut request, Vec::new())?;
+//! assert_eq!(encoded, b"POST /foo HTTP/1.1\r\nhost: example.com\r\ncontent-length: 3\r\n\r\nbar");
+//!
+//! let mut reader = Cursor::new(encoded);
+//! let head = decode_request_headers(&mut reader, false, MAX_HEADER_SIZE)?;
+//! let request = decode_request_body(head, reader, MAX_HEADER_SIZE)?;
+//! assert_eq!(request.url().as_str(), "http://example.com/foo");
+//! assert_eq!(request.into_body().to_string()?, "bar");
+//! # Result::<_,Box<dyn std::error::Error>>::Ok(())
+//! ```
+
 mod decoder;
 mod encoder;
 
-pub use decoder::{decode_request_body, decode_request_headers, decode_response};
-pub use encoder::{encode_request, encode_response};
+#[cfg(feature = "server")]
+pub(crate) use decoder::decode_request_head;
+#[cfg(any(feature = "client", feature = "server"))]
+pub(crate) use decoder::ReaderSlot;
+pub use decoder::{decode_request_body, decode_request_headers, decode_response, MAX_HEADER_SIZE};
+#[cfg(any(feature = "client", feature = "server"))]
+pub(crate) use encoder::DEFAULT_CHUNK_SIZE;
+pub use encoder::{
+    dump_request, dump_response, encode_interim_response, encode_request, encode_response,
+};
+#[cfg(feature = "client")]
+pub(crate) use encoder::{
+    encode_chunked_request_head, encode_request_with_options, ChunkedWriter, RequestEncodingOptions,
+};
+#[cfg(feature = "server")]
+pub(crate) use encoder::{encode_response_with_options, FramingHeaderPosition, SendFile};
 
 /// Capacity for buffers.
 ///
 /// Should be significantly greater than BufWriter capacity to avoid flush in the `copy` method.
+#[cfg(any(feature = "client", feature = "server"))]
 pub(super) const BUFFER_CAPACITY: usize = 16 * 1024;
+
+/// Minimal capacity allowed for buffers set by the users.
+#[cfg(any(feature = "client", feature = "server"))]
+pub(super) const MIN_BUFFER_CAPACITY: usize = 4 * 1024;
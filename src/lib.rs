@@ -11,15 +11,36 @@
     unused_qualifications
 )]
 
+#[cfg(any(feature = "client", feature = "server"))]
+mod builder;
+#[cfg(feature = "client")]
+mod cache;
 #[cfg(feature = "client")]
 mod client;
-mod io;
+pub mod io;
 pub mod model;
 #[cfg(feature = "server")]
+mod proxy_protocol;
+#[cfg(feature = "server")]
 mod server;
+#[cfg(all(feature = "client", feature = "rustls"))]
+mod trust;
 mod utils;
 
+#[cfg(any(feature = "client", feature = "server"))]
+pub use builder::BuildError;
+#[cfg(all(feature = "client", feature = "flate2"))]
+pub use client::ContentEncoding;
 #[cfg(feature = "client")]
-pub use client::Client;
+pub use client::{
+    AddressSelection, Client, ClientBuilder, RedirectAction, RequestBodyWriter, ResourceInfo,
+    ResponsePromise,
+};
+#[cfg(feature = "server")]
+pub use proxy_protocol::ProxyProtocolVersion;
 #[cfg(feature = "server")]
-pub use server::{ListeningServer, Server};
+pub use server::{
+    AccessLogEntry, CorsConfig, ListeningServer, Server, ServerBuilder, ShutdownSummary,
+};
+#[cfg(all(feature = "client", feature = "rustls"))]
+pub use trust::{CertTrustStore, TofuTrustStore};
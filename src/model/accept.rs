@@ -0,0 +1,134 @@
+//! Content negotiation based on the [`Accept`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.accept) header.
+
+use crate::model::HeaderValue;
+
+/// Returns the media type from `available` that best matches the given [`Accept`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.accept) header value.
+///
+/// Each available media type gets the quality value of the most specific media range matching it (`type/subtype`, then `type/*`, then `*/*`).
+/// The one with the highest quality is returned, ties being broken by the order of `available`.
+/// `None` is returned if no available media type is acceptable, the server should then answer with a `406 Not Acceptable` status.
+///
+/// ```
+/// use oxhttp::model::accept::negotiate;
+/// use oxhttp::model::HeaderValue;
+///
+/// let accept = HeaderValue::try_from("text/html, application/json;q=0.9")?;
+/// assert_eq!(
+///     negotiate(&accept, &["application/json", "text/html"]),
+///     Some("text/html")
+/// );
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn negotiate<'a>(accept_header: &HeaderValue, available: &[&'a str]) -> Option<&'a str> {
+    let ranges = parse_media_ranges(accept_header.to_str().ok()?);
+    let mut best: Option<(&'a str, f32)> = None;
+    for candidate in available {
+        let Some((candidate_type, candidate_subtype)) = split_media_type(candidate) else {
+            continue;
+        };
+        let quality = ranges
+            .iter()
+            .filter_map(|range| {
+                range
+                    .specificity(candidate_type, candidate_subtype)
+                    .map(|specificity| (specificity, range.quality))
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map_or(0., |(_, quality)| quality);
+        if quality > 0. && best.map_or(true, |(_, best_quality)| quality > best_quality) {
+            best = Some((candidate, quality));
+        }
+    }
+    best.map(|(media_type, _)| media_type)
+}
+
+struct MediaRange<'a> {
+    type_: &'a str,
+    subtype: &'a str,
+    quality: f32,
+}
+
+impl MediaRange<'_> {
+    /// Returns how specific the range is if it matches the given media type
+    fn specificity(&self, type_: &str, subtype: &str) -> Option<u8> {
+        if self.type_ == "*" {
+            Some(0)
+        } else if !self.type_.eq_ignore_ascii_case(type_) {
+            None
+        } else if self.subtype == "*" {
+            Some(1)
+        } else if self.subtype.eq_ignore_ascii_case(subtype) {
+            Some(2)
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_media_ranges(value: &str) -> Vec<MediaRange<'_>> {
+    value
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let (type_, subtype) = split_media_type(parts.next()?)?;
+            if type_ == "*" && subtype != "*" {
+                return None; // Invalid range
+            }
+            let mut quality = 1.;
+            for parameter in parts {
+                if let Some((name, value)) = parameter.split_once('=') {
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        quality = value
+                            .trim()
+                            .parse()
+                            .ok()
+                            .filter(|q| (0. ..=1.).contains(q))?;
+                    }
+                }
+            }
+            Some(MediaRange {
+                type_,
+                subtype,
+                quality,
+            })
+        })
+        .collect()
+}
+
+fn split_media_type(media_type: &str) -> Option<(&str, &str)> {
+    let media_type = media_type.split(';').next()?.trim();
+    let (type_, subtype) = media_type.split_once('/')?;
+    let (type_, subtype) = (type_.trim(), subtype.trim());
+    (!type_.is_empty() && !subtype.is_empty()).then_some((type_, subtype))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate_prefers_highest_quality() {
+        let accept = HeaderValue::try_from("application/json, text/*;q=0.5").unwrap();
+        assert_eq!(
+            negotiate(&accept, &["text/html", "application/json"]),
+            Some("application/json")
+        );
+        assert_eq!(negotiate(&accept, &["text/html"]), Some("text/html"));
+    }
+
+    #[test]
+    fn negotiate_uses_most_specific_range() {
+        let accept = HeaderValue::try_from("*/*;q=0.8, text/html;q=0").unwrap();
+        assert_eq!(
+            negotiate(&accept, &["text/html", "application/json"]),
+            Some("application/json")
+        );
+        assert_eq!(negotiate(&accept, &["text/html"]), None);
+    }
+
+    #[test]
+    fn negotiate_unsatisfiable() {
+        let accept = HeaderValue::try_from("image/png, image/*;q=0.5").unwrap();
+        assert_eq!(negotiate(&accept, &["text/html", "application/json"]), None);
+    }
+}
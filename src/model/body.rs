@@ -1,8 +1,26 @@
+#[cfg(feature = "encoding")]
+use crate::model::mime::MediaType;
+#[cfg(feature = "encoding")]
+use crate::model::HeaderValue;
 use crate::model::Headers;
+use crate::utils::{invalid_data_error, invalid_input_error};
+#[cfg(feature = "encoding")]
+use encoding_rs::Encoding;
 #[cfg(feature = "flate2")]
 use flate2::read::{DeflateDecoder, GzDecoder};
+#[cfg(all(feature = "client", feature = "flate2"))]
+use flate2::read::{DeflateEncoder, GzEncoder};
+#[cfg(all(feature = "client", feature = "flate2"))]
+use flate2::Compression;
+#[cfg(feature = "encoding")]
+use std::borrow::Cow;
 use std::fmt;
-use std::io::{Cursor, Error, ErrorKind, Read, Result};
+use std::fs::File;
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::mem::take;
+
+/// Maximal size of the items returned by [`Body::chunks`].
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
 
 /// A request or response [body](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#message.body).
 ///
@@ -13,15 +31,63 @@ enum BodyAlt {
     SimpleOwned(Cursor<Vec<u8>>),
     SimpleBorrowed(&'static [u8]),
     Sized {
-        content: Box<dyn Read>,
+        content: SizedContent,
         total_len: u64,
         consumed_len: u64,
     },
-    Chunked(Box<dyn ChunkedTransferPayload>),
+    Chunked {
+        payload: Box<dyn ChunkedTransferPayload>,
+        consumed: bool,
+    },
     #[cfg(feature = "flate2")]
     DecodingDeflate(DeflateDecoder<Box<Body>>),
     #[cfg(feature = "flate2")]
     DecodingGzip(GzDecoder<Box<Body>>),
+    #[cfg(all(feature = "client", feature = "flate2"))]
+    EncodingDeflate(DeflateEncoder<Box<Body>>),
+    #[cfg(all(feature = "client", feature = "flate2"))]
+    EncodingGzip(GzEncoder<Box<Body>>),
+    Peeked {
+        buffer: Vec<u8>,
+        position: usize,
+        inner: Box<Body>,
+    },
+    #[cfg(feature = "client")]
+    WithDropHook(DropHookBody),
+    #[cfg(feature = "client")]
+    WithEndCheck {
+        inner: Box<Body>,
+        check: Option<Box<dyn FnOnce() -> Result<()>>>,
+    },
+    Limited {
+        inner: Box<Body>,
+        remaining: u64,
+        limit: u64,
+    },
+    Chained {
+        parts: Vec<Body>,
+        current: usize,
+    },
+    Tee {
+        inner: Box<Body>,
+        sink: Box<dyn Write>,
+    },
+}
+
+/// The content of [`BodyAlt::Sized`], files are kept as is to allow sending them without copy.
+enum SizedContent {
+    Read(Box<dyn Read>),
+    File(File),
+}
+
+impl Read for SizedContent {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Read(read) => read.read(buf),
+            Self::File(file) => file.read(buf),
+        }
+    }
 }
 
 impl Body {
@@ -33,19 +99,151 @@ impl Body {
         Self::from_chunked_transfer_payload(SimpleChunkedTransferEncoding(read))
     }
 
+    /// Creates a new body from an iterator of chunks.
+    ///
+    /// The chunks are read lazily: the iterator is only advanced when the previous chunk has been fully read.
+    /// An error returned by the iterator is returned by [`read`](Read::read).
+    ///
+    /// If the body is sent as an HTTP request or response it will be streamed using [chunked transfer encoding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#chunked.encoding).
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    ///
+    /// let body = Body::from_iter([b"foo".to_vec(), b"bar".to_vec()].into_iter().map(Ok));
+    /// assert_eq!(&body.to_vec()?, b"foobar");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[allow(clippy::should_implement_trait)]
+    #[inline]
+    pub fn from_iter(iter: impl Iterator<Item = Result<Vec<u8>>> + 'static) -> Self {
+        Self::from_read(IterRead {
+            iter,
+            current: Cursor::new(Vec::new()),
+        })
+    }
+
+    /// Creates a new body from a file.
+    ///
+    /// If the file is a regular file, its size is used as the body length
+    /// and the body is sent with a `Content-Length` header without being buffered.
+    /// Otherwise (e.g. pipes), the body is streamed using [chunked transfer encoding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#chunked.encoding).
+    /// If the file is truncated while being read, the read fails with a [`ConnectionAborted`](ErrorKind::ConnectionAborted) error.
+    /// On Linux, the server sends the regular files to plain TCP connections with `sendfile(2)`, without copying them to userspace.
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    /// use std::fs::File;
+    ///
+    /// let body = Body::from_file(File::open("Cargo.toml")?)?;
+    /// assert!(body.len().is_some());
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn from_file(file: File) -> Result<Self> {
+        let metadata = file.metadata()?;
+        Ok(if metadata.is_file() {
+            Self(BodyAlt::Sized {
+                total_len: metadata.len(),
+                consumed_len: 0,
+                content: SizedContent::File(file),
+            })
+        } else {
+            Self::from_read(file)
+        })
+    }
+
+    /// Creates a new body from the bytes of a regular file between `start` (included) and `end` (excluded).
+    ///
+    /// Like with [`from_file`](Self::from_file), the body is sent with a `Content-Length` header without being buffered.
+    /// It fails with an [`InvalidInput`](ErrorKind::InvalidInput) error if the file is not a regular file
+    /// or if the range is not included in the file.
+    ///
+    /// See [`range::file_response`](crate::model::range::file_response) to answer requests with a [`Range`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.range) header.
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    /// use std::fs::File;
+    ///
+    /// let body = Body::from_file_range(File::open("Cargo.toml")?, 1, 8)?;
+    /// assert_eq!(body.to_string()?, "package");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn from_file_range(mut file: File, start: u64, end: u64) -> Result<Self> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(invalid_input_error(
+                "Only ranges of regular files can be read",
+            ));
+        }
+        if start > end || end > metadata.len() {
+            return Err(invalid_input_error(format!(
+                "The range {start}..{end} is not included in the file of {} bytes",
+                metadata.len()
+            )));
+        }
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Self(BodyAlt::Sized {
+            total_len: end - start,
+            consumed_len: 0,
+            content: SizedContent::File(file),
+        }))
+    }
+
     #[inline]
     pub(crate) fn from_read_and_len(read: impl Read + 'static, len: u64) -> Self {
         Self(BodyAlt::Sized {
             total_len: len,
             consumed_len: 0,
-            content: Box::new(read.take(len)),
+            content: SizedContent::Read(Box::new(read.take(len))),
         })
     }
 
+    /// Checks if the body has been built with [`from_file`](Self::from_file) from a regular file.
+    #[inline]
+    pub(crate) fn is_file(&self) -> bool {
+        matches!(
+            self.0,
+            BodyAlt::Sized {
+                content: SizedContent::File(_),
+                ..
+            }
+        )
+    }
+
+    /// Writes the remaining content of a body built with [`from_file`](Self::from_file) using `send_file`.
+    ///
+    /// `send_file` copies up to the given number of bytes from the current position of the file
+    /// and returns the number of copied bytes, `0` if it can't copy anything.
+    /// Returns the number of bytes written this way, the rest of the body is left to be read as usual.
+    pub(crate) fn send_file(
+        &mut self,
+        send_file: &mut dyn FnMut(&File, u64) -> Result<u64>,
+    ) -> Result<u64> {
+        let BodyAlt::Sized {
+            content: SizedContent::File(file),
+            total_len,
+            consumed_len,
+        } = &mut self.0
+        else {
+            return Ok(0);
+        };
+        let start = *consumed_len;
+        while *consumed_len < *total_len {
+            let sent = send_file(file, *total_len - *consumed_len)?;
+            if sent == 0 {
+                break;
+            }
+            *consumed_len += sent;
+        }
+        Ok(*consumed_len - start)
+    }
+
     /// Creates a [chunked transfer encoding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#chunked.encoding) body with optional [trailers](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#trailer.fields).
     #[inline]
     pub fn from_chunked_transfer_payload(payload: impl ChunkedTransferPayload + 'static) -> Self {
-        Self(BodyAlt::Chunked(Box::new(payload)))
+        Self(BodyAlt::Chunked {
+            payload: Box::new(payload),
+            consumed: false,
+        })
     }
 
     #[cfg(feature = "flate2")]
@@ -60,6 +258,148 @@ impl Body {
         ))))
     }
 
+    #[cfg(all(feature = "client", feature = "flate2"))]
+    pub(crate) fn encode_gzip(self) -> Self {
+        Self(BodyAlt::EncodingGzip(GzEncoder::new(
+            Box::new(self),
+            Compression::default(),
+        )))
+    }
+
+    #[cfg(all(feature = "client", feature = "flate2"))]
+    pub(crate) fn encode_deflate(self) -> Self {
+        Self(BodyAlt::EncodingDeflate(DeflateEncoder::new(
+            Box::new(self),
+            Compression::default(),
+        )))
+    }
+
+    /// Limits the number of bytes that can be read from the body.
+    ///
+    /// Reading past `limit` bytes returns an [`InvalidData`](ErrorKind::InvalidData) error instead of the content.
+    /// If the body size is known and greater than `limit`, the error is returned by the first read.
+    ///
+    /// It is useful to protect against untrusted peers sending huge bodies,
+    /// for example before calling [`to_vec`](Self::to_vec).
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    ///
+    /// assert_eq!(Body::from("foo").with_size_limit(3).to_vec()?, b"foo");
+    /// assert!(Body::from_read(b"foobar".as_slice())
+    ///     .with_size_limit(3)
+    ///     .to_vec()
+    ///     .is_err());
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_size_limit(self, limit: u64) -> Self {
+        Self(BodyAlt::Limited {
+            inner: Box::new(self),
+            remaining: limit,
+            limit,
+        })
+    }
+
+    /// Concatenates bodies into a single one that reads each part in sequence.
+    ///
+    /// The length is known only if the lengths of all the parts are known,
+    /// otherwise the body will be streamed using [chunked transfer encoding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#chunked.encoding).
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    ///
+    /// let body = Body::chain(vec!["<html>".into(), "content".into(), "</html>".into()]);
+    /// assert_eq!(body.len(), Some(20));
+    /// assert_eq!(body.to_string()?, "<html>content</html>");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn chain(parts: Vec<Body>) -> Self {
+        Self(BodyAlt::Chained { parts, current: 0 })
+    }
+
+    /// Copies the bytes read from the body into `sink` while they are read.
+    ///
+    /// The returned body yields the same bytes as the original one.
+    /// The sink is flushed when the end of the body is reached.
+    /// Errors raised by the sink are returned by the body reads.
+    /// It is useful to store a body while forwarding it, for example in a caching proxy.
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    /// use std::fs::File;
+    ///
+    /// # let path = std::env::temp_dir().join(format!("oxhttp-tee-doc-{}", std::process::id()));
+    /// let body = Body::from_read(b"foo".as_slice()).tee(File::create(&path)?);
+    /// assert_eq!(body.to_vec()?, b"foo");
+    /// assert_eq!(std::fs::read(&path)?, b"foo");
+    /// # std::fs::remove_file(&path)?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn tee(self, sink: impl Write + 'static) -> Self {
+        Self(BodyAlt::Tee {
+            inner: Box::new(self),
+            sink: Box::new(sink),
+        })
+    }
+
+    /// Calls `hook` when the body is dropped, just before the inner body is dropped.
+    #[cfg(feature = "client")]
+    pub(crate) fn with_drop_hook(self, hook: impl FnOnce() + 'static) -> Self {
+        Self(BodyAlt::WithDropHook(DropHookBody {
+            inner: Box::new(self),
+            hook: Some(Box::new(hook)),
+        }))
+    }
+
+    /// Calls `check` when the end of the body is reached, its error is returned by the last read.
+    #[cfg(feature = "client")]
+    pub(crate) fn with_end_check(self, check: impl FnOnce() -> Result<()> + 'static) -> Self {
+        Self(BodyAlt::WithEndCheck {
+            inner: Box::new(self),
+            check: Some(Box::new(check)),
+        })
+    }
+
+    /// Returns a way to build again a body with the current remaining content, without reading this one.
+    ///
+    /// It is only possible for the bodies stored in memory and the bodies built with [`from_file`](Self::from_file) from a regular file,
+    /// the file is then read again from its current position.
+    #[cfg(feature = "client")]
+    pub(crate) fn replay(&self) -> Result<Option<BodyReplay>> {
+        if let Some(content) = self.in_memory_content() {
+            return Ok(Some(BodyReplay::InMemory(content.to_vec())));
+        }
+        let BodyAlt::Sized {
+            content: SizedContent::File(file),
+            total_len,
+            consumed_len,
+        } = &self.0
+        else {
+            return Ok(None);
+        };
+        Ok(Some(BodyReplay::File {
+            file: file.try_clone()?,
+            position: (&*file).stream_position()?,
+            len: total_len - consumed_len,
+        }))
+    }
+
+    /// The remaining content of the body if it is stored in memory.
+    pub(crate) fn in_memory_content(&self) -> Option<&[u8]> {
+        match &self.0 {
+            BodyAlt::SimpleOwned(c) => Some(
+                &c.get_ref()[usize::try_from(c.position())
+                    .unwrap_or(usize::MAX)
+                    .min(c.get_ref().len())..],
+            ),
+            BodyAlt::SimpleBorrowed(c) => Some(c),
+            _ => None,
+        }
+    }
+
     /// The number of bytes in the body (if known).
     #[allow(clippy::len_without_is_empty)]
     #[inline]
@@ -68,23 +408,244 @@ impl Body {
             BodyAlt::SimpleOwned(d) => Some(d.get_ref().len().try_into().unwrap()),
             BodyAlt::SimpleBorrowed(d) => Some(d.len().try_into().unwrap()),
             BodyAlt::Sized { total_len, .. } => Some(*total_len),
-            BodyAlt::Chunked(_) => None,
+            BodyAlt::Chunked { .. } => None,
             #[cfg(feature = "flate2")]
             BodyAlt::DecodingDeflate(_) | BodyAlt::DecodingGzip(_) => None,
+            #[cfg(all(feature = "client", feature = "flate2"))]
+            BodyAlt::EncodingDeflate(_) | BodyAlt::EncodingGzip(_) => None,
+            BodyAlt::Peeked { inner, .. } => inner.len(),
+            #[cfg(feature = "client")]
+            BodyAlt::WithDropHook(b) => b.inner.len(),
+            #[cfg(feature = "client")]
+            BodyAlt::WithEndCheck { inner, .. } => inner.len(),
+            BodyAlt::Limited { inner, .. } | BodyAlt::Tee { inner, .. } => inner.len(),
+            BodyAlt::Chained { parts, .. } => parts
+                .iter()
+                .try_fold(0_u64, |len, part| len.checked_add(part.len()?)),
         }
     }
 
     /// Returns the chunked transfer encoding trailers if they exists and are already received.
     /// You should fully consume the body before attempting to fetch them.
+    ///
+    /// For example, a server handler reads the request body until the end with `request.body_mut()`,
+    /// then gets the trailers with `request.body().trailers()`.
+    /// Beware that [`to_vec`](Self::to_vec) and [`to_string`](Self::to_string) consume the body and its trailers with it.
     #[inline]
     pub fn trailers(&self) -> Option<&Headers> {
         match &self.0 {
             BodyAlt::SimpleOwned(_) | BodyAlt::SimpleBorrowed(_) | BodyAlt::Sized { .. } => None,
-            BodyAlt::Chunked(c) => c.trailers(),
+            BodyAlt::Chunked { payload, .. } => payload.trailers(),
             #[cfg(feature = "flate2")]
             BodyAlt::DecodingDeflate(c) => c.get_ref().trailers(),
             #[cfg(feature = "flate2")]
             BodyAlt::DecodingGzip(c) => c.get_ref().trailers(),
+            #[cfg(all(feature = "client", feature = "flate2"))]
+            BodyAlt::EncodingDeflate(c) => c.get_ref().trailers(),
+            #[cfg(all(feature = "client", feature = "flate2"))]
+            BodyAlt::EncodingGzip(c) => c.get_ref().trailers(),
+            BodyAlt::Peeked { inner, .. } => inner.trailers(),
+            #[cfg(feature = "client")]
+            BodyAlt::WithDropHook(b) => b.inner.trailers(),
+            #[cfg(feature = "client")]
+            BodyAlt::WithEndCheck { inner, .. } => inner.trailers(),
+            BodyAlt::Limited { inner, .. } | BodyAlt::Tee { inner, .. } => inner.trailers(),
+            BodyAlt::Chained { parts, .. } => parts.last()?.trailers(),
+        }
+    }
+
+    /// Returns up to the `n` next bytes of the body without consuming them.
+    ///
+    /// Fewer bytes are returned only if the body ends before.
+    /// The peeked bytes are returned again by the following [`read`](Read::read) calls.
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    /// use std::io::Read;
+    ///
+    /// let mut body = Body::from_read(b"%PDF-1.7".as_ref());
+    /// assert_eq!(body.peek(4)?, b"%PDF");
+    /// assert_eq!(body.to_vec()?, b"%PDF-1.7");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn peek(&mut self, n: usize) -> Result<&[u8]> {
+        if !matches!(
+            self.0,
+            BodyAlt::SimpleOwned(_) | BodyAlt::SimpleBorrowed(_) | BodyAlt::Peeked { .. }
+        ) {
+            // We add a buffer in front of the body
+            *self = Self(BodyAlt::Peeked {
+                buffer: Vec::with_capacity(n),
+                position: 0,
+                inner: Box::new(take(self)),
+            });
+        }
+        match &mut self.0 {
+            BodyAlt::SimpleOwned(c) => {
+                let remaining = &c.get_ref()[usize::try_from(c.position())
+                    .unwrap()
+                    .min(c.get_ref().len())..];
+                Ok(&remaining[..n.min(remaining.len())])
+            }
+            BodyAlt::SimpleBorrowed(c) => Ok(&c[..n.min(c.len())]),
+            BodyAlt::Peeked {
+                buffer,
+                position,
+                inner,
+            } => {
+                buffer.drain(..*position);
+                *position = 0;
+                if buffer.len() < n {
+                    inner
+                        .take(u64::try_from(n - buffer.len()).unwrap())
+                        .read_to_end(buffer)?;
+                }
+                Ok(&buffer[..n.min(buffer.len())])
+            }
+            _ => unreachable!("Other bodies have been wrapped"),
+        }
+    }
+
+    /// Iterates over the body content, chunk by chunk, without buffering it fully.
+    ///
+    /// For bodies received with [chunked transfer encoding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#chunked.encoding),
+    /// each item is a chunk as sent on the wire.
+    /// For the other bodies, each item is the result of a read.
+    /// The items are at most 64KiB long, larger chunks are split.
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    ///
+    /// let mut body = Body::from("foo");
+    /// let chunks = body.chunks().collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(chunks, [b"foo"]);
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn chunks(&mut self) -> impl Iterator<Item = Result<Vec<u8>>> + '_ {
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let chunk = self.read_chunk(MAX_CHUNK_SIZE).transpose();
+            failed = matches!(chunk, Some(Err(_)));
+            chunk
+        })
+    }
+
+    /// Iterates over the frames of the body, consuming it.
+    ///
+    /// For bodies received with [chunked transfer encoding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#chunked.encoding),
+    /// each frame is a chunk as sent on the wire, whatever its size.
+    /// The other bodies are returned as a single frame.
+    /// Empty bodies have no frame.
+    ///
+    /// <div class="warning">Beware of the frame sizes! Use <a href="#method.with_size_limit"><code>with_size_limit</code></a> with untrusted peers.</div>
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    ///
+    /// let frames = Body::from("foo").frames().collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(frames, [b"foo"]);
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn frames(mut self) -> impl Iterator<Item = Result<Vec<u8>>> {
+        let is_chunked = self.is_chunked();
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let frame = if is_chunked {
+                self.read_chunk(usize::MAX)
+            } else {
+                take(&mut self)
+                    .to_vec()
+                    .map(|frame| Some(frame).filter(|frame| !frame.is_empty()))
+            }
+            .transpose();
+            failed = matches!(frame, Some(Err(_)));
+            frame
+        })
+    }
+
+    fn is_chunked(&self) -> bool {
+        match &self.0 {
+            BodyAlt::Chunked { .. } => true,
+            BodyAlt::Peeked { inner, .. }
+            | BodyAlt::Limited { inner, .. }
+            | BodyAlt::Tee { inner, .. } => inner.is_chunked(),
+            #[cfg(feature = "client")]
+            BodyAlt::WithDropHook(b) => b.inner.is_chunked(),
+            #[cfg(feature = "client")]
+            BodyAlt::WithEndCheck { inner, .. } => inner.is_chunked(),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn read_chunk(&mut self, max_size: usize) -> Result<Option<Vec<u8>>> {
+        match &mut self.0 {
+            BodyAlt::Chunked { payload, consumed } => {
+                let chunk = payload.read_chunk(max_size)?;
+                if chunk.is_none() {
+                    *consumed = true;
+                }
+                Ok(chunk)
+            }
+            BodyAlt::Peeked {
+                buffer, position, ..
+            } if *position < buffer.len() => {
+                let end = buffer.len().min(position.saturating_add(max_size));
+                let chunk = buffer[*position..end].to_vec();
+                *position = end;
+                Ok(Some(chunk))
+            }
+            BodyAlt::Peeked { inner, .. } => inner.read_chunk(max_size),
+            #[cfg(feature = "client")]
+            BodyAlt::WithDropHook(b) => b.inner.read_chunk(max_size),
+            #[cfg(feature = "client")]
+            BodyAlt::WithEndCheck { inner, check } if inner.is_chunked() => {
+                let chunk = inner.read_chunk(max_size)?;
+                if chunk.is_none() {
+                    if let Some(check) = check.take() {
+                        check()?;
+                    }
+                }
+                Ok(chunk)
+            }
+            BodyAlt::Limited {
+                inner,
+                remaining,
+                limit,
+            } if inner.is_chunked() => {
+                // We read one more byte than allowed to detect too large chunks
+                let max_size = max_size.min(
+                    usize::try_from(*remaining)
+                        .unwrap_or(usize::MAX)
+                        .saturating_add(1),
+                );
+                let chunk = inner.read_chunk(max_size)?;
+                if let Some(chunk) = &chunk {
+                    let len = u64::try_from(chunk.len()).unwrap_or(u64::MAX);
+                    if len > *remaining {
+                        return Err(invalid_data_error(format!(
+                            "The body is larger than the limit of {limit} bytes"
+                        )));
+                    }
+                    *remaining -= len;
+                }
+                Ok(chunk)
+            }
+            BodyAlt::Tee { inner, sink } if inner.is_chunked() => {
+                let chunk = inner.read_chunk(max_size)?;
+                if let Some(chunk) = &chunk {
+                    sink.write_all(chunk)?;
+                } else {
+                    sink.flush()?;
+                }
+                Ok(chunk)
+            }
+            _ => SimpleChunkedTransferEncoding(self).read_chunk(max_size),
         }
     }
 
@@ -100,10 +661,27 @@ impl Body {
     /// assert_eq!(&body.to_vec()?, b"foo");
     /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
     /// ```
+    ///
+    /// If the body is shorter than its announced length, the returned [`ConnectionAborted`](ErrorKind::ConnectionAborted) error
+    /// wraps a [`TruncatedBody`] giving access to the content read before the error.
     #[inline]
     pub fn to_vec(mut self) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
-        self.read_to_end(&mut buf)?;
+        if let Err(error) = self.read_to_end(&mut buf) {
+            if !error
+                .get_ref()
+                .is_some_and(|inner| inner.is::<TruncatedBody>())
+            {
+                return Err(error);
+            }
+            let kind = error.kind();
+            let mut truncated = error
+                .into_inner()
+                .and_then(|inner| inner.downcast::<TruncatedBody>().ok())
+                .unwrap();
+            truncated.content = buf;
+            return Err(Error::new(kind, *truncated));
+        }
         Ok(buf)
     }
 
@@ -119,11 +697,62 @@ impl Body {
     /// assert_eq!(&body.to_string()?, "foo");
     /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
     /// ```
+    ///
+    /// Returns an [`InvalidData`](ErrorKind::InvalidData) error if the body is not valid UTF-8.
     #[inline]
-    pub fn to_string(mut self) -> Result<String> {
-        let mut buf = String::new();
-        self.read_to_string(&mut buf)?;
-        Ok(buf)
+    pub fn to_string(self) -> Result<String> {
+        String::from_utf8(self.to_vec()?)
+            .map_err(|e| invalid_data_error(format!("The body is not valid UTF-8: {e}")))
+    }
+
+    /// Reads the full body into a string, replacing invalid UTF-8 sequences with `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// <div class="warning">Beware of the body size!</div>
+    ///
+    /// ```
+    /// use oxhttp::model::Body;
+    ///
+    /// let body = Body::from(b"foo\xFFbar".to_vec());
+    /// assert_eq!(body.to_string_lossy()?, "foo\u{FFFD}bar");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn to_string_lossy(self) -> Result<String> {
+        Ok(match String::from_utf8(self.to_vec()?) {
+            Ok(string) => string,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        })
+    }
+
+    /// Reads the full body into a string using the `charset` parameter of the given [`Content-Type`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.content-type) header value.
+    ///
+    /// The charset labels are the ones of the [Encoding standard](https://encoding.spec.whatwg.org/#names-and-labels).
+    /// The body is decoded as UTF-8 if there is no `charset` parameter.
+    /// Returns an [`InvalidData`](ErrorKind::InvalidData) error if the charset is not supported or if the body is not valid in this charset.
+    ///
+    /// <div class="warning">Beware of the body size!</div>
+    ///
+    /// ```
+    /// use oxhttp::model::{Body, HeaderValue};
+    ///
+    /// let body = Body::from(b"caf\xE9".to_vec());
+    /// let content_type = HeaderValue::try_from("text/plain; charset=ISO-8859-1")?;
+    /// assert_eq!(body.into_string_with_charset(Some(&content_type))?, "café");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[cfg(feature = "encoding")]
+    pub fn into_string_with_charset(self, content_type: Option<&HeaderValue>) -> Result<String> {
+        let Some(charset) = content_type.and_then(charset_parameter) else {
+            return self.to_string();
+        };
+        let encoding = Encoding::for_label(charset.as_bytes()).ok_or_else(|| {
+            invalid_data_error(format!("The charset '{charset}' is not supported"))
+        })?;
+        let content = self.to_vec()?;
+        encoding
+            .decode_without_bom_handling_and_without_replacement(&content)
+            .map(Cow::into_owned)
+            .ok_or_else(|| invalid_data_error(format!("The body is not valid {}", encoding.name())))
     }
 
     fn debug_fields<'a, 'b, 'c>(
@@ -131,10 +760,20 @@ impl Body {
         s: &'c mut fmt::DebugStruct<'b, 'a>,
     ) -> &'c mut fmt::DebugStruct<'b, 'a> {
         match &self.0 {
-            BodyAlt::SimpleOwned(d) => s.field("content-length", &d.get_ref().len()),
-            BodyAlt::SimpleBorrowed(d) => s.field("content-length", &d.len()),
-            BodyAlt::Sized { total_len, .. } => s.field("content-length", total_len),
-            BodyAlt::Chunked(_) => s.field("transfer-encoding", &"chunked"),
+            BodyAlt::SimpleOwned(d) => Self::debug_fixed_fields(s, d.get_ref().len()),
+            BodyAlt::SimpleBorrowed(d) => Self::debug_fixed_fields(s, d.len()),
+            BodyAlt::Sized {
+                total_len,
+                consumed_len,
+                ..
+            } => s
+                .field("kind", &format_args!("Streaming"))
+                .field("content-length", total_len)
+                .field("consumed-length", consumed_len),
+            BodyAlt::Chunked { consumed, .. } => s
+                .field("kind", &format_args!("Chunked"))
+                .field("transfer-encoding", &"chunked")
+                .field("consumed", consumed),
             #[cfg(feature = "flate2")]
             BodyAlt::DecodingDeflate(inner) => inner
                 .get_ref()
@@ -143,7 +782,53 @@ impl Body {
             BodyAlt::DecodingGzip(inner) => inner
                 .get_ref()
                 .debug_fields(s.field("content-encoding", &"gzip")),
+            #[cfg(all(feature = "client", feature = "flate2"))]
+            BodyAlt::EncodingDeflate(inner) => inner
+                .get_ref()
+                .debug_fields(s.field("encoding-to", &"deflate")),
+            #[cfg(all(feature = "client", feature = "flate2"))]
+            BodyAlt::EncodingGzip(inner) => inner
+                .get_ref()
+                .debug_fields(s.field("encoding-to", &"gzip")),
+            BodyAlt::Peeked {
+                buffer,
+                position,
+                inner,
+            } => {
+                if *position < buffer.len() {
+                    s.field("peeked-length", &(buffer.len() - position));
+                }
+                inner.debug_fields(s)
+            }
+            #[cfg(feature = "client")]
+            BodyAlt::WithDropHook(b) => b.inner.debug_fields(s),
+            #[cfg(feature = "client")]
+            BodyAlt::WithEndCheck { inner, .. } => inner.debug_fields(s),
+            BodyAlt::Limited { inner, limit, .. } => {
+                inner.debug_fields(s.field("size-limit", limit))
+            }
+            BodyAlt::Tee { inner, .. } => inner.debug_fields(s.field("tee", &true)),
+            BodyAlt::Chained { parts, current } => {
+                if let Some(len) = self.len() {
+                    s.field("content-length", &len);
+                }
+                s.field("kind", &format_args!("Chained"))
+                    .field("parts", parts)
+                    .field("current-part", current)
+            }
+        }
+    }
+
+    fn debug_fixed_fields<'a, 'b, 'c>(
+        s: &'c mut fmt::DebugStruct<'b, 'a>,
+        len: usize,
+    ) -> &'c mut fmt::DebugStruct<'b, 'a> {
+        if len == 0 {
+            s.field("kind", &format_args!("Empty"))
+        } else {
+            s.field("kind", &format_args!("Fixed({len})"))
         }
+        .field("content-length", &len)
     }
 }
 
@@ -169,15 +854,227 @@ impl Read for Body {
                 *consumed_len += u64::try_from(read).unwrap();
                 if read == 0 {
                     // We are missing some bytes
-                    return Err(Error::new(ErrorKind::ConnectionAborted, format!("The body was expected to contain {total_len} bytes but we have been able to only read {consumed_len}")));
+                    return Err(Error::new(
+                        ErrorKind::ConnectionAborted,
+                        TruncatedBody {
+                            content: Vec::new(),
+                            expected_len: *total_len,
+                            missing_len: remaining_size,
+                        },
+                    ));
+                }
+                Ok(read)
+            }
+            BodyAlt::Chunked { payload, consumed } => {
+                let read = payload.read(buf)?;
+                if read == 0 && !buf.is_empty() {
+                    *consumed = true;
                 }
                 Ok(read)
             }
-            BodyAlt::Chunked(inner) => inner.read(buf),
             #[cfg(feature = "flate2")]
             BodyAlt::DecodingDeflate(inner) => inner.read(buf),
             #[cfg(feature = "flate2")]
             BodyAlt::DecodingGzip(inner) => inner.read(buf),
+            #[cfg(all(feature = "client", feature = "flate2"))]
+            BodyAlt::EncodingDeflate(inner) => inner.read(buf),
+            #[cfg(all(feature = "client", feature = "flate2"))]
+            BodyAlt::EncodingGzip(inner) => inner.read(buf),
+            BodyAlt::Peeked {
+                buffer,
+                position,
+                inner,
+            } => {
+                if *position < buffer.len() {
+                    let read = (&buffer[*position..]).read(buf)?;
+                    *position += read;
+                    Ok(read)
+                } else {
+                    inner.read(buf)
+                }
+            }
+            #[cfg(feature = "client")]
+            BodyAlt::WithDropHook(b) => b.inner.read(buf),
+            #[cfg(feature = "client")]
+            BodyAlt::WithEndCheck { inner, check } => {
+                let read = inner.read(buf)?;
+                if read == 0 && !buf.is_empty() {
+                    if let Some(check) = check.take() {
+                        check()?;
+                    }
+                }
+                Ok(read)
+            }
+            BodyAlt::Limited {
+                inner,
+                remaining,
+                limit,
+            } => {
+                let too_large_error = |limit| {
+                    invalid_data_error(format!(
+                        "The body is larger than the limit of {limit} bytes"
+                    ))
+                };
+                if inner.len().is_some_and(|len| len > *limit) {
+                    return Err(too_large_error(*limit));
+                }
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+                if *remaining == 0 {
+                    // We check that the body is finished
+                    return if inner.read(&mut [0])? == 0 {
+                        Ok(0)
+                    } else {
+                        Err(too_large_error(*limit))
+                    };
+                }
+                if *remaining < u64::try_from(buf.len()).unwrap_or(u64::MAX) {
+                    buf = &mut buf[..usize::try_from(*remaining).unwrap()];
+                }
+                let read = inner.read(buf)?;
+                *remaining -= u64::try_from(read).unwrap();
+                Ok(read)
+            }
+            BodyAlt::Chained { parts, current } => {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+                while let Some(part) = parts.get_mut(*current) {
+                    let read = part.read(buf)?;
+                    if read > 0 {
+                        return Ok(read);
+                    }
+                    *current += 1;
+                }
+                Ok(0)
+            }
+            BodyAlt::Tee { inner, sink } => {
+                let read = inner.read(buf)?;
+                if read > 0 {
+                    sink.write_all(&buf[..read])?;
+                } else if !buf.is_empty() {
+                    sink.flush()?;
+                }
+                Ok(read)
+            }
+        }
+    }
+}
+
+/// Error returned when a body is shorter than its announced length.
+///
+/// It is wrapped in the [`ConnectionAborted`](ErrorKind::ConnectionAborted) error returned by [`Body::to_vec`],
+/// allowing to recover the received content, for example to resume a download with a `Range` request.
+///
+/// ```
+/// use oxhttp::model::{Body, TruncatedBody};
+///
+/// # let body = Body::from("");
+/// match body.to_vec() {
+///     Ok(content) => println!("{} bytes received", content.len()),
+///     Err(error) => match error.get_ref().and_then(|e| e.downcast_ref::<TruncatedBody>()) {
+///         Some(truncated) => println!(
+///             "{} bytes received, {} missing",
+///             truncated.content().len(),
+///             truncated.missing_len()
+///         ),
+///         None => return Err(error.into()),
+///     },
+/// }
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TruncatedBody {
+    content: Vec<u8>,
+    expected_len: u64,
+    missing_len: u64,
+}
+
+impl TruncatedBody {
+    /// The content read before the body ended.
+    ///
+    /// It is only filled by [`Body::to_vec`], the errors returned by [`read`](Read::read) have an empty content.
+    #[inline]
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    #[inline]
+    pub fn into_content(self) -> Vec<u8> {
+        self.content
+    }
+
+    /// The length announced by the `Content-Length` header.
+    #[inline]
+    pub fn expected_len(&self) -> u64 {
+        self.expected_len
+    }
+
+    /// The number of bytes missing to reach the announced length.
+    #[inline]
+    pub fn missing_len(&self) -> u64 {
+        self.missing_len
+    }
+}
+
+impl fmt::Display for TruncatedBody {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The body was expected to contain {} bytes but we have been able to only read {}",
+            self.expected_len,
+            self.expected_len - self.missing_len
+        )
+    }
+}
+
+impl std::error::Error for TruncatedBody {}
+
+/// A body content that can be read again, see [`Body::replay`]
+#[cfg(feature = "client")]
+pub(crate) enum BodyReplay {
+    InMemory(Vec<u8>),
+    File { file: File, position: u64, len: u64 },
+}
+
+#[cfg(feature = "client")]
+impl BodyReplay {
+    /// Builds a new body with the content
+    pub(crate) fn body(&self) -> Result<Body> {
+        Ok(match self {
+            Self::InMemory(content) => content.clone().into(),
+            Self::File {
+                file,
+                position,
+                len,
+            } => {
+                // The clone shares the file cursor with the previous bodies
+                let mut file = file.try_clone()?;
+                file.seek(SeekFrom::Start(*position))?;
+                Body(BodyAlt::Sized {
+                    content: SizedContent::File(file),
+                    total_len: *len,
+                    consumed_len: 0,
+                })
+            }
+        })
+    }
+}
+
+/// Body calling a hook when dropped, see [`Body::with_drop_hook`]
+#[cfg(feature = "client")]
+struct DropHookBody {
+    inner: Box<Body>,
+    hook: Option<Box<dyn FnOnce()>>,
+}
+
+#[cfg(feature = "client")]
+impl Drop for DropHookBody {
+    fn drop(&mut self) {
+        if let Some(hook) = self.hook.take() {
+            hook();
         }
     }
 }
@@ -224,6 +1121,17 @@ impl fmt::Debug for Body {
     }
 }
 
+/// Extracts the `charset` parameter of a `Content-Type` header value
+#[cfg(feature = "encoding")]
+fn charset_parameter(content_type: &HeaderValue) -> Option<String> {
+    Some(
+        MediaType::parse(content_type)
+            .ok()?
+            .get_param("charset")?
+            .to_owned(),
+    )
+}
+
 /// Trait to give to [`Body::from_chunked_transfer_payload`] a body to serialize
 /// as [chunked transfer encoding](https://httpwg.org/http-core/draft-ietf-httpbis-messaging-latest.html#chunked.encoding).
 ///
@@ -231,10 +1139,51 @@ impl fmt::Debug for Body {
 pub trait ChunkedTransferPayload: Read {
     /// The [trailers](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#trailer.fields) to serialize.
     fn trailers(&self) -> Option<&Headers>;
+
+    /// Reads the next chunk of at most `max_size` bytes or returns `None` if the payload is finished.
+    ///
+    /// It is used by [`Body::chunks`].
+    /// By default, a chunk is the result of a single [`read`](Read::read) call.
+    fn read_chunk(&mut self, max_size: usize) -> Result<Option<Vec<u8>>> {
+        let mut chunk = vec![0; max_size.min(MAX_CHUNK_SIZE)];
+        let read = loop {
+            match self.read(&mut chunk) {
+                Ok(read) => break read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        };
+        if read == 0 {
+            return Ok(None);
+        }
+        chunk.truncate(read);
+        Ok(Some(chunk))
+    }
 }
 
 struct SimpleChunkedTransferEncoding<R: Read>(R);
 
+struct IterRead<I: Iterator<Item = Result<Vec<u8>>>> {
+    iter: I,
+    current: Cursor<Vec<u8>>,
+}
+
+impl<I: Iterator<Item = Result<Vec<u8>>>> Read for IterRead<I> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let Some(chunk) = self.iter.next() else {
+                return Ok(0);
+            };
+            self.current = Cursor::new(chunk?);
+        }
+    }
+}
+
 impl<R: Read> Read for SimpleChunkedTransferEncoding<R> {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
@@ -248,3 +1197,319 @@ impl<R: Read> ChunkedTransferPayload for SimpleChunkedTransferEncoding<R> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_shows_body_kind() {
+        assert_eq!(
+            format!("{:?}", Body::default()),
+            "Body { kind: Empty, content-length: 0 }"
+        );
+        assert_eq!(
+            format!("{:?}", Body::from("foo")),
+            "Body { kind: Fixed(3), content-length: 3 }"
+        );
+        assert_eq!(
+            format!("{:?}", Body::from_read_and_len(b"foo".as_ref(), 3)),
+            "Body { kind: Streaming, content-length: 3, consumed-length: 0 }"
+        );
+        let mut chunked = Body::from_read(b"foo".as_ref());
+        assert_eq!(
+            format!("{chunked:?}"),
+            "Body { kind: Chunked, transfer-encoding: \"chunked\", consumed: false }"
+        );
+        chunked.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(
+            format!("{chunked:?}"),
+            "Body { kind: Chunked, transfer-encoding: \"chunked\", consumed: true }"
+        );
+    }
+
+    #[test]
+    fn peek_chunked_body() -> Result<()> {
+        let mut body = Body::from_iter(
+            [b"fo".to_vec(), b"ob".to_vec(), b"arbaz".to_vec()]
+                .into_iter()
+                .map(Ok),
+        );
+        assert_eq!(body.peek(4)?, b"foob");
+        assert_eq!(body.peek(2)?, b"fo");
+        let mut buf = [0; 3];
+        body.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"foo");
+        assert_eq!(body.peek(4)?, b"barb");
+        assert_eq!(body.len(), None);
+        assert_eq!(body.to_vec()?, b"barbaz");
+        Ok(())
+    }
+
+    #[test]
+    fn peek_past_the_end() -> Result<()> {
+        let mut body = Body::from_read_and_len(b"foo".as_ref(), 3);
+        assert_eq!(body.peek(10)?, b"foo");
+        assert_eq!(body.len(), Some(3));
+        assert_eq!(body.to_vec()?, b"foo");
+
+        let mut body = Body::from(b"foo".to_vec());
+        assert_eq!(body.peek(2)?, b"fo");
+        assert_eq!(body.to_vec()?, b"foo");
+        Ok(())
+    }
+
+    #[test]
+    fn from_iter_propagates_errors() {
+        let mut body = Body::from_iter(
+            [
+                Ok(b"foo".to_vec()),
+                Ok(Vec::new()),
+                Ok(b"bar".to_vec()),
+                Ok(b"baz".to_vec()),
+                Err(Error::other("database failure")),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(body.len(), None);
+        let mut buf = [0; 16];
+        let mut content = Vec::new();
+        let error = loop {
+            match body.read(&mut buf) {
+                Ok(0) => panic!("the error should be returned"),
+                Ok(read) => content.extend_from_slice(&buf[..read]),
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(content, b"foobarbaz");
+        assert_eq!(error.to_string(), "database failure");
+    }
+
+    #[test]
+    fn size_limit() -> Result<()> {
+        assert_eq!(
+            Body::from_read(b"foobar".as_slice())
+                .with_size_limit(6)
+                .to_vec()?,
+            b"foobar"
+        );
+        let error = Body::from_read(b"foobar".as_slice())
+            .with_size_limit(5)
+            .to_vec()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        // Sized bodies fail right away
+        let mut body = Body::from_read_and_len(b"foobar".as_slice(), 6).with_size_limit(5);
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(
+            body.read(&mut [0; 1]).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn chain() -> Result<()> {
+        let body = Body::chain(vec![
+            "preamble ".into(),
+            Body::from_read_and_len(b"content".as_slice(), 7),
+            " footer".into(),
+        ]);
+        assert_eq!(body.len(), Some(23));
+        assert_eq!(body.to_string()?, "preamble content footer");
+
+        // Unknown lengths make the full length unknown
+        let body = Body::chain(vec!["foo".into(), Body::from_read(b"bar".as_slice())]);
+        assert_eq!(body.len(), None);
+        assert_eq!(body.to_string()?, "foobar");
+
+        // The length of each part is still enforced
+        let body = Body::chain(vec![
+            Body::from_read_and_len(b"foo".as_slice(), 4),
+            "bar".into(),
+        ]);
+        assert_eq!(
+            body.to_vec().unwrap_err().kind(),
+            ErrorKind::ConnectionAborted
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tee() -> Result<()> {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        #[derive(Clone, Default)]
+        struct SharedSink(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for SharedSink {
+            fn write(&mut self, buf: &[u8]) -> Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        for body in [
+            Body::from("foobar"),
+            Body::from_read(b"foobar".as_slice()),
+            Body::chain(vec!["foo".into(), Body::from_read(b"bar".as_slice())]),
+        ] {
+            let sink = SharedSink::default();
+            let body = body.tee(sink.clone());
+            assert_eq!(body.to_vec()?, b"foobar");
+            assert_eq!(*sink.0.borrow(), b"foobar");
+        }
+
+        // The chunks are kept
+        let sink = SharedSink::default();
+        let mut body = Body::from_read(b"foobar".as_slice()).tee(sink.clone());
+        assert_eq!(
+            body.chunks().collect::<Result<Vec<_>>>()?,
+            [b"foobar".to_vec()]
+        );
+        assert_eq!(*sink.0.borrow(), b"foobar");
+
+        // Sink errors are read errors
+        struct FailingSink;
+
+        impl Write for FailingSink {
+            fn write(&mut self, _: &[u8]) -> Result<usize> {
+                Err(Error::other("sink failure"))
+            }
+
+            fn flush(&mut self) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        let error = Body::from("foo").tee(FailingSink).to_vec().unwrap_err();
+        assert_eq!(error.to_string(), "sink failure");
+        Ok(())
+    }
+
+    #[test]
+    fn from_file() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("oxhttp-body-{}", std::process::id()));
+        std::fs::write(&path, b"foobar")?;
+        let body = Body::from_file(File::open(&path)?)?;
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.to_vec()?, b"foobar");
+
+        // Truncated file
+        let body = Body::from_file(File::open(&path)?)?;
+        File::options().write(true).open(&path)?.set_len(3)?;
+        let error = body.to_vec().unwrap_err();
+        std::fs::remove_file(&path)?;
+        assert_eq!(error.kind(), ErrorKind::ConnectionAborted);
+        Ok(())
+    }
+
+    #[test]
+    fn from_file_range() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("oxhttp-file-range-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789")?;
+        let body = Body::from_file_range(File::open(&path)?, 3, 10)?;
+        assert_eq!(body.len(), Some(7));
+        assert_eq!(body.to_vec()?, b"3456789");
+        assert_eq!(
+            Body::from_file_range(File::open(&path)?, 3, 3)?.len(),
+            Some(0)
+        );
+        let invalid_ranges = [
+            Body::from_file_range(File::open(&path)?, 4, 3),
+            Body::from_file_range(File::open(&path)?, 3, 11),
+        ];
+        std::fs::remove_file(&path)?;
+        for invalid_range in invalid_ranges {
+            assert_eq!(invalid_range.unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn replay() -> Result<()> {
+        let mut body = Body::from("foobar");
+        body.read_exact(&mut [0; 3])?;
+        assert_eq!(body.replay()?.unwrap().body()?.to_vec()?, b"bar");
+        assert!(Body::from_read(b"foo".as_slice()).replay()?.is_none());
+
+        let path = std::env::temp_dir().join(format!("oxhttp-replay-{}", std::process::id()));
+        std::fs::write(&path, b"foobar")?;
+        let body = Body::from_file(File::open(&path)?)?;
+        let replay = body.replay()?.unwrap();
+        assert_eq!(body.to_vec()?, b"foobar");
+        let body = replay.body()?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.to_vec()?, b"foobar");
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_body() {
+        let error = Body::from_read_and_len(b"foobar".as_slice(), 10)
+            .to_vec()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ConnectionAborted);
+        let truncated = error
+            .into_inner()
+            .unwrap()
+            .downcast::<TruncatedBody>()
+            .unwrap();
+        assert_eq!(truncated.content(), b"foobar");
+        assert_eq!(truncated.expected_len(), 10);
+        assert_eq!(truncated.missing_len(), 4);
+    }
+
+    #[test]
+    fn to_string_invalid_utf8() {
+        let error = Body::from(b"foo\xFFbar".to_vec()).to_string().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("not valid UTF-8"));
+    }
+
+    #[test]
+    fn to_string_lossy_invalid_utf8() -> Result<()> {
+        assert_eq!(
+            Body::from_read(b"foo\xFFbar".as_slice()).to_string_lossy()?,
+            "foo\u{FFFD}bar"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn into_string_with_windows_1252_charset() -> Result<()> {
+        let content_type = HeaderValue::try_from("text/html; charset=\"windows-1252\"").unwrap();
+        assert_eq!(
+            Body::from(b"\x93caf\xE9\x94 \x80".to_vec())
+                .into_string_with_charset(Some(&content_type))?,
+            "\u{201C}caf\u{E9}\u{201D} \u{20AC}"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn into_string_with_charset_fallbacks_to_utf8() -> Result<()> {
+        let content_type = HeaderValue::try_from("text/plain").unwrap();
+        assert_eq!(
+            Body::from("café").into_string_with_charset(Some(&content_type))?,
+            "café"
+        );
+        assert!(Body::from(b"caf\xE9".to_vec())
+            .into_string_with_charset(None)
+            .is_err());
+        let content_type = HeaderValue::try_from("text/plain; charset=foo").unwrap();
+        assert!(Body::from("foo")
+            .into_string_with_charset(Some(&content_type))
+            .is_err());
+        Ok(())
+    }
+}
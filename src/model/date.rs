@@ -0,0 +1,288 @@
+//! Parsing and serialization of [HTTP dates](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#http.date)
+//! used by headers like `Date`, `Last-Modified`, `Expires` or `Retry-After`.
+
+use crate::model::HeaderValue;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SHORT_DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const LONG_DAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Parses an [HTTP date](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#http.date).
+///
+/// The preferred IMF-fixdate format (`Sun, 06 Nov 1994 08:49:37 GMT`)
+/// and the obsolete RFC 850 (`Sunday, 06-Nov-94 08:49:37 GMT`) and asctime (`Sun Nov  6 08:49:37 1994`) formats are supported.
+/// Returns `None` if the value is not a valid HTTP date.
+///
+/// ```
+/// use oxhttp::model::date::parse_http_date;
+/// use oxhttp::model::HeaderValue;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// assert_eq!(
+///     parse_http_date(&HeaderValue::try_from("Sun, 06 Nov 1994 08:49:37 GMT")?),
+///     Some(UNIX_EPOCH + Duration::from_secs(784111777))
+/// );
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn parse_http_date(value: &HeaderValue) -> Option<SystemTime> {
+    let value = value.to_str().ok()?.trim();
+    let (year, month, day, time) = parse_imf_fixdate(value)
+        .or_else(|| parse_rfc850_date(value))
+        .or_else(|| parse_asctime_date(value))?;
+    let days = days_from_civil(year, month, day);
+    let seconds = days * SECONDS_PER_DAY + i64::from(time);
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds.unsigned_abs()))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    }
+}
+
+/// Serializes a time as an [HTTP date](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#http.date) in the IMF-fixdate format.
+///
+/// Sub-second precision is truncated.
+///
+/// ```
+/// use oxhttp::model::date::format_http_date;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// assert_eq!(
+///     format_http_date(UNIX_EPOCH + Duration::from_secs(784111777)).as_ref(),
+///     b"Sun, 06 Nov 1994 08:49:37 GMT"
+/// );
+/// ```
+pub fn format_http_date(time: SystemTime) -> HeaderValue {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => i64::try_from(duration.as_secs()).unwrap_or(i64::MAX),
+        Err(e) => {
+            // We round toward the past
+            let duration = e.duration();
+            let seconds = i64::try_from(duration.as_secs()).unwrap_or(i64::MAX);
+            if duration.subsec_nanos() > 0 {
+                -seconds - 1
+            } else {
+                -seconds
+            }
+        }
+    };
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let time = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    // 1970-01-01 was a Thursday
+    let weekday = usize::try_from((days + 3).rem_euclid(7)).unwrap();
+    HeaderValue::new_unchecked(
+        format!(
+            "{}, {day:02} {} {year:04} {:02}:{:02}:{:02} GMT",
+            SHORT_DAY_NAMES[weekday],
+            MONTH_NAMES[usize::try_from(month - 1).unwrap()],
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        )
+        .into_bytes(),
+    )
+}
+
+/// `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_imf_fixdate(value: &str) -> Option<(i64, u32, u32, u32)> {
+    let mut parts = value.split(' ');
+    let day_name = parts.next()?.strip_suffix(',')?;
+    if !SHORT_DAY_NAMES.contains(&day_name) {
+        return None;
+    }
+    let day = parse_digits(parts.next()?, 2, 2)?;
+    let month = parse_month(parts.next()?)?;
+    let year = parse_digits(parts.next()?, 4, 4)?;
+    let time = parse_time(parts.next()?)?;
+    if parts.next()? != "GMT" || parts.next().is_some() {
+        return None;
+    }
+    validate_date(i64::from(year), month, day, time)
+}
+
+/// `Sunday, 06-Nov-94 08:49:37 GMT`
+fn parse_rfc850_date(value: &str) -> Option<(i64, u32, u32, u32)> {
+    let mut parts = value.split(' ');
+    let day_name = parts.next()?.strip_suffix(',')?;
+    if !LONG_DAY_NAMES.contains(&day_name) {
+        return None;
+    }
+    let mut date = parts.next()?.split('-');
+    let day = parse_digits(date.next()?, 2, 2)?;
+    let month = parse_month(date.next()?)?;
+    let year = parse_two_digits_year(parse_digits(date.next()?, 2, 2)?);
+    if date.next().is_some() {
+        return None;
+    }
+    let time = parse_time(parts.next()?)?;
+    if parts.next()? != "GMT" || parts.next().is_some() {
+        return None;
+    }
+    validate_date(year, month, day, time)
+}
+
+/// `Sun Nov  6 08:49:37 1994`
+fn parse_asctime_date(value: &str) -> Option<(i64, u32, u32, u32)> {
+    let mut parts = value.split_ascii_whitespace();
+    if !SHORT_DAY_NAMES.contains(&parts.next()?) {
+        return None;
+    }
+    let month = parse_month(parts.next()?)?;
+    let day = parse_digits(parts.next()?, 1, 2)?;
+    let time = parse_time(parts.next()?)?;
+    let year = parse_digits(parts.next()?, 4, 4)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    validate_date(i64::from(year), month, day, time)
+}
+
+/// Interprets a two digits year as the latest year with these digits that is not more than 50 years in the future
+fn parse_two_digits_year(year: u32) -> i64 {
+    let current_year = civil_from_days(
+        i64::try_from(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        )
+        .unwrap_or(i64::MAX)
+            / SECONDS_PER_DAY,
+    )
+    .0;
+    let mut year = current_year - current_year.rem_euclid(100) + i64::from(year);
+    if year > current_year + 50 {
+        year -= 100;
+    }
+    year
+}
+
+fn parse_month(value: &str) -> Option<u32> {
+    let position = MONTH_NAMES.iter().position(|m| *m == value)?;
+    u32::try_from(position).ok().map(|m| m + 1)
+}
+
+/// Parses `HH:MM:SS` into a number of seconds since midnight
+fn parse_time(value: &str) -> Option<u32> {
+    let mut parts = value.split(':');
+    let hours = parse_digits(parts.next()?, 2, 2)?;
+    let minutes = parse_digits(parts.next()?, 2, 2)?;
+    let seconds = parse_digits(parts.next()?, 2, 2)?;
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+fn parse_digits(value: &str, min_len: usize, max_len: usize) -> Option<u32> {
+    if !(min_len..=max_len).contains(&value.len()) || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn validate_date(year: i64, month: u32, day: u32, time: u32) -> Option<(i64, u32, u32, u32)> {
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    (1..=days_in_month)
+        .contains(&day)
+        .then_some((year, month, day, time))
+}
+
+/// Number of days since 1970-01-01 of a proleptic Gregorian date
+///
+/// Algorithm from <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Proleptic Gregorian date from a number of days since 1970-01-01
+///
+/// Algorithm from <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = u32::try_from(day_of_year - (153 * month_index + 2) / 5 + 1).unwrap();
+    let month = u32::try_from(if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    })
+    .unwrap();
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &'static str) -> Option<SystemTime> {
+        parse_http_date(&HeaderValue::try_from(value).unwrap())
+    }
+
+    #[test]
+    fn parse_all_formats() {
+        let expected = Some(UNIX_EPOCH + Duration::from_secs(784_111_777));
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+        assert_eq!(parse("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+        assert_eq!(parse("Sun Nov  6 08:49:37 1994"), expected);
+    }
+
+    #[test]
+    fn parse_invalid_dates() {
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse("Sun, 6 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun, 31 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun, 06 Nov 1994 24:49:37 GMT"), None);
+        assert_eq!(parse("Sun, 29 Feb 1900 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun Nov  6 08:49:37 1994 GMT"), None);
+        assert_eq!(parse("foo"), None);
+    }
+
+    #[test]
+    fn format_round_trip() {
+        for value in [
+            "Thu, 01 Jan 1970 00:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Tue, 29 Feb 2000 23:59:59 GMT",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+            "Fri, 31 Dec 9999 23:59:59 GMT",
+        ] {
+            assert_eq!(
+                format_http_date(parse(value).unwrap()).as_ref(),
+                value.as_bytes()
+            );
+        }
+    }
+}
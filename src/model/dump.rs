@@ -0,0 +1,49 @@
+//! Rendering of requests and responses as they are written on the wire, for debugging purposes.
+//!
+//! Nothing is sent and the bodies are not consumed:
+//! bodies stored in memory are rendered as is and the other ones are replaced by a `<streamed body>` placeholder.
+//! Invalid UTF-8 sequences are replaced by `U+FFFD REPLACEMENT CHARACTER`.
+
+use crate::io::{dump_request, dump_response};
+use crate::model::{Request, Response};
+use std::io::Result;
+
+/// Renders the request as it is encoded by the client.
+///
+/// The headers added by the client itself (`Connection`, `User-Agent`, `Accept-Encoding`...) are not included.
+///
+/// ```
+/// use oxhttp::model::dump::request_to_string;
+/// use oxhttp::model::{HeaderName, Method, Request};
+///
+/// let request = Request::builder(Method::POST, "http://example.com/foo?bar".parse()?)
+///     .with_header(HeaderName::CONTENT_TYPE, "text/plain")?
+///     .with_body("baz");
+/// assert_eq!(
+///     request_to_string(&request)?,
+///     "POST /foo?bar HTTP/1.1\r\nhost: example.com\r\ncontent-type: text/plain\r\ncontent-length: 3\r\n\r\nbaz"
+/// );
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn request_to_string(request: &Request) -> Result<String> {
+    Ok(String::from_utf8_lossy(&dump_request(request)?).into_owned())
+}
+
+/// Renders the response as it is encoded by the server.
+///
+/// The headers added by the server itself (`Server`, `Connection`, `Keep-Alive`...) are not included.
+///
+/// ```
+/// use oxhttp::model::dump::response_to_string;
+/// use oxhttp::model::{Body, Response, Status};
+///
+/// let response = Response::builder(Status::OK).with_body(Body::from_read(b"foo".as_slice()));
+/// assert_eq!(
+///     response_to_string(&response)?,
+///     "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n<streamed body>"
+/// );
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn response_to_string(response: &Response) -> Result<String> {
+    Ok(String::from_utf8_lossy(&dump_response(response)?).into_owned())
+}
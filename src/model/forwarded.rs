@@ -0,0 +1,170 @@
+//! Client IP address resolution for servers behind proxies using the [`Forwarded`](https://www.rfc-editor.org/rfc/rfc7239) and `X-Forwarded-For` headers.
+
+use crate::model::{HeaderName, Headers};
+use std::net::{IpAddr, SocketAddr};
+
+/// Returns the IP address of the client that sent the request.
+///
+/// The chain of addresses is built from the [`Forwarded`](https://www.rfc-editor.org/rfc/rfc7239) header `for` parameters,
+/// or from the legacy `X-Forwarded-For` header if there is no `Forwarded` header, followed by the address of the TCP `peer`.
+/// It is walked from the end while the addresses are the ones of `trusted_proxies`.
+/// The first address that is not a trusted proxy is returned.
+/// Entries added before reaching an untrusted address are ignored because they might have been spoofed by the client.
+///
+/// If an entry is not an IP address (e.g. `unknown` or an obfuscated identifier), the address of the proxy that added it is returned.
+///
+/// ```
+/// use oxhttp::model::forwarded::client_ip;
+/// use oxhttp::model::{HeaderName, Headers};
+/// use std::net::{IpAddr, SocketAddr};
+///
+/// let proxy = IpAddr::from([10, 0, 0, 1]);
+/// let mut headers = Headers::new();
+/// headers.set(HeaderName::try_from("forwarded")?, "for=192.0.2.60;proto=http".parse()?);
+/// assert_eq!(
+///     client_ip(&headers, SocketAddr::new(proxy, 4321), &[proxy]),
+///     IpAddr::from([192, 0, 2, 60])
+/// );
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn client_ip(headers: &Headers, peer: SocketAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut current = peer.ip();
+    if !trusted_proxies.contains(&current) {
+        return current;
+    }
+    let entries = if let Some(forwarded) = headers
+        .get(&HeaderName::new_unchecked("forwarded"))
+        .and_then(|v| v.to_str().ok())
+    {
+        forwarded.split(',').map(parse_forwarded_element).collect()
+    } else if let Some(forwarded_for) = headers
+        .get(&HeaderName::new_unchecked("x-forwarded-for"))
+        .and_then(|v| v.to_str().ok())
+    {
+        forwarded_for.split(',').map(parse_node).collect()
+    } else {
+        Vec::new()
+    };
+    for entry in entries.into_iter().rev() {
+        let Some(ip) = entry else {
+            return current; // We do not know who is behind the proxy
+        };
+        current = ip;
+        if !trusted_proxies.contains(&current) {
+            return current;
+        }
+    }
+    current
+}
+
+/// Returns the `for` parameter of a `Forwarded` element
+fn parse_forwarded_element(element: &str) -> Option<IpAddr> {
+    element.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| parse_node(value.trim().trim_matches('"')))?
+    })
+}
+
+/// Parses a node identifier: an IP address, optionally with a port and IPv6 addresses between brackets
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Ok(address) = node.parse::<SocketAddr>() {
+        return Some(address.ip());
+    }
+    node.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::HeaderValue;
+    use std::net::Ipv4Addr;
+
+    const PROXY_1: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const PROXY_2: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    fn headers(name: &'static str, value: &'static str) -> Headers {
+        let mut headers = Headers::new();
+        headers.set(
+            HeaderName::try_from(name).unwrap(),
+            HeaderValue::try_from(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn client_ip_single_proxy() {
+        let peer = SocketAddr::new(PROXY_1, 1234);
+        assert_eq!(
+            client_ip(&headers("x-forwarded-for", "203.0.113.7"), peer, &[PROXY_1]),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            client_ip(
+                &headers("forwarded", "for=\"[2001:db8:cafe::17]:4711\""),
+                peer,
+                &[PROXY_1]
+            ),
+            "2001:db8:cafe::17".parse::<IpAddr>().unwrap()
+        );
+        // No header
+        assert_eq!(client_ip(&Headers::new(), peer, &[PROXY_1]), PROXY_1);
+    }
+
+    #[test]
+    fn client_ip_multiple_proxies() {
+        let peer = SocketAddr::new(PROXY_2, 1234);
+        assert_eq!(
+            client_ip(
+                &headers("x-forwarded-for", "203.0.113.7, 10.0.0.1"),
+                peer,
+                &[PROXY_1, PROXY_2]
+            ),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            client_ip(
+                &headers(
+                    "forwarded",
+                    "for=203.0.113.7;proto=https, for=\"10.0.0.1:8080\";by=10.0.0.2"
+                ),
+                peer,
+                &[PROXY_1, PROXY_2]
+            ),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        // Unknown entry: we stop at the proxy that added it
+        assert_eq!(
+            client_ip(
+                &headers("forwarded", "for=unknown, for=10.0.0.1"),
+                peer,
+                &[PROXY_1, PROXY_2]
+            ),
+            PROXY_1
+        );
+    }
+
+    #[test]
+    fn client_ip_spoofed_entries() {
+        // The client itself added a fake entry before the trusted proxy
+        assert_eq!(
+            client_ip(
+                &headers("x-forwarded-for", "10.0.0.2, 203.0.113.7"),
+                SocketAddr::new(PROXY_1, 1234),
+                &[PROXY_1, PROXY_2]
+            ),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        // The peer is not a trusted proxy: the headers are ignored
+        let peer: SocketAddr = "198.51.100.1:1234".parse().unwrap();
+        assert_eq!(
+            client_ip(&headers("forwarded", "for=203.0.113.7"), peer, &[PROXY_1]),
+            peer.ip()
+        );
+    }
+}
@@ -53,6 +53,30 @@ impl Headers {
         self.0.remove(name);
     }
 
+    /// Keeps only the headers for which `f` returns `true`.
+    ///
+    /// ```
+    /// use oxhttp::model::{Headers, HeaderName};
+    ///
+    /// let mut headers = Headers::new();
+    /// headers.append(HeaderName::ACCEPT, "text/plain".parse()?);
+    /// headers.append(HeaderName::CONNECTION, "close".parse()?);
+    /// headers.retain(|name, _| *name != HeaderName::CONNECTION);
+    /// assert!(headers.contains(&HeaderName::ACCEPT));
+    /// assert!(!headers.contains(&HeaderName::CONNECTION));
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn retain(&mut self, mut f: impl FnMut(&HeaderName, &HeaderValue) -> bool) {
+        self.0.retain(|name, value| f(name, value));
+    }
+
+    /// Removes all headers from the list.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
     /// Get an header value(s) from the list.
     #[inline]
     pub fn get(&self, name: &HeaderName) -> Option<&HeaderValue> {
@@ -72,6 +96,26 @@ impl Headers {
         self.0.insert(name, value);
     }
 
+    /// Sets all the given headers in the list.
+    ///
+    /// Unlike [`extend`](Extend::extend) that [appends](Self::append) the values,
+    /// it overrides the existing value(s) for the same headers.
+    /// If a header is given multiple times, the last value is kept.
+    ///
+    /// ```
+    /// use oxhttp::model::{Headers, HeaderName};
+    ///
+    /// let mut headers = Headers::new();
+    /// headers.append(HeaderName::ACCEPT, "text/plain".parse()?);
+    /// headers.set_all([(HeaderName::ACCEPT, "text/html".parse()?)]);
+    /// assert_eq!(headers.get(&HeaderName::ACCEPT).unwrap().as_ref(), b"text/html");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn set_all(&mut self, headers: impl IntoIterator<Item = (HeaderName, HeaderValue)>) {
+        self.0.extend(headers);
+    }
+
     #[inline]
     pub fn iter(&self) -> Iter<'_> {
         Iter(self.0.iter())
@@ -99,6 +143,40 @@ impl IntoIterator for Headers {
     }
 }
 
+/// Builds the list by [appending](Headers::append) the headers: the values of the same header are combined.
+///
+/// ```
+/// use oxhttp::model::{Headers, HeaderName};
+///
+/// let headers = [
+///     (HeaderName::ACCEPT_LANGUAGE, "en".parse()?),
+///     (HeaderName::ACCEPT, "text/plain".parse()?),
+///     (HeaderName::ACCEPT_LANGUAGE, "fr".parse()?),
+/// ]
+/// .into_iter()
+/// .collect::<Headers>();
+/// assert_eq!(headers.get(&HeaderName::ACCEPT_LANGUAGE).unwrap().as_ref(), b"en, fr");
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+impl FromIterator<(HeaderName, HeaderValue)> for Headers {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (HeaderName, HeaderValue)>>(iter: I) -> Self {
+        let mut headers = Self::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+/// [Appends](Headers::append) the headers to the list, see [`Headers::set_all`] to override the existing values instead.
+impl Extend<(HeaderName, HeaderValue)> for Headers {
+    #[inline]
+    fn extend<I: IntoIterator<Item = (HeaderName, HeaderValue)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
 impl<'a> IntoIterator for &'a Headers {
     type Item = (&'a HeaderName, &'a HeaderValue);
     type IntoIter = Iter<'a>;
@@ -339,6 +417,58 @@ impl HeaderValue {
     pub fn to_str(&self) -> Result<&str, Utf8Error> {
         str::from_utf8(self)
     }
+
+    /// The value without its leading and trailing [whitespaces](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#whitespace) (spaces and horizontal tabs).
+    ///
+    /// The inner whitespaces are kept.
+    ///
+    /// ```
+    /// use oxhttp::model::HeaderValue;
+    /// use std::str::FromStr;
+    ///
+    /// assert_eq!(HeaderValue::from_str("foo bar")?.trim(), b"foo bar");
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn trim(&self) -> &[u8] {
+        trim_whitespaces(self)
+    }
+
+    /// Checks if the [trimmed](Self::trim) value is equal to `other` ignoring ASCII case.
+    ///
+    /// ```
+    /// use oxhttp::model::HeaderValue;
+    /// use std::str::FromStr;
+    ///
+    /// assert!(HeaderValue::from_str("Chunked")?.trimmed_eq_ignore_ascii_case(b"chunked"));
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn trimmed_eq_ignore_ascii_case(&self, other: &[u8]) -> bool {
+        self.trim().eq_ignore_ascii_case(other)
+    }
+
+    /// Checks if the value is a comma-separated list containing `token` ignoring ASCII case.
+    pub(crate) fn contains_token(&self, token: &[u8]) -> bool {
+        self.split(|b| *b == b',')
+            .any(|element| trim_whitespaces(element).eq_ignore_ascii_case(token))
+    }
+
+    /// Checks if the [trimmed](Self::trim) value starts with `prefix` ignoring ASCII case.
+    ///
+    /// ```
+    /// use oxhttp::model::HeaderValue;
+    /// use std::str::FromStr;
+    ///
+    /// assert!(HeaderValue::from_str("Bearer foo")?.starts_with_ignore_ascii_case(b"bearer "));
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn starts_with_ignore_ascii_case(&self, prefix: &[u8]) -> bool {
+        self.trim()
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    }
 }
 impl Deref for HeaderValue {
     type Target = [u8];
@@ -480,8 +610,8 @@ impl<'a> Iterator for Iter<'a> {
     }
 
     #[inline]
-    fn last(self) -> Option<(&'a HeaderName, &'a HeaderValue)> {
-        self.0.last()
+    fn last(mut self) -> Option<(&'a HeaderName, &'a HeaderValue)> {
+        self.0.next_back()
     }
 }
 
@@ -516,8 +646,8 @@ impl Iterator for IntoIter {
     }
 
     #[inline]
-    fn last(self) -> Option<(HeaderName, HeaderValue)> {
-        self.0.last()
+    fn last(mut self) -> Option<(HeaderName, HeaderValue)> {
+        self.0.next_back()
     }
 }
 
@@ -583,10 +713,108 @@ impl From<Infallible> for InvalidHeader {
     }
 }
 
+fn trim_whitespaces(value: &[u8]) -> &[u8] {
+    let is_whitespace = |b: &u8| *b == b' ' || *b == b'\t';
+    let start = value
+        .iter()
+        .position(|b| !is_whitespace(b))
+        .unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|b| !is_whitespace(b))
+        .map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn headers_from_iter_and_extend() {
+        let value = |v: &'static str| HeaderValue::new_unchecked(v.as_bytes());
+        let mut headers = vec![
+            (HeaderName::ACCEPT, value("text/plain")),
+            (HeaderName::USER_AGENT, value("foo")),
+            (HeaderName::ACCEPT, value("text/html")),
+        ]
+        .into_iter()
+        .collect::<Headers>();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(
+            headers.get(&HeaderName::ACCEPT),
+            Some(&value("text/plain, text/html"))
+        );
+
+        headers.extend([
+            (HeaderName::ACCEPT, value("*/*")),
+            (HeaderName::CONTENT_TYPE, value("text/plain")),
+        ]);
+        assert_eq!(
+            headers.get(&HeaderName::ACCEPT),
+            Some(&value("text/plain, text/html, */*"))
+        );
+        assert_eq!(headers.get(&HeaderName::USER_AGENT), Some(&value("foo")));
+        assert_eq!(
+            headers.get(&HeaderName::CONTENT_TYPE),
+            Some(&value("text/plain"))
+        );
+
+        headers.set_all([
+            (HeaderName::ACCEPT, value("text/plain")),
+            (HeaderName::USER_AGENT, value("bar")),
+            (HeaderName::USER_AGENT, value("baz")),
+        ]);
+        assert_eq!(headers.get(&HeaderName::ACCEPT), Some(&value("text/plain")));
+        assert_eq!(headers.get(&HeaderName::USER_AGENT), Some(&value("baz")));
+        assert_eq!(headers.len(), 3);
+    }
+
+    #[test]
+    fn header_value_trim() {
+        for (value, expected) in [
+            ("foo", "foo"),
+            (" foo\t", "foo"),
+            ("\t foo bar  ", "foo bar"),
+            ("  ", ""),
+            ("", ""),
+        ] {
+            assert_eq!(
+                HeaderValue::new_unchecked(value.as_bytes()).trim(),
+                expected.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn header_value_trimmed_eq_ignore_ascii_case() {
+        let value = HeaderValue::new_unchecked(" Keep-Alive\t".as_bytes());
+        assert!(value.trimmed_eq_ignore_ascii_case(b"keep-alive"));
+        assert!(value.trimmed_eq_ignore_ascii_case(b"KEEP-ALIVE"));
+        assert!(!value.trimmed_eq_ignore_ascii_case(b"keep"));
+        assert!(!value.trimmed_eq_ignore_ascii_case(b" keep-alive"));
+        // The slice method is not trimming
+        assert!(!value.eq_ignore_ascii_case(b"keep-alive"));
+        assert!(value.eq_ignore_ascii_case(b" keep-alive\t"));
+    }
+
+    #[test]
+    fn header_value_contains_token() {
+        let value = HeaderValue::new_unchecked("Keep-Alive, TE".as_bytes());
+        assert!(value.contains_token(b"keep-alive"));
+        assert!(value.contains_token(b"te"));
+        assert!(!value.contains_token(b"close"));
+    }
+
+    #[test]
+    fn header_value_starts_with_ignore_ascii_case() {
+        let value = HeaderValue::new_unchecked("  Bearer abc ".as_bytes());
+        assert!(value.starts_with_ignore_ascii_case(b"bearer"));
+        assert!(value.starts_with_ignore_ascii_case(b"BEARER ABC"));
+        assert!(!value.starts_with_ignore_ascii_case(b"basic"));
+        assert!(!value.starts_with_ignore_ascii_case(b"bearer abcd"));
+    }
+
     #[test]
     fn validate_header_name() {
         assert!(HeaderName::from_str("").is_err());
@@ -607,4 +835,33 @@ mod tests {
         assert!(HeaderValue::from_str("ffo\nbar").is_err());
         assert!(HeaderValue::from_str("ffoébar").is_ok());
     }
+
+    #[test]
+    fn headers_retain() {
+        let mut headers = Headers::new();
+        headers.append(HeaderName::CONNECTION, "close".parse().unwrap());
+        headers.append(HeaderName::TE, "trailers".parse().unwrap());
+        headers.append(HeaderName::ACCEPT, "text/plain".parse().unwrap());
+        headers.append(HeaderName::USER_AGENT, "OxHTTP".parse().unwrap());
+        headers.retain(|name, _| *name != HeaderName::CONNECTION && *name != HeaderName::TE);
+        assert_eq!(
+            headers
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>(),
+            [HeaderName::ACCEPT, HeaderName::USER_AGENT]
+        );
+        headers.retain(|_, value| value.as_ref() != b"OxHTTP");
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains(&HeaderName::ACCEPT));
+    }
+
+    #[test]
+    fn headers_clear() {
+        let mut headers = Headers::new();
+        headers.append(HeaderName::ACCEPT, "text/plain".parse().unwrap());
+        headers.append(HeaderName::USER_AGENT, "OxHTTP".parse().unwrap());
+        headers.clear();
+        assert!(headers.is_empty());
+    }
 }
@@ -0,0 +1,82 @@
+//! Removal of the [hop-by-hop](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.connection) headers that must not be forwarded by proxies.
+
+use crate::model::{HeaderName, Headers};
+
+/// The headers that are always hop-by-hop.
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Removes the hop-by-hop headers.
+///
+/// They are the standard hop-by-hop headers (`Connection`, `Keep-Alive`, `Proxy-Authenticate`, `Proxy-Authorization`, `Proxy-Connection`, `TE`, `Trailer`, `Transfer-Encoding` and `Upgrade`)
+/// and the ones listed in the [`Connection`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.connection) header.
+///
+/// ```
+/// use oxhttp::model::hop_by_hop::strip;
+/// use oxhttp::model::{HeaderName, Headers};
+///
+/// let mut headers = Headers::new();
+/// headers.set(HeaderName::CONNECTION, "close".parse()?);
+/// headers.set(HeaderName::ACCEPT, "text/plain".parse()?);
+/// strip(&mut headers);
+/// assert!(!headers.contains(&HeaderName::CONNECTION));
+/// assert!(headers.contains(&HeaderName::ACCEPT));
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn strip(headers: &mut Headers) {
+    let listed = headers
+        .get(&HeaderName::CONNECTION)
+        .and_then(|v| v.to_str().ok())
+        .map(|connection| {
+            connection
+                .split(',')
+                .map(|token| token.trim().to_ascii_lowercase())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    headers.retain(|name, _| {
+        let name = name.as_ref();
+        !HOP_BY_HOP_HEADERS.contains(&name) && !listed.iter().any(|listed| listed == name)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::HeaderValue;
+
+    #[test]
+    fn strip_connection_listed_headers() {
+        let mut headers = Headers::new();
+        for (name, value) in [
+            ("connection", "keep-alive, X-Custom"),
+            ("keep-alive", "timeout=5"),
+            ("transfer-encoding", "chunked"),
+            ("x-custom", "foo"),
+            ("x-other", "bar"),
+            ("accept", "text/plain"),
+        ] {
+            headers.set(
+                HeaderName::try_from(name).unwrap(),
+                HeaderValue::try_from(value).unwrap(),
+            );
+        }
+        strip(&mut headers);
+        assert_eq!(
+            headers
+                .iter()
+                .map(|(name, _)| name.as_ref())
+                .collect::<Vec<_>>(),
+            ["accept", "x-other"]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Parsing of [media types](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#media.type)
+//! like the [`Content-Type`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.content-type) header values.
+
+use crate::model::HeaderValue;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A [media type](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#media.type) like `text/plain; charset=utf-8`.
+///
+/// The type, the subtype and the parameter names are case-insensitive: they are normalized to lowercase.
+/// The parameter values are kept as is, without the quotes and the escapes of quoted values.
+///
+/// ```
+/// use oxhttp::model::mime::MediaType;
+/// use oxhttp::model::HeaderValue;
+///
+/// let content_type = HeaderValue::try_from("Text/HTML; Charset=\"utf-8\"")?;
+/// let media_type = MediaType::parse(&content_type)?;
+/// assert_eq!(media_type.type_(), "text");
+/// assert_eq!(media_type.subtype(), "html");
+/// assert_eq!(media_type.get_param("charset"), Some("utf-8"));
+/// assert_eq!(media_type.to_string(), "text/html; charset=utf-8");
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MediaType {
+    type_: String,
+    subtype: String,
+    parameters: Vec<(String, String)>,
+}
+
+impl MediaType {
+    /// Parses a header value like the one of the `Content-Type` header.
+    #[inline]
+    pub fn parse(value: &HeaderValue) -> Result<Self, InvalidMediaType> {
+        value
+            .to_str()
+            .map_err(|_| InvalidMediaType("Media types should be ASCII".into()))?
+            .parse()
+    }
+
+    /// The type in lowercase (e.g. `text` for `text/plain`).
+    #[inline]
+    pub fn type_(&self) -> &str {
+        &self.type_
+    }
+
+    /// The subtype in lowercase (e.g. `plain` for `text/plain`).
+    #[inline]
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// Returns the value of the first parameter with the given name, compared case-insensitively.
+    #[inline]
+    pub fn get_param(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Iterates over the parameters `(name, value)` in their order of appearance.
+    #[inline]
+    pub fn parameters(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parameters
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Checks if the type and subtype are the given ones, compared case-insensitively.
+    ///
+    /// ```
+    /// use oxhttp::model::mime::MediaType;
+    ///
+    /// let media_type = "application/JSON".parse::<MediaType>()?;
+    /// assert!(media_type.is("Application", "json"));
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn is(&self, type_: &str, subtype: &str) -> bool {
+        self.type_.eq_ignore_ascii_case(type_) && self.subtype.eq_ignore_ascii_case(subtype)
+    }
+}
+
+impl FromStr for MediaType {
+    type Err = InvalidMediaType;
+
+    fn from_str(value: &str) -> Result<Self, InvalidMediaType> {
+        let (essence, mut rest) = value.split_at(value.find(';').unwrap_or(value.len()));
+        let Some((type_, subtype)) = essence.trim_matches(is_ows).split_once('/') else {
+            return Err(InvalidMediaType(format!(
+                "The media type '{value}' should contain a '/'"
+            )));
+        };
+        validate_token(type_, value)?;
+        validate_token(subtype, value)?;
+        let mut parameters = Vec::new();
+        while let Some(after_separator) = rest.strip_prefix(';') {
+            rest = after_separator.trim_start_matches(is_ows);
+            if rest.is_empty() || rest.starts_with(';') {
+                continue; // Empty parameter
+            }
+            let Some((name, after_name)) = rest.split_once('=') else {
+                return Err(InvalidMediaType(format!(
+                    "The media type '{value}' contains a parameter without value"
+                )));
+            };
+            validate_token(name, value)?;
+            let parameter_value = if let Some(quoted) = after_name.strip_prefix('"') {
+                let mut parameter_value = String::new();
+                let mut chars = quoted.char_indices();
+                loop {
+                    let c = match chars.next() {
+                        Some((i, '"')) => {
+                            rest = quoted[i + 1..].trim_start_matches(is_ows);
+                            break;
+                        }
+                        Some((_, '\\')) => chars.next(),
+                        c => c,
+                    };
+                    let Some((_, c)) = c else {
+                        return Err(InvalidMediaType(format!(
+                            "The media type '{value}' contains an unterminated quoted string"
+                        )));
+                    };
+                    parameter_value.push(c);
+                }
+                if !rest.is_empty() && !rest.starts_with(';') {
+                    return Err(InvalidMediaType(format!(
+                        "The media type '{value}' contains characters after a quoted string"
+                    )));
+                }
+                parameter_value
+            } else {
+                let (parameter_value, after_value) =
+                    after_name.split_at(after_name.find(';').unwrap_or(after_name.len()));
+                let parameter_value = parameter_value.trim_end_matches(is_ows);
+                validate_token(parameter_value, value)?;
+                rest = after_value;
+                parameter_value.to_owned()
+            };
+            parameters.push((name.to_ascii_lowercase(), parameter_value));
+        }
+        Ok(Self {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            parameters,
+        })
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+        for (name, value) in &self.parameters {
+            if !value.is_empty() && value.chars().all(is_token_char) {
+                write!(f, "; {name}={value}")?;
+            } else {
+                write!(f, "; {name}=\"")?;
+                for c in value.chars() {
+                    if matches!(c, '"' | '\\') {
+                        f.write_str("\\")?;
+                    }
+                    write!(f, "{c}")?;
+                }
+                f.write_str("\"")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_token(token: &str, value: &str) -> Result<(), InvalidMediaType> {
+    if token.is_empty() {
+        return Err(InvalidMediaType(format!(
+            "The media type '{value}' contains an empty token"
+        )));
+    }
+    if let Some(c) = token.chars().find(|c| !is_token_char(*c)) {
+        return Err(InvalidMediaType(format!(
+            "The character '{c}' is not valid inside of media type '{value}'"
+        )));
+    }
+    Ok(())
+}
+
+fn is_token_char(c: char) -> bool {
+    matches!(c, '!' | '#' | '$' | '%' | '&' | '\'' | '*'
+        | '+' | '-' | '.' | '^' | '_' | '`' | '|' | '~'
+        | '0'..='9' | 'a'..='z' | 'A'..='Z')
+}
+
+fn is_ows(c: char) -> bool {
+    matches!(c, ' ' | '\t')
+}
+
+/// Error returned by [`MediaType::parse`].
+#[derive(Debug, Clone)]
+pub struct InvalidMediaType(String);
+
+impl fmt::Display for InvalidMediaType {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for InvalidMediaType {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_media_types() -> Result<(), InvalidMediaType> {
+        let media_type = "text/plain".parse::<MediaType>()?;
+        assert!(media_type.is("text", "plain"));
+        assert_eq!(media_type.parameters().count(), 0);
+
+        let media_type =
+            "Multipart/Form-Data ; Boundary=\"a \\\"b\\\\\" ;; foo=bar".parse::<MediaType>()?;
+        assert_eq!(media_type.type_(), "multipart");
+        assert_eq!(media_type.subtype(), "form-data");
+        assert_eq!(media_type.get_param("boundary"), Some("a \"b\\"));
+        assert_eq!(media_type.get_param("FOO"), Some("bar"));
+        assert_eq!(media_type.get_param("charset"), None);
+        assert_eq!(
+            media_type.to_string(),
+            "multipart/form-data; boundary=\"a \\\"b\\\\\"; foo=bar"
+        );
+        assert_eq!(media_type.to_string().parse::<MediaType>()?, media_type);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid_media_types() {
+        for value in [
+            "",
+            "text",
+            "text/",
+            "/plain",
+            "text/pl ain",
+            "text/plain; charset",
+            "text/plain; charset=",
+            "text/plain; charset=\"utf-8",
+            "text/plain; charset=\"utf-8\\",
+            "text/plain; charset=\"utf-8\"foo",
+            "text/plain; charset=utf 8",
+            "text/plain; char set=utf-8",
+        ] {
+            assert!(value.parse::<MediaType>().is_err(), "{value}");
+        }
+    }
+}
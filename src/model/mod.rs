@@ -1,17 +1,24 @@
 //! The HTTP model encoded in Rust type system.
 //!
 //! The main entry points are [`Request`] and [`Response`].
+pub mod accept;
 mod body;
+pub mod date;
+pub mod dump;
+pub mod forwarded;
 mod header;
+pub mod hop_by_hop;
 mod method;
+pub mod mime;
+pub mod range;
 mod request;
 mod response;
 mod status;
 
-pub use body::{Body, ChunkedTransferPayload};
+pub use body::{Body, ChunkedTransferPayload, TruncatedBody};
 pub use header::{HeaderName, HeaderValue, Headers, InvalidHeader};
 pub use method::{InvalidMethod, Method};
-pub use request::{Request, RequestBuilder};
+pub use request::{ConnectionContext, Request, RequestBuilder};
 pub use response::{Response, ResponseBuilder};
 pub use status::{InvalidStatus, Status};
 pub use url::Url;
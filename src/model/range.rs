@@ -0,0 +1,634 @@
+//! Partial file serving based on the [`Range`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.range) header.
+
+use crate::model::date::{format_http_date, parse_http_date};
+use crate::model::{Body, HeaderName, HeaderValue, Method, Request, Response, Status};
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default maximal number of ranges of a request, see [`FileResponseOptions::with_max_ranges`].
+const DEFAULT_MAX_RANGES: usize = 16;
+
+/// Options of [`file_response_with_options`].
+///
+/// ```
+/// use oxhttp::model::range::FileResponseOptions;
+/// use oxhttp::model::HeaderValue;
+///
+/// let options = FileResponseOptions::new()
+///     .with_content_type(HeaderValue::try_from("text/plain")?)
+///     .with_max_ranges(4);
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileResponseOptions {
+    content_type: Option<HeaderValue>,
+    max_ranges: usize,
+}
+
+impl FileResponseOptions {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `Content-Type` of the file.
+    ///
+    /// It is sent in the response headers and in the headers of each part of `multipart/byteranges` responses.
+    #[inline]
+    pub fn with_content_type(mut self, content_type: HeaderValue) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// Sets the maximal number of ranges a request can ask for (16 by default).
+    ///
+    /// Requests with more ranges get a `416 Range Not Satisfiable` response.
+    #[inline]
+    pub fn with_max_ranges(mut self, max_ranges: usize) -> Self {
+        self.max_ranges = max_ranges;
+        self
+    }
+}
+
+impl Default for FileResponseOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            content_type: None,
+            max_ranges: DEFAULT_MAX_RANGES,
+        }
+    }
+}
+
+/// Builds the response to `request` with the content of `file`, honoring the request [`Range`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.range) header.
+///
+/// If the request is a `GET` with byte ranges, the response is a `206 Partial Content` with the requested bytes
+/// and a [`Content-Range`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.content-range) header.
+/// If several ranges are requested, the overlapping and adjacent ones are merged and,
+/// if more than one range remains, the response is a streamed [`multipart/byteranges`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#multipart.byteranges) body
+/// whose parts have a `Content-Range` header.
+/// If the ranges are invalid, not satisfiable or too many (16 by default), the response is a `416 Range Not Satisfiable` with a `Content-Range: bytes */{file length}` header.
+/// Otherwise, the response is a `200 OK` with the full file.
+/// It is also the case for ranges in other units than bytes
+/// and if the [`If-Range`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.if-range) header is not the file modification date.
+///
+/// The responses have the `Accept-Ranges: bytes` header and the `Last-Modified` header if the file modification date is known.
+/// Files that are not regular files (e.g. pipes) are always sent fully.
+///
+/// ```
+/// use oxhttp::model::range::file_response;
+/// use oxhttp::model::{HeaderName, Method, Request, Status};
+/// use std::fs::File;
+///
+/// let request = Request::builder(Method::GET, "http://example.com/Cargo.toml".parse()?)
+///     .with_header(HeaderName::RANGE, "bytes=1-7")?
+///     .build();
+/// let response = file_response(&request, File::open("Cargo.toml")?)?;
+/// assert_eq!(response.status(), Status::PARTIAL_CONTENT);
+/// assert!(response
+///     .header(&HeaderName::CONTENT_RANGE)
+///     .unwrap()
+///     .to_str()?
+///     .starts_with("bytes 1-7/"));
+/// assert_eq!(response.into_body().to_string()?, "package");
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn file_response(request: &Request, file: File) -> Result<Response> {
+    file_response_with_options(request, file, &FileResponseOptions::default())
+}
+
+/// Same as [`file_response`] but with the given options.
+///
+/// ```
+/// use oxhttp::model::range::{file_response_with_options, FileResponseOptions};
+/// use oxhttp::model::{HeaderName, HeaderValue, Method, Request, Status};
+/// use std::fs::File;
+///
+/// let request = Request::builder(Method::GET, "http://example.com/Cargo.toml".parse()?)
+///     .with_header(HeaderName::RANGE, "bytes=0-0,2-3")?
+///     .build();
+/// let response = file_response_with_options(
+///     &request,
+///     File::open("Cargo.toml")?,
+///     &FileResponseOptions::new().with_content_type(HeaderValue::try_from("text/plain")?),
+/// )?;
+/// assert_eq!(response.status(), Status::PARTIAL_CONTENT);
+/// assert!(response
+///     .header(&HeaderName::CONTENT_TYPE)
+///     .unwrap()
+///     .to_str()?
+///     .starts_with("multipart/byteranges; boundary="));
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+pub fn file_response_with_options(
+    request: &Request,
+    file: File,
+    options: &FileResponseOptions,
+) -> Result<Response> {
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        let mut response = Response::builder(Status::OK);
+        if let Some(content_type) = &options.content_type {
+            response
+                .headers_mut()
+                .set(HeaderName::CONTENT_TYPE, content_type.clone());
+        }
+        return Ok(response.with_body(Body::from_file(file)?));
+    }
+    let len = metadata.len();
+    let last_modified = metadata.modified().ok().map(format_http_date);
+    let range = match request.header(&HeaderName::RANGE) {
+        Some(range)
+            if *request.method() == Method::GET
+                && if_range_matches(request, last_modified.as_ref()) =>
+        {
+            parse_byte_range(range, len, options.max_ranges)
+        }
+        _ => ByteRange::Ignored,
+    };
+    let (mut response, body) = match range {
+        ByteRange::Satisfiable(ranges) if ranges.len() > 1 => {
+            let boundary = generate_boundary();
+            let mut response = Response::builder(Status::PARTIAL_CONTENT);
+            response.headers_mut().set(
+                HeaderName::CONTENT_TYPE,
+                HeaderValue::new_unchecked(
+                    format!("multipart/byteranges; boundary={boundary}").into_bytes(),
+                ),
+            );
+            let body = MultipartByteRanges::new(
+                file,
+                &ranges,
+                len,
+                &boundary,
+                options.content_type.as_ref(),
+            )
+            .into_body();
+            (response, body)
+        }
+        ByteRange::Satisfiable(ranges) => {
+            let range = &ranges[0];
+            let mut response = Response::builder(Status::PARTIAL_CONTENT);
+            response.headers_mut().set(
+                HeaderName::CONTENT_RANGE,
+                content_range(&format!("{}-{}/{len}", range.start, range.end - 1)),
+            );
+            if let Some(content_type) = &options.content_type {
+                response
+                    .headers_mut()
+                    .set(HeaderName::CONTENT_TYPE, content_type.clone());
+            }
+            let body = Body::from_file_range(file, range.start, range.end)?;
+            (response, body)
+        }
+        ByteRange::NotSatisfiable => {
+            let mut response = Response::builder(Status::RANGE_NOT_SATISFIABLE);
+            response.headers_mut().set(
+                HeaderName::CONTENT_RANGE,
+                content_range(&format!("*/{len}")),
+            );
+            (response, Body::default())
+        }
+        ByteRange::Ignored => {
+            let mut response = Response::builder(Status::OK);
+            if let Some(content_type) = &options.content_type {
+                response
+                    .headers_mut()
+                    .set(HeaderName::CONTENT_TYPE, content_type.clone());
+            }
+            (response, Body::from_file(file)?)
+        }
+    };
+    response.headers_mut().set(
+        HeaderName::ACCEPT_RANGES,
+        HeaderValue::new_unchecked("bytes".as_bytes()),
+    );
+    if let Some(last_modified) = last_modified {
+        response
+            .headers_mut()
+            .set(HeaderName::LAST_MODIFIED, last_modified);
+    }
+    Ok(response.with_body(body))
+}
+
+enum ByteRange {
+    /// Sorted ranges that do not overlap and are not adjacent
+    Satisfiable(Vec<Range<u64>>),
+    NotSatisfiable,
+    /// The full content must be sent
+    Ignored,
+}
+
+/// Parses a `Range` header value for a content of `len` bytes
+fn parse_byte_range(value: &HeaderValue, len: u64, max_ranges: usize) -> ByteRange {
+    let Ok(value) = value.to_str() else {
+        return ByteRange::NotSatisfiable;
+    };
+    let Some((unit, ranges)) = value.trim().split_once('=') else {
+        return ByteRange::NotSatisfiable;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return ByteRange::Ignored;
+    }
+    let ranges = ranges
+        .split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .collect::<Vec<_>>();
+    // Many ranges are a denial of service vector
+    if ranges.is_empty() || ranges.len() > max_ranges {
+        return ByteRange::NotSatisfiable;
+    }
+    let mut satisfiable = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match parse_single_byte_range(range, len) {
+            Some(Some(range)) => satisfiable.push(range),
+            // The unsatisfiable ranges are ignored if another range is satisfiable
+            Some(None) => (),
+            None => return ByteRange::NotSatisfiable,
+        }
+    }
+    // We merge the overlapping and adjacent ranges
+    satisfiable.sort_unstable_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(satisfiable.len());
+    for range in satisfiable {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    if merged.is_empty() {
+        return ByteRange::NotSatisfiable;
+    }
+    ByteRange::Satisfiable(merged)
+}
+
+/// Parses a single range like `0-99` or `-100`.
+///
+/// Returns `None` if the range is invalid and `Some(None)` if it is not satisfiable.
+fn parse_single_byte_range(range: &str, len: u64) -> Option<Option<Range<u64>>> {
+    let (first, last) = range.split_once('-')?;
+    if first.is_empty() {
+        // Suffix range: the last bytes
+        let suffix = parse_position(last)?;
+        return Some((suffix > 0 && len > 0).then(|| len.saturating_sub(suffix)..len));
+    }
+    let first = parse_position(first)?;
+    let end = if last.is_empty() {
+        len
+    } else {
+        let last = parse_position(last).filter(|last| *last >= first)?;
+        last.saturating_add(1).min(len)
+    };
+    Some((first < len).then_some(first..end))
+}
+
+fn parse_position(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Checks that the `If-Range` header, if any, is the file modification date
+fn if_range_matches(request: &Request, last_modified: Option<&HeaderValue>) -> bool {
+    let Some(if_range) = request.header(&HeaderName::IF_RANGE) else {
+        return true;
+    };
+    // Entity tags are not supported
+    last_modified.is_some_and(|last_modified| {
+        parse_http_date(if_range).is_some_and(|date| Some(date) == parse_http_date(last_modified))
+    })
+}
+
+fn content_range(range: &str) -> HeaderValue {
+    HeaderValue::new_unchecked(format!("bytes {range}").into_bytes())
+}
+
+/// Generates a random `multipart/byteranges` boundary
+fn generate_boundary() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos()),
+    );
+    let first = hasher.finish();
+    hasher.write_u8(0);
+    format!("{first:016x}{:016x}", hasher.finish())
+}
+
+/// Streams the `multipart/byteranges` body of the given file ranges.
+struct MultipartByteRanges {
+    file: File,
+    /// The head of each part (delimiter and headers) and its range
+    parts: std::vec::IntoIter<(Vec<u8>, Range<u64>)>,
+    close_delimiter: Option<Vec<u8>>,
+    /// The bytes to write before the next file bytes
+    current_head: Cursor<Vec<u8>>,
+    /// The number of bytes of the current range still to be written
+    remaining: u64,
+}
+
+impl MultipartByteRanges {
+    fn new(
+        file: File,
+        ranges: &[Range<u64>],
+        len: u64,
+        boundary: &str,
+        content_type: Option<&HeaderValue>,
+    ) -> Self {
+        let parts = ranges
+            .iter()
+            .enumerate()
+            .map(|(i, range)| {
+                let mut head = Vec::new();
+                if i > 0 {
+                    head.extend_from_slice(b"\r\n");
+                }
+                head.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+                if let Some(content_type) = content_type {
+                    head.extend_from_slice(b"content-type: ");
+                    head.extend_from_slice(content_type);
+                    head.extend_from_slice(b"\r\n");
+                }
+                head.extend_from_slice(
+                    format!(
+                        "content-range: bytes {}-{}/{len}\r\n\r\n",
+                        range.start,
+                        range.end - 1
+                    )
+                    .as_bytes(),
+                );
+                (head, range.clone())
+            })
+            .collect::<Vec<_>>();
+        Self {
+            file,
+            parts: parts.into_iter(),
+            close_delimiter: Some(format!("\r\n--{boundary}--\r\n").into_bytes()),
+            current_head: Cursor::new(Vec::new()),
+            remaining: 0,
+        }
+    }
+
+    fn into_body(self) -> Body {
+        let len = self
+            .parts
+            .as_slice()
+            .iter()
+            .map(|(head, range)| head.len() as u64 + range.end - range.start)
+            .sum::<u64>()
+            + self.close_delimiter.as_ref().map_or(0, |d| d.len() as u64);
+        Body::from_read_and_len(self, len)
+    }
+}
+
+impl Read for MultipartByteRanges {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let read = self.current_head.read(buf)?;
+            if read > 0 {
+                return Ok(read);
+            }
+            if self.remaining > 0 {
+                let max_len = usize::try_from(self.remaining)
+                    .unwrap_or(usize::MAX)
+                    .min(buf.len());
+                let read = self.file.read(&mut buf[..max_len])?;
+                if read == 0 {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionAborted,
+                        "The file has been truncated while being read",
+                    ));
+                }
+                self.remaining -= read as u64;
+                return Ok(read);
+            }
+            if let Some((head, range)) = self.parts.next() {
+                self.file.seek(SeekFrom::Start(range.start))?;
+                self.current_head = Cursor::new(head);
+                self.remaining = range.end - range.start;
+            } else if let Some(close_delimiter) = self.close_delimiter.take() {
+                self.current_head = Cursor::new(close_delimiter);
+            } else {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::mime::MediaType;
+
+    /// Returns the satisfiable ranges as (start, end) pairs
+    fn byte_range(value: &'static str, len: u64) -> Option<Option<Vec<(u64, u64)>>> {
+        match parse_byte_range(&HeaderValue::try_from(value).unwrap(), len, 2) {
+            ByteRange::Satisfiable(ranges) => Some(Some(
+                ranges
+                    .into_iter()
+                    .map(|range| (range.start, range.end))
+                    .collect(),
+            )),
+            ByteRange::NotSatisfiable => Some(None),
+            ByteRange::Ignored => None,
+        }
+    }
+
+    #[test]
+    fn parse_byte_ranges() {
+        assert_eq!(byte_range("bytes=0-3", 10), Some(Some(vec![(0, 4)])));
+        assert_eq!(byte_range("Bytes = 2-", 10), Some(Some(vec![(2, 10)])));
+        assert_eq!(byte_range("bytes=5-100", 10), Some(Some(vec![(5, 10)])));
+        assert_eq!(byte_range("bytes=-3", 10), Some(Some(vec![(7, 10)])));
+        assert_eq!(byte_range("bytes=-30", 10), Some(Some(vec![(0, 10)])));
+        assert_eq!(byte_range("bytes=9-9", 10), Some(Some(vec![(9, 10)])));
+        for unsatisfiable in [
+            "bytes=10-",
+            "bytes=3-2",
+            "bytes=-0",
+            "bytes=a-2",
+            "bytes=+1-2",
+            "bytes=1",
+            "bytes=",
+            "bytes",
+        ] {
+            assert_eq!(byte_range(unsatisfiable, 10), Some(None), "{unsatisfiable}");
+        }
+        assert_eq!(byte_range("bytes=-1", 0), Some(None));
+        assert_eq!(byte_range("items=0-3", 10), None);
+        // Multiple ranges
+        assert_eq!(
+            byte_range("bytes=4-5, 0-1", 10),
+            Some(Some(vec![(0, 2), (4, 6)]))
+        );
+        assert_eq!(byte_range("bytes=0-4,2-6", 10), Some(Some(vec![(0, 7)])));
+        assert_eq!(byte_range("bytes=0-3,4-5", 10), Some(Some(vec![(0, 6)])));
+        assert_eq!(byte_range("bytes=-2,0-", 10), Some(Some(vec![(0, 10)])));
+        assert_eq!(byte_range("bytes=0-1,20-", 10), Some(Some(vec![(0, 2)])));
+        assert_eq!(byte_range("bytes=20-,30-", 10), Some(None));
+        assert_eq!(byte_range("bytes=0-1,a", 10), Some(None));
+        assert_eq!(byte_range("bytes=0-0,2-2,4-4", 10), Some(None));
+    }
+
+    #[test]
+    fn file_responses() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("oxhttp-range-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789")?;
+        let last_modified = format_http_date(std::fs::metadata(&path)?.modified()?);
+        let request = |method: Method, headers: &[(HeaderName, &HeaderValue)]| {
+            let mut request = Request::builder(method, "http://example.com".parse().unwrap());
+            for (name, value) in headers {
+                request
+                    .headers_mut()
+                    .set(name.clone(), HeaderValue::clone(value));
+            }
+            request.build()
+        };
+        let range = |value: &'static str| HeaderValue::try_from(value).unwrap();
+        let response = |request: Request| file_response(&request, File::open(&path)?);
+
+        let partial = response(request(
+            Method::GET,
+            &[(HeaderName::RANGE, &range("bytes=2-4"))],
+        ))?;
+        assert_eq!(partial.status(), Status::PARTIAL_CONTENT);
+        assert_eq!(
+            partial.header(&HeaderName::CONTENT_RANGE).unwrap().as_ref(),
+            b"bytes 2-4/10"
+        );
+        assert_eq!(
+            partial.header(&HeaderName::ACCEPT_RANGES).unwrap().as_ref(),
+            b"bytes"
+        );
+        assert_eq!(partial.body().len(), Some(3));
+        assert_eq!(partial.into_body().to_vec()?, b"234");
+
+        let unsatisfiable = response(request(
+            Method::GET,
+            &[(HeaderName::RANGE, &range("bytes=20-"))],
+        ))?;
+        assert_eq!(unsatisfiable.status(), Status::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            unsatisfiable
+                .header(&HeaderName::CONTENT_RANGE)
+                .unwrap()
+                .as_ref(),
+            b"bytes */10"
+        );
+        assert_eq!(unsatisfiable.body().len(), Some(0));
+
+        let full = response(request(Method::GET, &[]))?;
+        assert_eq!(full.status(), Status::OK);
+        assert_eq!(
+            full.header(&HeaderName::LAST_MODIFIED),
+            Some(&last_modified)
+        );
+        assert_eq!(full.into_body().to_vec()?, b"0123456789");
+
+        // Ranges only apply to GET requests
+        let head = response(request(
+            Method::HEAD,
+            &[(HeaderName::RANGE, &range("bytes=2-4"))],
+        ))?;
+        assert_eq!(head.status(), Status::OK);
+
+        // If-Range
+        let matching = response(request(
+            Method::GET,
+            &[
+                (HeaderName::RANGE, &range("bytes=-2")),
+                (HeaderName::IF_RANGE, &last_modified),
+            ],
+        ))?;
+        assert_eq!(matching.into_body().to_vec()?, b"89");
+        let outdated = response(request(
+            Method::GET,
+            &[
+                (HeaderName::RANGE, &range("bytes=-2")),
+                (HeaderName::IF_RANGE, &format_http_date(UNIX_EPOCH)),
+            ],
+        ))?;
+        assert_eq!(outdated.status(), Status::OK);
+        assert_eq!(outdated.into_body().to_vec()?, b"0123456789");
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn multipart_file_response() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("oxhttp-ranges-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789")?;
+        let request = Request::builder(Method::GET, "http://example.com".parse().unwrap())
+            .with_header(HeaderName::RANGE, "bytes=7-,1-2")
+            .unwrap()
+            .build();
+        let response = file_response_with_options(
+            &request,
+            File::open(&path)?,
+            &FileResponseOptions::new()
+                .with_content_type(HeaderValue::try_from("text/plain").unwrap()),
+        )?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(response.status(), Status::PARTIAL_CONTENT);
+        assert!(response.header(&HeaderName::CONTENT_RANGE).is_none());
+        let content_type = MediaType::parse(response.header(&HeaderName::CONTENT_TYPE).unwrap())
+            .map_err(Error::other)?;
+        assert!(content_type.is("multipart", "byteranges"));
+        let boundary = content_type.get_param("boundary").unwrap().to_owned();
+        let body_len = response.body().len();
+        let body = response.into_body().to_string()?;
+        assert_eq!(body_len, Some(body.len() as u64));
+
+        // We parse the multipart body back
+        let body = body
+            .strip_suffix(&format!("\r\n--{boundary}--\r\n"))
+            .unwrap();
+        let parts = body
+            .split(&format!("--{boundary}\r\n"))
+            .skip(1)
+            .map(|part| {
+                let part = part.strip_suffix("\r\n").unwrap_or(part);
+                let (headers, content) = part.split_once("\r\n\r\n").unwrap();
+                (headers.to_owned(), content.to_owned())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            [
+                (
+                    "content-type: text/plain\r\ncontent-range: bytes 1-2/10".to_owned(),
+                    "12".to_owned()
+                ),
+                (
+                    "content-type: text/plain\r\ncontent-range: bytes 7-9/10".to_owned(),
+                    "789".to_owned()
+                )
+            ]
+        );
+
+        // Too many ranges
+        let request = Request::builder(Method::GET, "http://example.com".parse().unwrap())
+            .with_header(HeaderName::RANGE, "bytes=0-0,2-2")
+            .unwrap()
+            .build();
+        std::fs::write(&path, b"0123456789")?;
+        let response = file_response_with_options(
+            &request,
+            File::open(&path)?,
+            &FileResponseOptions::new().with_max_ranges(1),
+        )?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(response.status(), Status::RANGE_NOT_SATISFIABLE);
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::model::header::IntoHeaderName;
 use crate::model::{Body, HeaderName, HeaderValue, Headers, InvalidHeader, Method, Url};
+use std::net::SocketAddr;
 
 /// A HTTP request.
 ///
@@ -22,6 +23,8 @@ pub struct Request {
     url: Url,
     headers: Headers,
     body: Body,
+    connection: Option<ConnectionContext>,
+    is_asterisk_form: bool,
 }
 
 impl Request {
@@ -31,6 +34,7 @@ impl Request {
             method,
             url,
             headers: Headers::new(),
+            is_asterisk_form: false,
         }
     }
 
@@ -44,6 +48,15 @@ impl Request {
         &self.url
     }
 
+    /// If the request targets the whole server instead of a resource (i.e. `OPTIONS * HTTP/1.1`).
+    ///
+    /// The [URL](Self::url) path is then meaningless and is always `/`.
+    /// See [`RequestBuilder::with_asterisk_form`].
+    #[inline]
+    pub fn is_asterisk_form(&self) -> bool {
+        self.is_asterisk_form
+    }
+
     #[inline]
     pub fn headers(&self) -> &Headers {
         &self.headers
@@ -84,6 +97,20 @@ impl Request {
     pub fn into_body(self) -> Body {
         self.body
     }
+
+    /// The connection the request has been received from.
+    ///
+    /// It is only set on the requests received by the server.
+    #[inline]
+    pub fn connection(&self) -> Option<&ConnectionContext> {
+        self.connection.as_ref()
+    }
+
+    #[cfg(feature = "server")]
+    #[inline]
+    pub(crate) fn set_connection(&mut self, connection: ConnectionContext) {
+        self.connection = Some(connection);
+    }
 }
 
 /// Builder for [`Request`]
@@ -91,6 +118,7 @@ pub struct RequestBuilder {
     method: Method,
     url: Url,
     headers: Headers,
+    is_asterisk_form: bool,
 }
 
 impl RequestBuilder {
@@ -104,6 +132,12 @@ impl RequestBuilder {
         &self.url
     }
 
+    /// See [`Request::is_asterisk_form`].
+    #[inline]
+    pub fn is_asterisk_form(&self) -> bool {
+        self.is_asterisk_form
+    }
+
     #[inline]
     pub fn headers(&self) -> &Headers {
         &self.headers
@@ -130,6 +164,25 @@ impl RequestBuilder {
         Ok(self)
     }
 
+    /// Makes the request target the whole server instead of the URL path (i.e. `OPTIONS * HTTP/1.1`).
+    ///
+    /// It is only meaningful with the `OPTIONS` method.
+    ///
+    /// ```
+    /// use oxhttp::model::{Method, Request};
+    ///
+    /// let request = Request::builder(Method::OPTIONS, "http://example.com".parse()?)
+    ///     .with_asterisk_form()
+    ///     .build();
+    /// assert!(request.is_asterisk_form());
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_asterisk_form(mut self) -> Self {
+        self.is_asterisk_form = true;
+        self
+    }
+
     #[inline]
     pub fn with_body(self, body: impl Into<Body>) -> Request {
         Request {
@@ -137,6 +190,8 @@ impl RequestBuilder {
             url: self.url,
             headers: self.headers,
             body: body.into(),
+            connection: None,
+            is_asterisk_form: self.is_asterisk_form,
         }
     }
 
@@ -145,3 +200,35 @@ impl RequestBuilder {
         self.with_body(Body::default())
     }
 }
+
+/// Information about a connection received by the server, see [`Request::connection`].
+///
+/// By default it is built from the TCP connection.
+/// A custom one might be returned by the server connection initialization function,
+/// for example to expose the client address sent by a proxy.
+///
+/// ```
+/// use oxhttp::model::ConnectionContext;
+/// use std::net::{Ipv4Addr, SocketAddr};
+///
+/// let context = ConnectionContext::new(SocketAddr::from((Ipv4Addr::LOCALHOST, 8080)));
+/// assert_eq!(context.peer_addr().port(), 8080);
+/// ```
+#[derive(Debug, Clone)]
+#[allow(missing_copy_implementations)]
+pub struct ConnectionContext {
+    peer_addr: SocketAddr,
+}
+
+impl ConnectionContext {
+    #[inline]
+    pub fn new(peer_addr: SocketAddr) -> Self {
+        Self { peer_addr }
+    }
+
+    /// The address of the client.
+    #[inline]
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+}
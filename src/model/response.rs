@@ -131,3 +131,66 @@ impl ResponseBuilder {
         self.with_body(Body::default())
     }
 }
+
+impl From<String> for Response {
+    /// Builds a `200 OK` response with the string as `text/plain` body.
+    #[inline]
+    fn from(text: String) -> Self {
+        text_response(Status::OK, text)
+    }
+}
+
+impl From<&'static str> for Response {
+    /// Builds a `200 OK` response with the string as `text/plain` body.
+    #[inline]
+    fn from(text: &'static str) -> Self {
+        text_response(Status::OK, text)
+    }
+}
+
+impl From<Status> for Response {
+    /// Builds a response with the given status and an empty body.
+    #[inline]
+    fn from(status: Status) -> Self {
+        Response::builder(status).build()
+    }
+}
+
+impl From<(Status, String)> for Response {
+    /// Builds a response with the given status and the string as `text/plain` body.
+    #[inline]
+    fn from((status, text): (Status, String)) -> Self {
+        text_response(status, text)
+    }
+}
+
+fn text_response(status: Status, text: impl Into<Body>) -> Response {
+    let mut response = Response::builder(status);
+    response.headers_mut().set(
+        HeaderName::CONTENT_TYPE,
+        HeaderValue::new_unchecked("text/plain; charset=utf-8".as_bytes()),
+    );
+    response.with_body(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_status_and_text() -> std::io::Result<()> {
+        let response = Response::from((Status::NOT_FOUND, "not found".to_owned()));
+        assert_eq!(response.status(), Status::NOT_FOUND);
+        assert_eq!(
+            response.header(&HeaderName::CONTENT_TYPE).unwrap().as_ref(),
+            b"text/plain; charset=utf-8"
+        );
+        assert_eq!(response.into_body().to_string()?, "not found");
+
+        let response = Response::from(Status::NO_CONTENT);
+        assert_eq!(response.status(), Status::NO_CONTENT);
+        assert!(response.headers().is_empty());
+        assert_eq!(response.body().len(), Some(0));
+        Ok(())
+    }
+}
@@ -49,6 +49,8 @@ impl Status {
     pub const CONTINUE: Self = Self(100);
     /// [101 Switching Protocols](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.101)
     pub const SWITCHING_PROTOCOLS: Self = Self(101);
+    /// [103 Early Hints](https://www.rfc-editor.org/rfc/rfc8297)
+    pub const EARLY_HINTS: Self = Self(103);
     /// [200 OK](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.200)
     pub const OK: Self = Self(200);
     /// [201 Created](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.201)
@@ -121,6 +123,8 @@ impl Status {
     pub const UNPROCESSABLE_CONTENT: Self = Self(422);
     /// [426 Upgrade Required](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.426)
     pub const UPGRADE_REQUIRED: Self = Self(426);
+    /// [429 Too Many Requests](https://www.rfc-editor.org/rfc/rfc6585#section-4)
+    pub const TOO_MANY_REQUESTS: Self = Self(429);
     /// [500 Internal Server Error](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.500)
     pub const INTERNAL_SERVER_ERROR: Self = Self(500);
     /// [501 Not Implemented](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.501)
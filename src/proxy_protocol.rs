@@ -0,0 +1,255 @@
+use crate::model::ConnectionContext;
+use crate::utils::invalid_data_error;
+use std::io::{Read, Result};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+
+/// The maximal length of a PROXY protocol v1 header including the final CRLF.
+const V1_MAX_HEADER_SIZE: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Version of the [PROXY protocol](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt) header
+/// expected at the start of the connections.
+///
+/// See [`ServerBuilder::with_proxy_protocol`](crate::ServerBuilder::with_proxy_protocol).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProxyProtocolVersion {
+    /// The human-readable header format (e.g. `PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n`).
+    V1,
+    /// The binary header format.
+    V2,
+}
+
+/// Reads the PROXY protocol header at the start of the stream and builds the connection context from it.
+///
+/// Only the header bytes are consumed from the stream.
+/// If the header does not convey a client address (`UNKNOWN` or `LOCAL` connections),
+/// the address of the TCP peer is used.
+pub fn read_proxy_protocol_header(
+    stream: &mut TcpStream,
+    version: ProxyProtocolVersion,
+) -> Result<ConnectionContext> {
+    let source = match version {
+        ProxyProtocolVersion::V1 => read_v1_header(stream)?,
+        ProxyProtocolVersion::V2 => read_v2_header(stream)?,
+    };
+    Ok(ConnectionContext::new(match source {
+        Some(source) => source,
+        None => stream.peer_addr()?,
+    }))
+}
+
+fn read_v1_header(reader: &mut impl Read) -> Result<Option<SocketAddr>> {
+    // We read byte by byte to not consume the beginning of the HTTP request
+    let mut line = Vec::with_capacity(V1_MAX_HEADER_SIZE);
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_HEADER_SIZE {
+            return Err(invalid_data_error(format!(
+                "The PROXY protocol header should fit in {V1_MAX_HEADER_SIZE} bytes"
+            )));
+        }
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid_data_error("The PROXY protocol header is not valid ASCII"))?;
+    let mut parts = line.split(' ');
+    if parts.next() != Some("PROXY") {
+        return Err(invalid_data_error(
+            "The connection does not start with a PROXY protocol v1 header",
+        ));
+    }
+    let is_v4 = match parts.next() {
+        Some("TCP4") => true,
+        Some("TCP6") => false,
+        Some("UNKNOWN") => return Ok(None),
+        _ => {
+            return Err(invalid_data_error(format!(
+                "Unsupported PROXY protocol header: {line}"
+            )))
+        }
+    };
+    let (Some(source), Some(_destination), Some(source_port), Some(_destination_port), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return Err(invalid_data_error(format!(
+            "Invalid PROXY protocol header: {line}"
+        )));
+    };
+    let ip = if is_v4 {
+        source.parse::<Ipv4Addr>().map(Into::into)
+    } else {
+        source.parse::<Ipv6Addr>().map(Into::into)
+    }
+    .map_err(|e| invalid_data_error(format!("Invalid PROXY protocol source address: {e}")))?;
+    let port = source_port
+        .parse::<u16>()
+        .map_err(|e| invalid_data_error(format!("Invalid PROXY protocol source port: {e}")))?;
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+fn read_v2_header(reader: &mut impl Read) -> Result<Option<SocketAddr>> {
+    let mut header = [0; 16];
+    reader.read_exact(&mut header)?;
+    if header[..12] != V2_SIGNATURE[..] {
+        return Err(invalid_data_error(
+            "The connection does not start with a PROXY protocol v2 header",
+        ));
+    }
+    if header[12] >> 4 != 2 {
+        return Err(invalid_data_error(format!(
+            "Unsupported PROXY protocol version {}",
+            header[12] >> 4
+        )));
+    }
+    let command = header[12] & 0x0F;
+    let family = header[13];
+    let mut payload = vec![0; usize::from(u16::from_be_bytes([header[14], header[15]]))];
+    reader.read_exact(&mut payload)?;
+    match command {
+        // LOCAL: the connection has been established by the proxy itself
+        0 => return Ok(None),
+        // PROXY
+        1 => (),
+        _ => {
+            return Err(invalid_data_error(format!(
+                "Unsupported PROXY protocol command {command}"
+            )))
+        }
+    }
+    match family {
+        // TCP over IPv4
+        0x11 => {
+            let Some(addresses) = payload.get(..12) else {
+                return Err(invalid_data_error(
+                    "The PROXY protocol header is too short for IPv4 addresses",
+                ));
+            };
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&addresses[..4]).unwrap());
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // TCP over IPv6
+        0x21 => {
+            let Some(addresses) = payload.get(..36) else {
+                return Err(invalid_data_error(
+                    "The PROXY protocol header is too short for IPv6 addresses",
+                ));
+            };
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&addresses[..16]).unwrap());
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // Unspecified
+        0x00 => Ok(None),
+        _ => Err(invalid_data_error(format!(
+            "Unsupported PROXY protocol address family {family:#04x}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn v1_tcp4() -> Result<()> {
+        let mut reader = Cursor::new(b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\nGET".as_slice());
+        assert_eq!(
+            read_v1_header(&mut reader)?,
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(reader.position(), 42);
+        Ok(())
+    }
+
+    #[test]
+    fn v1_tcp6() -> Result<()> {
+        assert_eq!(
+            read_v1_header(&mut b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n".as_slice())?,
+            Some("[2001:db8::1]:56324".parse().unwrap())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn v1_unknown() -> Result<()> {
+        assert_eq!(
+            read_v1_header(&mut b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n".as_slice())?,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn v1_invalid() {
+        for header in [
+            b"GET / HTTP/1.1\r\n".as_slice(),
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324\r\n",
+            b"PROXY TCP4 2001:db8::1 192.0.2.2 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 65536 443\r\n",
+            b"PROXY UDP4 192.0.2.1 192.0.2.2 56324 443\r\n",
+            &[b'a'; 200],
+        ] {
+            read_v1_header(&mut &*header).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn v2_tcp4() -> Result<()> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0, 15]);
+        header.extend_from_slice(&[192, 0, 2, 1, 192, 0, 2, 2, 0xDC, 0x04, 0x01, 0xBB]);
+        header.extend_from_slice(&[0x04, 0, 0]); // Empty NOOP TLV
+        header.extend_from_slice(b"GET");
+        let mut reader = Cursor::new(header);
+        assert_eq!(
+            read_v2_header(&mut reader)?,
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(reader.position(), 31);
+        Ok(())
+    }
+
+    #[test]
+    fn v2_tcp6() -> Result<()> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        header.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        header.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        header.extend_from_slice(&[0xDC, 0x04, 0x01, 0xBB]);
+        assert_eq!(
+            read_v2_header(&mut header.as_slice())?,
+            Some("[2001:db8::1]:56324".parse().unwrap())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn v2_local() -> Result<()> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(read_v2_header(&mut header.as_slice())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn v2_invalid() {
+        let mut short = V2_SIGNATURE.to_vec();
+        short.extend_from_slice(&[0x21, 0x11, 0, 4, 192, 0, 2, 1]);
+        let mut bad_version = V2_SIGNATURE.to_vec();
+        bad_version.extend_from_slice(&[0x11, 0x11, 0, 0]);
+        for header in [
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n".as_slice(),
+            &short,
+            &bad_version,
+        ] {
+            read_v2_header(&mut &*header).unwrap_err();
+        }
+    }
+}
@@ -1,19 +1,52 @@
-use crate::io::{decode_request_body, decode_request_headers};
-use crate::io::{encode_response, BUFFER_CAPACITY};
+use crate::builder::BuildError;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::io::SendFile;
+use crate::io::{decode_request_body, decode_request_head, ReaderSlot, MAX_HEADER_SIZE};
+use crate::io::{
+    encode_interim_response, encode_response_with_options, FramingHeaderPosition, BUFFER_CAPACITY,
+    DEFAULT_CHUNK_SIZE, MIN_BUFFER_CAPACITY,
+};
 use crate::model::{
-    HeaderName, HeaderValue, InvalidHeader, Request, RequestBuilder, Response, Status,
+    Body, ChunkedTransferPayload, ConnectionContext, HeaderName, HeaderValue, Headers,
+    InvalidHeader, Method, Request, RequestBuilder, Response, Status, Url,
 };
+use crate::proxy_protocol::{read_proxy_protocol_header, ProxyProtocolVersion};
+#[cfg(feature = "native-tls")]
+use native_tls::TlsAcceptor;
+#[cfg(feature = "rustls")]
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::any::Any;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
-use std::io::{copy, sink, BufReader, BufWriter, Error, ErrorKind, Result, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::fs::File;
+use std::io::{copy, sink, BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::fd::AsRawFd;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::ptr;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use std::rc::Rc;
+use std::sync::mpsc::{sync_channel, SyncSender};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread::{Builder, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use url::Host;
+
+/// Maximal number of unread request body bytes drained after the response is built to keep the connection alive.
+///
+/// If more bytes are remaining the connection is closed instead.
+const MAX_DRAINED_BODY_SIZE: u64 = 1024 * 1024;
 
 /// An HTTP server.
 ///
 /// It uses a very simple threading mechanism: a new thread is started on each connection and closed when the client connection is closed.
-/// To avoid crashes it is possible to set an upper bound to the number of concurrent connections using the [`Server::with_max_concurrent_connections`] function.
+/// To avoid crashes it is possible to set an upper bound to the number of concurrent connections using the [`ServerBuilder::with_max_concurrent_connections`] function.
 ///
 /// ```no_run
 /// use std::net::{Ipv4Addr, Ipv6Addr};
@@ -22,19 +55,21 @@ use std::time::Duration;
 /// use std::time::Duration;
 ///
 /// // Builds a new server that returns a 404 everywhere except for "/" where it returns the body 'home'
-/// let mut server = Server::new(|request| {
+/// let server = Server::builder(|request| {
 ///     if request.url().path() == "/" {
 ///         Response::builder(Status::OK).with_body("home")
 ///     } else {
 ///         Response::builder(Status::NOT_FOUND).build()
 ///     }
-/// });
+/// })
 /// // We bind the server to localhost on both IPv4 and v6
-/// server = server.bind((Ipv4Addr::LOCALHOST, 8080)).bind((Ipv6Addr::LOCALHOST, 8080));
+/// .bind((Ipv4Addr::LOCALHOST, 8080))
+/// .bind((Ipv6Addr::LOCALHOST, 8080))
 /// // Raise a timeout error if the client does not respond after 10s.
-/// server = server.with_global_timeout(Duration::from_secs(10));
+/// .with_global_timeout(Duration::from_secs(10))
 /// // Limits the number of concurrent connections to 128.
-/// server = server.with_max_concurrent_connections(128);
+/// .with_max_concurrent_connections(128)
+/// .build()?;
 /// // We spawn the server and block on it
 /// server.spawn()?.join()?;
 /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
@@ -43,21 +78,76 @@ use std::time::Duration;
 pub struct Server {
     on_request: Arc<dyn Fn(&mut Request) -> Response + Send + Sync + 'static>,
     socket_addrs: Vec<SocketAddr>,
+    listeners: Vec<TcpListener>,
+    tcp_backlog: Option<u32>,
     timeout: Option<Duration>,
     server: Option<HeaderValue>,
     max_num_thread: Option<usize>,
+    max_connections_per_listener: Option<usize>,
+    thread_pool_size: Option<usize>,
+    keep_alive_timeout: Option<Duration>,
+    max_requests_per_connection: Option<usize>,
+    cors: Option<CorsConfig>,
+    framing_header_position: FramingHeaderPosition,
+    max_connections_per_ip: Option<usize>,
+    thread_stack_size: Option<usize>,
+    thread_name_prefix: Option<String>,
+    max_header_size: u64,
+    chunk_size: usize,
+    buffer_capacity: usize,
+    body_budget: Option<u64>,
+    connection_init: Option<Arc<ConnectionInit>>,
+    tls: Option<ServerTls>,
+    access_log: Option<Arc<AccessLog>>,
+    on_response: Option<Arc<OnResponse>>,
+    interim_responses: Option<Arc<InterimResponses>>,
+    header_size_warning: Option<(u64, Arc<HeaderSizeWarning>)>,
+    error_renderer: Option<Arc<ErrorRenderer>>,
+    catch_panics: bool,
+    normalize_path: bool,
+    virtual_hosts: HashMap<String, Arc<RequestHandler>>,
 }
 
 impl Server {
     /// Builds the server using the given `on_request` method that builds a `Response` from a given `Request`.
+    ///
+    /// `on_request` may return anything convertible into a [`Response`],
+    /// for example a [`String`] for a `200 OK` text response or a [`Status`] for an empty response.
     #[inline]
-    pub fn new(on_request: impl Fn(&mut Request) -> Response + Send + Sync + 'static) -> Self {
+    pub fn new<R: Into<Response>>(
+        on_request: impl Fn(&mut Request) -> R + Send + Sync + 'static,
+    ) -> Self {
         Self {
-            on_request: Arc::new(on_request),
+            on_request: Arc::new(move |request: &mut Request| on_request(request).into()),
             socket_addrs: Vec::new(),
+            listeners: Vec::new(),
+            tcp_backlog: None,
             timeout: None,
             server: None,
             max_num_thread: None,
+            max_connections_per_listener: None,
+            thread_pool_size: None,
+            keep_alive_timeout: None,
+            max_requests_per_connection: None,
+            cors: None,
+            framing_header_position: FramingHeaderPosition::Last,
+            max_connections_per_ip: None,
+            thread_stack_size: None,
+            thread_name_prefix: None,
+            max_header_size: MAX_HEADER_SIZE,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            buffer_capacity: BUFFER_CAPACITY,
+            body_budget: None,
+            connection_init: None,
+            tls: None,
+            access_log: None,
+            on_response: None,
+            interim_responses: None,
+            header_size_warning: None,
+            error_renderer: None,
+            catch_panics: true,
+            normalize_path: false,
+            virtual_hosts: HashMap::new(),
         }
     }
 
@@ -70,7 +160,28 @@ impl Server {
         self
     }
 
+    /// Ask the server to accept connections from an already bound listener when spawned.
+    ///
+    /// This is useful for socket activation or to bind to port 0 and retrieve the actual address
+    /// with [`ListeningServer::local_addrs`].
+    pub fn listen(mut self, listener: TcpListener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// Builds a server with a custom configuration, validated by [`ServerBuilder::build`].
+    #[inline]
+    pub fn builder<R: Into<Response>>(
+        on_request: impl Fn(&mut Request) -> R + Send + Sync + 'static,
+    ) -> ServerBuilder {
+        ServerBuilder {
+            server: Self::new(on_request),
+            error: None,
+        }
+    }
+
     /// Sets the default value for the [`Server`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.server) header.
+    #[deprecated(note = "Use Server::builder() and ServerBuilder::with_server_name instead")]
     #[inline]
     pub fn with_server_name(
         mut self,
@@ -81,6 +192,7 @@ impl Server {
     }
 
     /// Sets the global timeout value (applies to both read and write).
+    #[deprecated(note = "Use Server::builder() and ServerBuilder::with_global_timeout instead")]
     #[inline]
     pub fn with_global_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = Some(timeout);
@@ -88,303 +200,2543 @@ impl Server {
     }
 
     /// Sets the number maximum number of threads this server can spawn.
+    #[deprecated(
+        note = "Use Server::builder() and ServerBuilder::with_max_concurrent_connections instead"
+    )]
     #[inline]
     pub fn with_max_concurrent_connections(mut self, max_num_thread: usize) -> Self {
         self.max_num_thread = Some(max_num_thread);
         self
     }
 
+    /// Sets the maximal number of concurrent connections from the same IP address.
+    #[deprecated(
+        note = "Use Server::builder() and ServerBuilder::with_max_connections_per_ip instead"
+    )]
+    #[inline]
+    pub fn with_max_connections_per_ip(mut self, max_connections: usize) -> Self {
+        self.max_connections_per_ip = Some(max_connections);
+        self
+    }
+
+    /// Sets how long an idle connection is kept open while waiting for the next request.
+    #[deprecated(note = "Use Server::builder() and ServerBuilder::with_keep_alive_timeout instead")]
+    #[inline]
+    pub fn with_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximal number of requests served on a single connection before closing it.
+    #[deprecated(
+        note = "Use Server::builder() and ServerBuilder::with_max_requests_per_connection instead"
+    )]
+    #[inline]
+    pub fn with_max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.max_requests_per_connection = Some(max_requests);
+        self
+    }
+
+    /// Enables [CORS](https://fetch.spec.whatwg.org/#http-cors-protocol) support.
+    #[deprecated(note = "Use Server::builder() and ServerBuilder::with_cors instead")]
+    #[inline]
+    pub fn with_cors(mut self, cors: CorsConfig) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    /// Writes the framing header (`Content-Length` or `Transfer-Encoding`) just after the status line.
+    #[deprecated(
+        note = "Use Server::builder() and ServerBuilder::with_framing_header_first instead"
+    )]
+    #[inline]
+    pub fn with_framing_header_first(mut self) -> Self {
+        self.framing_header_position = FramingHeaderPosition::First;
+        self
+    }
+
     /// Spawns the server by listening to the given addresses.
     ///
     /// Note that this is not blocking.
     /// To wait for the server to terminate indefinitely, call [`join`](ListeningServer::join) on the result.
     pub fn spawn(self) -> Result<ListeningServer> {
-        let timeout = self.timeout;
-        let thread_limit = self.max_num_thread.map(Semaphore::new);
-        let listener_threads = self.socket_addrs
-                .into_iter()
-                .map(|listener_addr| {
-                    let listener = TcpListener::bind(listener_addr)?;
-                    let thread_name = format!("{}: listener thread of OxHTTP", listener_addr);
-                    let thread_limit = thread_limit.clone();
-                    let on_request = Arc::clone(&self.on_request);
-                    let server = self.server.clone();
-                    Builder::new().name(thread_name).spawn(move || {
-                        for stream in listener.incoming() {
-                            match stream {
-                                Ok(stream) => {
-                                    let peer_addr = match stream.peer_addr() {
-                                        Ok(peer) => peer,
-                                        Err(error) => {
-                                            eprintln!("OxHTTP TCP error when attempting to get the peer address: {error}");
-                                            continue;
-                                        }
-                                    };
-                                    if let Err(error) = stream.set_nodelay(true) {
-                                        eprintln!("OxHTTP TCP error when attempting to set the TCP_NODELAY option: {error}");
-                                    }
-                                    let thread_name = format!("{}: responding thread of OxHTTP", peer_addr);
-                                    let thread_guard = thread_limit.as_ref().map(|s| s.lock());
-                                    let on_request = Arc::clone(&on_request);
-                                    let server = server.clone();
-                                    if let Err(error) = Builder::new().name(thread_name).spawn(
-                                        move || {
-                                            if let Err(error) =
-                                                accept_request(stream, &*on_request, timeout, &server)
-                                            {
-                                                eprintln!(
-                                                    "OxHTTP TCP error when writing response to {peer_addr}: {error}"
-                                                )
-                                            }
-                                            drop(thread_guard);
-                                        }
-                                    ) {
-                                        eprintln!("OxHTTP thread spawn error: {error}");
-                                    }
-                                }
-                                Err(error) => {
-                                    eprintln!("OxHTTP TCP error when opening stream: {error}");
-                                }
+        let on_request = if self.virtual_hosts.is_empty() {
+            self.on_request
+        } else {
+            let virtual_hosts = self.virtual_hosts;
+            let default = self.on_request;
+            Arc::new(move |request: &mut Request| {
+                let handler = request
+                    .url()
+                    .host_str()
+                    .and_then(|host| virtual_hosts.get(host))
+                    .unwrap_or(&default);
+                handler(request)
+            })
+        };
+        let on_request = if let Some(cors) = self.cors {
+            Arc::new(move |request: &mut Request| cors.handle(request, &*on_request))
+        } else {
+            on_request
+        };
+        let settings = Arc::new(ConnectionSettings {
+            on_request,
+            timeout: self.timeout,
+            server: self.server,
+            keep_alive_timeout: self.keep_alive_timeout,
+            max_requests_per_connection: self.max_requests_per_connection,
+            connections: ConnectionTracker::default(),
+            framing_header_position: self.framing_header_position,
+            max_connections_per_ip: self.max_connections_per_ip,
+            thread_stack_size: self.thread_stack_size,
+            thread_name_prefix: self.thread_name_prefix,
+            max_header_size: self.max_header_size,
+            chunk_size: self.chunk_size,
+            buffer_capacity: self.buffer_capacity,
+            body_budget: self.body_budget.map(BodyBudget::new),
+            connection_init: self.connection_init,
+            tls: self.tls,
+            access_log: self.access_log,
+            on_response: self.on_response,
+            interim_responses: self.interim_responses,
+            header_size_warning: self.header_size_warning,
+            error_renderer: self.error_renderer,
+            catch_panics: self.catch_panics,
+            normalize_path: self.normalize_path,
+        });
+        let mut threads = Vec::new();
+        let dispatcher = if let Some(pool_size) = self.thread_pool_size {
+            let (sender, receiver) = sync_channel::<TcpStream>(pool_size);
+            let receiver = Arc::new(Mutex::new(receiver));
+            for i in 0..pool_size {
+                let receiver = Arc::clone(&receiver);
+                let settings = Arc::clone(&settings);
+                threads.push(
+                    settings
+                        .thread_builder(format!("worker thread {i} of OxHTTP"))
+                        .spawn(move || loop {
+                            // The lock is released as soon as a connection is received
+                            let Ok(stream) = receiver.lock().unwrap().recv() else {
+                                break; // The listeners are closed
+                            };
+                            serve_connection(stream, &settings);
+                        })?,
+                );
+            }
+            ConnectionDispatcher::ThreadPool(sender)
+        } else {
+            ConnectionDispatcher::SpawnThread {
+                global_limit: self.max_num_thread.map(Semaphore::new),
+                listener_limit: None,
+            }
+        };
+        let mut listeners = self.listeners;
+        for listener_addr in self.socket_addrs {
+            listeners.push(bind_listener(listener_addr, self.tcp_backlog)?);
+        }
+        let mut listener_addrs = Vec::new();
+        for listener in listeners {
+            let listener_addr = listener.local_addr()?;
+            listener_addrs.push(listener_addr);
+            let thread_name = format!("{listener_addr}: listener thread of OxHTTP");
+            let dispatcher = dispatcher.for_listener(self.max_connections_per_listener);
+            let settings = Arc::clone(&settings);
+            threads.push(settings.thread_builder(thread_name).spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if settings.connections.is_shutting_down() {
+                                break;
+                            }
+                            if let Err(error) = stream.set_nodelay(true) {
+                                eprintln!("OxHTTP TCP error when attempting to set the TCP_NODELAY option: {error}");
                             }
+                            dispatcher.dispatch(stream, &settings);
                         }
-                    })
-                })
-                .collect::<Result<Vec<_>>>()?;
+                        Err(error) => {
+                            eprintln!("OxHTTP TCP error when opening stream: {error}");
+                        }
+                    }
+                }
+            })?);
+        }
         Ok(ListeningServer {
-            threads: listener_threads,
+            threads,
+            listener_addrs,
+            settings,
         })
     }
 }
 
-/// Handle to a running server created by [`Server::spawn`].
-pub struct ListeningServer {
-    threads: Vec<JoinHandle<()>>,
+/// Builder for a [`Server`], created with [`Server::builder`].
+///
+/// The configuration is validated by [`ServerBuilder::build`].
+pub struct ServerBuilder {
+    server: Server,
+    error: Option<BuildError>,
 }
 
-impl ListeningServer {
-    /// Join the server threads and wait for them indefinitely except in case of crash.
-    pub fn join(self) -> Result<()> {
-        for thread in self.threads {
-            thread.join().map_err(|e| {
-                Error::new(
-                    ErrorKind::Other,
-                    if let Ok(e) = e.downcast::<&dyn fmt::Display>() {
-                        format!("The server thread panicked with error: {e}")
-                    } else {
-                        "The server thread panicked with an unknown error".into()
-                    },
-                )
-            })?;
-        }
-        Ok(())
+impl ServerBuilder {
+    /// Ask the server to listen to a given socket when spawned.
+    #[inline]
+    pub fn bind(mut self, addr: impl Into<SocketAddr>) -> Self {
+        self.server = self.server.bind(addr);
+        self
     }
-}
 
-fn accept_request(
-    mut stream: TcpStream,
-    on_request: &dyn Fn(&mut Request) -> Response,
-    timeout: Option<Duration>,
-    server: &Option<HeaderValue>,
-) -> Result<()> {
-    stream.set_read_timeout(timeout)?;
-    stream.set_write_timeout(timeout)?;
-    let mut connection_state = ConnectionState::KeepAlive;
-    while connection_state == ConnectionState::KeepAlive {
-        let mut reader = BufReader::with_capacity(BUFFER_CAPACITY, stream.try_clone()?);
-        let (mut response, new_connection_state) = match decode_request_headers(&mut reader, false)
-        {
-            Ok(request) => {
-                // Handles Expect header
-                if let Some(expect) = request.header(&HeaderName::EXPECT).cloned() {
-                    if expect.eq_ignore_ascii_case(b"100-continue") {
-                        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
-                        read_body_and_build_response(request, reader, on_request)
-                    } else {
-                        (
-                            build_text_response(
-                                Status::EXPECTATION_FAILED,
-                                format!(
-                                    "Expect header value '{}' is not supported.",
-                                    String::from_utf8_lossy(expect.as_ref())
-                                ),
-                            ),
-                            ConnectionState::Close,
-                        )
-                    }
-                } else {
-                    read_body_and_build_response(request, reader, on_request)
-                }
-            }
-            Err(error) => {
-                if error.kind() == ErrorKind::ConnectionAborted {
-                    return Ok(()); // The client is disconnected. Let's ignore this error and do not try to write an answer that won't be received.
-                } else {
-                    (build_error(error), ConnectionState::Close)
-                }
-            }
-        };
-        connection_state = new_connection_state;
+    /// Ask the server to accept connections from an already bound listener when spawned.
+    ///
+    /// See [`Server::listen`].
+    #[inline]
+    pub fn listen(mut self, listener: TcpListener) -> Self {
+        self.server = self.server.listen(listener);
+        self
+    }
 
-        // Additional headers
-        if let Some(server) = server {
-            if !response.headers().contains(&HeaderName::SERVER) {
-                response
-                    .headers_mut()
-                    .set(HeaderName::SERVER, server.clone())
-            }
+    /// Sets the default value for the [`Server`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.server) header.
+    ///
+    /// An invalid value makes [`build`](Self::build) fail.
+    #[inline]
+    pub fn with_server_name(mut self, server: impl Into<String>) -> Self {
+        match HeaderValue::try_from(server.into()) {
+            Ok(server) => self.server.server = Some(server),
+            Err(e) => self.set_error(format!("Invalid Server header value: {e}")),
         }
-
-        stream = encode_response(
-            &mut response,
-            BufWriter::with_capacity(BUFFER_CAPACITY, stream),
-        )?
-        .into_inner()
-        .map_err(|e| e.into_error())?;
+        self
     }
-    Ok(())
-}
 
-#[derive(Eq, PartialEq, Debug, Copy, Clone)]
-enum ConnectionState {
-    Close,
-    KeepAlive,
-}
+    /// Sets the global timeout value (applies to both read and write).
+    #[inline]
+    pub fn with_global_timeout(mut self, timeout: Duration) -> Self {
+        self.server.timeout = Some(timeout);
+        self
+    }
 
-fn read_body_and_build_response(
-    request: RequestBuilder,
-    reader: BufReader<TcpStream>,
-    on_request: &dyn Fn(&mut Request) -> Response,
-) -> (Response, ConnectionState) {
-    match decode_request_body(request, reader) {
-        Ok(mut request) => {
-            let response = on_request(&mut request);
-            // We make sure to finish reading the body
-            if let Err(error) = copy(request.body_mut(), &mut sink()) {
-                (build_error(error), ConnectionState::Close) //TODO: ignore?
-            } else {
-                let connection_state = request
-                    .header(&HeaderName::CONNECTION)
-                    .and_then(|v| {
-                        v.eq_ignore_ascii_case(b"close")
-                            .then_some(ConnectionState::Close)
-                    })
-                    .unwrap_or(ConnectionState::KeepAlive);
-                (response, connection_state)
-            }
-        }
-        Err(error) => (build_error(error), ConnectionState::Close),
+    /// Sets the number maximum number of threads this server can spawn.
+    #[inline]
+    pub fn with_max_concurrent_connections(mut self, max_num_thread: usize) -> Self {
+        self.server.max_num_thread = Some(max_num_thread);
+        self
     }
-}
 
-fn build_error(error: Error) -> Response {
-    build_text_response(
-        match error.kind() {
-            ErrorKind::TimedOut => Status::REQUEST_TIMEOUT,
-            ErrorKind::InvalidData => Status::BAD_REQUEST,
-            _ => Status::INTERNAL_SERVER_ERROR,
-        },
-        error.to_string(),
-    )
-}
+    /// Sets the maximal number of concurrent connections accepted by each listener.
+    ///
+    /// Contrary to [`with_max_concurrent_connections`](Self::with_max_concurrent_connections) that is shared by all listeners,
+    /// each bound address gets its own limit: a flood of connections on one of them does not starve the others.
+    /// Both limits can be combined.
+    /// It is ignored when a [thread pool](Self::with_thread_pool) is set.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use std::net::{Ipv4Addr, Ipv6Addr};
+    ///
+    /// let server = Server::builder(|_| "hello")
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .bind((Ipv6Addr::LOCALHOST, 8080))
+    ///     .with_max_concurrent_connections_per_listener(64)
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_max_concurrent_connections_per_listener(mut self, max_connections: usize) -> Self {
+        self.server.max_connections_per_listener = Some(max_connections);
+        self
+    }
 
-fn build_text_response(status: Status, text: String) -> Response {
-    Response::builder(status)
-        .with_header(HeaderName::CONTENT_TYPE, "text/plain; charset=utf-8")
-        .unwrap()
-        .with_body(text)
-}
+    /// Sets the maximal number of concurrent connections from the same IP address.
+    ///
+    /// New connections from an IP address that already reached the limit get a `429 Too Many Requests` response and are closed.
+    ///
+    /// The IP address is the one returned by the function set with [`ServerBuilder::with_connection_init`],
+    /// i.e. the client address conveyed by the header if [`ServerBuilder::with_proxy_protocol`] is used.
+    #[inline]
+    pub fn with_max_connections_per_ip(mut self, max_connections: usize) -> Self {
+        self.server.max_connections_per_ip = Some(max_connections);
+        self
+    }
 
-/// Dumb semaphore allowing to overflow capacity
-#[derive(Clone)]
-struct Semaphore {
-    inner: Arc<InnerSemaphore>,
-}
+    /// Sets how long an idle connection is kept open while waiting for the next request.
+    ///
+    /// The value is advertised to the clients using the `Keep-Alive` header,
+    /// rounded down to the second and omitted if it is shorter than a second.
+    #[inline]
+    pub fn with_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.server.keep_alive_timeout = Some(timeout);
+        self
+    }
 
-struct InnerSemaphore {
-    count: Mutex<usize>,
-    capacity: usize,
-    condvar: Condvar,
-}
+    /// Sets the maximal number of requests served on a single connection before closing it.
+    ///
+    /// The number of remaining requests is advertised to the clients using the `Keep-Alive` header.
+    #[inline]
+    pub fn with_max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.server.max_requests_per_connection = Some(max_requests);
+        self
+    }
 
-impl Semaphore {
-    fn new(capacity: usize) -> Self {
-        Self {
-            inner: Arc::new(InnerSemaphore {
-                count: Mutex::new(0),
-                capacity,
-                condvar: Condvar::new(),
-            }),
-        }
+    /// Enables [CORS](https://fetch.spec.whatwg.org/#http-cors-protocol) support.
+    ///
+    /// The CORS preflight requests are answered by the server without calling the `on_request` method
+    /// and the `Access-Control-Allow-Origin` header is added to the responses to the other CORS requests.
+    ///
+    /// ```
+    /// use oxhttp::{CorsConfig, Server};
+    /// use oxhttp::model::{Method, Response, Status};
+    /// use std::net::Ipv4Addr;
+    /// use std::time::Duration;
+    ///
+    /// let server = Server::builder(|_| Response::builder(Status::OK).build())
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_cors(
+    ///         CorsConfig::new()
+    ///             .with_allowed_origin("https://example.com")?
+    ///             .with_allowed_method(Method::PUT)
+    ///             .with_max_age(Duration::from_secs(3600)),
+    ///     )
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_cors(mut self, cors: CorsConfig) -> Self {
+        self.server.cors = Some(cors);
+        self
     }
 
-    fn lock(&self) -> SemaphoreGuard {
-        let data = &self.inner;
-        *data
-            .condvar
-            .wait_while(data.count.lock().unwrap(), |count| *count >= data.capacity)
-            .unwrap() += 1;
-        SemaphoreGuard {
-            inner: Arc::clone(&self.inner),
+    /// Serves the requests to the given host name with `on_request` instead of the server default function.
+    ///
+    /// The host name is taken from the request target or the `Host` header.
+    /// It is compared case-insensitively and without the port.
+    /// The requests to hosts without a specific function are served by the server default function.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use oxhttp::model::Status;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| Status::NOT_FOUND)
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_virtual_host("a.example", |_| "Welcome to a.example")
+    ///     .with_virtual_host("b.example", |_| "Welcome to b.example")
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_virtual_host<R: Into<Response>>(
+        mut self,
+        host: impl Into<String>,
+        on_request: impl Fn(&mut Request) -> R + Send + Sync + 'static,
+    ) -> Self {
+        let host = host.into();
+        match Host::parse(&host) {
+            Ok(parsed) => {
+                self.server.virtual_hosts.insert(
+                    parsed.to_string(),
+                    Arc::new(move |request: &mut Request| on_request(request).into()),
+                );
+            }
+            Err(e) => self.set_error(format!("Invalid virtual host name '{host}': {e}")),
         }
+        self
     }
-}
-
-struct SemaphoreGuard {
-    inner: Arc<InnerSemaphore>,
-}
 
-impl Drop for SemaphoreGuard {
-    fn drop(&mut self) {
-        let data = &self.inner;
-        *data.count.lock().unwrap() -= 1;
-        data.condvar.notify_one();
+    /// Writes the framing header (`Content-Length` or `Transfer-Encoding`) just after the status line.
+    ///
+    /// By default, responses are written with the status line, then the headers set by `on_request`,
+    /// then the connection management headers (`Connection`, `Keep-Alive`) and finally the framing header.
+    /// Some intermediaries expect the framing header to come first.
+    #[inline]
+    pub fn with_framing_header_first(mut self) -> Self {
+        self.server.framing_header_position = FramingHeaderPosition::First;
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::model::Status;
-    use std::io::Read;
-    use std::net::{Ipv4Addr, Ipv6Addr};
-    use std::thread::sleep;
+    /// Sets the stack size in bytes of the threads spawned by the server.
+    ///
+    /// By default, the [`std::thread`] default stack size is used.
+    #[inline]
+    pub fn with_thread_stack_size(mut self, stack_size: usize) -> Self {
+        self.server.thread_stack_size = Some(stack_size);
+        self
+    }
 
-    #[test]
-    fn test_regular_http_operations() -> Result<()> {
-        test_server("localhost", 9999, [
-            "GET / HTTP/1.1\nhost: localhost:9999\n\n",
-            "POST /foo HTTP/1.1\nhost: localhost:9999\nexpect: 100-continue\nconnection:close\ncontent-length:4\n\nabcd",
-        ], [
-            "HTTP/1.1 200 OK\r\nserver: OxHTTP/1.0\r\ncontent-length: 4\r\n\r\nhome",
-            "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 404 Not Found\r\nserver: OxHTTP/1.0\r\ncontent-length: 0\r\n\r\n"
-        ])
+    /// Sets a prefix to add to the names of the threads spawned by the server.
+    #[inline]
+    pub fn with_thread_name_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.server.thread_name_prefix = Some(prefix.into());
+        self
     }
 
-    #[test]
-    fn test_bad_request() -> Result<()> {
-        test_server(
-            "::1", 9998,
-            ["GET / HTTP/1.1\nhost: localhost:9999\nfoo\n\n"],
-            ["HTTP/1.1 400 Bad Request\r\ncontent-type: text/plain; charset=utf-8\r\nserver: OxHTTP/1.0\r\ncontent-length: 19\r\n\r\ninvalid header name"],
-        )
+    /// Sets the maximal number of pending connections waiting to be accepted on each bound address.
+    ///
+    /// A larger backlog avoids refusing connections during bursts.
+    /// By default, the operating system default is used.
+    /// It does not apply to the listeners given with [`listen`](Self::listen).
+    #[inline]
+    pub fn with_tcp_backlog(mut self, backlog: u32) -> Self {
+        self.server.tcp_backlog = Some(backlog);
+        self
     }
 
-    #[test]
-    fn test_bad_expect() -> Result<()> {
-        test_server(
-            "127.0.0.1", 9997,
-            ["GET / HTTP/1.1\nhost: localhost:9999\nexpect: bad\n\n"],
-            ["HTTP/1.1 417 Expectation Failed\r\ncontent-type: text/plain; charset=utf-8\r\nserver: OxHTTP/1.0\r\ncontent-length: 43\r\n\r\nExpect header value 'bad' is not supported."],
-        )
+    /// Sets the maximal size in bytes of the request headers and of the chunked encoding trailers.
+    ///
+    /// Requests with larger headers are rejected with a `400 Bad Request` response.
+    /// Default to 8KiB.
+    #[inline]
+    pub fn with_max_header_size(mut self, max_header_size: u64) -> Self {
+        self.server.max_header_size = max_header_size;
+        self
     }
 
-    fn test_server(
-        request_host: &'static str,
+    /// Sets the maximal size in bytes of the chunks written when a response body is streamed using chunked transfer encoding.
+    ///
+    /// Bigger chunks reduce the framing overhead of large bodies.
+    /// Default to 4KiB.
+    #[inline]
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.server.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets the capacity in bytes of the buffers used to read the requests and write the responses of each connection.
+    ///
+    /// Bigger buffers reduce the number of system calls of large transfers.
+    /// It must be at least 4KiB.
+    /// Default to 16KiB.
+    #[inline]
+    pub fn with_buffer_capacity(mut self, buffer_capacity: usize) -> Self {
+        self.server.buffer_capacity = buffer_capacity;
+        self
+    }
+
+    /// Limits the total size in bytes of the request bodies being received by all the connections at the same time.
+    ///
+    /// The bodies with a `Content-Length` header are accounted for when their request is received:
+    /// if the remaining budget is too small, the server answers with a `503 Service Unavailable` response without reading the body.
+    /// The chunked bodies are accounted for while being read:
+    /// if the budget gets exhausted, the reads fail with an [`OutOfMemory`](ErrorKind::OutOfMemory) error.
+    /// The budget is released when the request body is dropped.
+    ///
+    /// It protects from memory exhaustion if `on_request` buffers the bodies.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use oxhttp::model::Status;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| Status::NO_CONTENT)
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_global_body_budget(64 * 1024 * 1024)
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_global_body_budget(mut self, bytes: u64) -> Self {
+        self.server.body_budget = Some(bytes);
+        self
+    }
+
+    /// Sets a function called when the headers of a request are larger than `soft_limit` bytes.
+    ///
+    /// The function is given the size of the request headers and the address of the client.
+    /// The request is still processed: this allows to detect clients that are close to the
+    /// [maximal header size](Self::with_max_header_size) before their requests get rejected.
+    /// `soft_limit` must be lower than the maximal header size.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use oxhttp::model::Status;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| Status::NO_CONTENT)
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_header_size_warning(4 * 1024, |size, peer_addr| {
+    ///         eprintln!("{peer_addr} sent {size} bytes of headers")
+    ///     })
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_header_size_warning(
+        mut self,
+        soft_limit: u64,
+        on_warning: impl Fn(u64, SocketAddr) + Send + Sync + 'static,
+    ) -> Self {
+        self.server.header_size_warning = Some((soft_limit, Arc::new(on_warning)));
+        self
+    }
+
+    /// Sets the function building the responses to the errors raised by the server itself
+    /// (invalid requests, timeouts, too many connections, panics of `on_request`...).
+    ///
+    /// It is given the response status, the error message and the request headers if they have been parsed,
+    /// allowing for example to pick the error format using the `Accept` header.
+    /// By default, the error message is returned as `text/plain`.
+    /// The responses built by `on_request` are not affected.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use oxhttp::model::{HeaderName, Response, Status};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| Status::NO_CONTENT)
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_error_renderer(|status, message, _| {
+    ///         Response::builder(status)
+    ///             .with_header(HeaderName::CONTENT_TYPE, "application/json")
+    ///             .unwrap()
+    ///             .with_body(format!("{{\"error\": {message:?}}}"))
+    ///     })
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_error_renderer(
+        mut self,
+        renderer: impl Fn(Status, &str, Option<&Headers>) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        self.server.error_renderer = Some(Arc::new(renderer));
+        self
+    }
+
+    /// Sets if the panics of `on_request` are caught (enabled by default).
+    ///
+    /// If enabled, a panicking `on_request` leads to a `500 Internal Server Error` response
+    /// built with the [error renderer](Self::with_error_renderer) and the connection is closed after it.
+    /// The panic message is written to the standard error output.
+    /// If disabled, the connection is dropped without any response.
+    #[inline]
+    pub fn with_catch_panics(mut self, catch_panics: bool) -> Self {
+        self.server.catch_panics = catch_panics;
+        self
+    }
+
+    /// Sets if the request target paths are normalized before being given to `on_request` (disabled by default).
+    ///
+    /// If enabled, the `.` and `..` segments are removed from the path, comparing the segments after percent-decoding.
+    /// Requests whose path goes above the root (e.g. `/../etc/passwd`) or contains an invalid percent-encoding
+    /// or a `\` or an encoded `/`, `\` or NUL character get a `400 Bad Request` response.
+    /// The query string is kept as is.
+    /// It protects handlers that map the URL paths to file system paths, like static file servers.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|request| request.url().path().to_owned())
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_normalize_path(true)
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_normalize_path(mut self, normalize_path: bool) -> Self {
+        self.server.normalize_path = normalize_path;
+        self
+    }
+
+    /// Sets a function called on each new connection before reading its requests.
+    ///
+    /// It might read from or write to the stream, for example to parse a header sent by a proxy,
+    /// and returns the [`ConnectionContext`] given to the `on_request` function with [`Request::connection`].
+    /// If it fails, the connection is closed.
+    /// By default, the context is built from the TCP connection.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use oxhttp::model::ConnectionContext;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|request| {
+    ///     request.connection().unwrap().peer_addr().to_string()
+    /// })
+    /// .bind((Ipv4Addr::LOCALHOST, 8080))
+    /// .with_connection_init(|stream| Ok(ConnectionContext::new(stream.peer_addr()?)))
+    /// .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_connection_init(
+        mut self,
+        init: impl Fn(&mut TcpStream) -> Result<ConnectionContext> + Send + Sync + 'static,
+    ) -> Self {
+        self.server.connection_init = Some(Arc::new(init));
+        self
+    }
+
+    /// Requires each connection to start with a [PROXY protocol](https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt) header
+    /// like the ones sent by HAProxy or AWS Network Load Balancers.
+    ///
+    /// The client address conveyed by the header is exposed with [`Request::connection`].
+    /// The connections not starting with a valid header of the given version are closed
+    /// so only enable it if the server is reachable only through the proxy.
+    ///
+    /// It replaces the function set with [`ServerBuilder::with_connection_init`].
+    /// The limit set with [`ServerBuilder::with_max_connections_per_ip`] applies to the client address conveyed by the header.
+    ///
+    /// ```
+    /// use oxhttp::{ProxyProtocolVersion, Server};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|request| {
+    ///     request.connection().unwrap().peer_addr().to_string()
+    /// })
+    /// .bind((Ipv4Addr::LOCALHOST, 8080))
+    /// .with_proxy_protocol(ProxyProtocolVersion::V2)
+    /// .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_proxy_protocol(self, version: ProxyProtocolVersion) -> Self {
+        self.with_connection_init(move |stream| read_proxy_protocol_header(stream, version))
+    }
+
+    /// Sets a function called after each response has been sent, for example to write access logs.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| "hello")
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_access_log(|entry| {
+    ///         eprintln!(
+    ///             "{} {} {} {}",
+    ///             entry.peer_addr(),
+    ///             entry.url().map_or_else(|| "-".into(), ToString::to_string),
+    ///             entry.status(),
+    ///             entry.bytes_written()
+    ///         )
+    ///     })
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_access_log(
+        mut self,
+        access_log: impl Fn(&AccessLogEntry) + Send + Sync + 'static,
+    ) -> Self {
+        self.server.access_log = Some(Arc::new(access_log));
+        self
+    }
+
+    /// Sets a function called after each response to a request has been sent.
+    ///
+    /// It is given the request as received (with an empty body),
+    /// the response and the time elapsed between the end of the request head reading and the end of the response writing.
+    /// The client address is available with [`Request::connection`].
+    /// It is also called for the error responses built by the server itself, for example if the request body is invalid,
+    /// but not if the request head can't be parsed: use [`with_access_log`](Self::with_access_log) to log these.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| "hello")
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_on_response(|request, response, duration| {
+    ///         eprintln!(
+    ///             "{} {} {} {}ms",
+    ///             request.method(),
+    ///             request.url(),
+    ///             response.status(),
+    ///             duration.as_millis()
+    ///         )
+    ///     })
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_on_response(
+        mut self,
+        on_response: impl Fn(&Request, &Response, Duration) + Send + Sync + 'static,
+    ) -> Self {
+        self.server.on_response = Some(Arc::new(on_response));
+        self
+    }
+
+    /// Sets a function returning the [informational responses](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#status.1xx)
+    /// to send to the requests with the `Expect: 100-continue` header, like `103 Early Hints`.
+    ///
+    /// It is called with the request head (the body is empty) before the body is read.
+    /// The returned responses are sent in order before the `100 Continue` response written by the server.
+    /// Only their status and headers are sent.
+    /// Only the `1xx` statuses other than `100 Continue` and `101 Switching Protocols` are allowed:
+    /// the server answers with a `500 Internal Server Error` if another one is returned.
+    ///
+    /// ```
+    /// use oxhttp::model::{Response, Status};
+    /// use oxhttp::Server;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| "hello")
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_interim_responses(|_| {
+    ///         vec![Response::builder(Status::EARLY_HINTS)
+    ///             .with_header("link", "</style.css>; rel=preload")
+    ///             .unwrap()
+    ///             .build()]
+    ///     })
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_interim_responses(
+        mut self,
+        interim_responses: impl Fn(&Request) -> Vec<Response> + Send + Sync + 'static,
+    ) -> Self {
+        self.server.interim_responses = Some(Arc::new(interim_responses));
+        self
+    }
+
+    /// Terminates TLS on the server connections using the given [Rustls](https://github.com/rustls/rustls) configuration.
+    ///
+    /// The requests are then received with an `https` URL.
+    /// It replaces the acceptor set with [`ServerBuilder::with_native_tls_acceptor`].
+    #[cfg(feature = "rustls")]
+    #[inline]
+    pub fn with_rustls_config(mut self, config: Arc<ServerConfig>) -> Self {
+        self.server.tls = Some(ServerTls::Rustls(config));
+        self
+    }
+
+    /// Terminates TLS on the server connections using the given [native-tls](https://github.com/sfackler/rust-native-tls) acceptor.
+    ///
+    /// The requests are then received with an `https` URL.
+    /// It replaces the configuration set with [`ServerBuilder::with_rustls_config`].
+    #[cfg(feature = "native-tls")]
+    #[inline]
+    pub fn with_native_tls_acceptor(mut self, acceptor: TlsAcceptor) -> Self {
+        self.server.tls = Some(ServerTls::NativeTls(acceptor));
+        self
+    }
+
+    /// Serves the connections with a fixed set of `size` worker threads started by [`Server::spawn`]
+    /// instead of a new thread per connection.
+    ///
+    /// The accepted connections wait in a queue until a worker is available.
+    /// [`with_max_concurrent_connections`](Self::with_max_concurrent_connections) and
+    /// [`with_max_concurrent_connections_per_listener`](Self::with_max_concurrent_connections_per_listener) are ignored when a pool is set.
+    ///
+    /// ```
+    /// use oxhttp::Server;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let server = Server::builder(|_| "hello")
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .with_thread_pool(16)
+    ///     .build()?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    pub fn with_thread_pool(mut self, size: usize) -> Self {
+        self.server.thread_pool_size = Some(size);
+        self
+    }
+
+    /// Validates the configuration and builds the server.
+    pub fn build(self) -> std::result::Result<Server, BuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let server = self.server;
+        if server.socket_addrs.is_empty() && server.listeners.is_empty() {
+            return Err(BuildError::new(
+                "The server should be bound to at least one address or listener",
+            ));
+        }
+        if server.timeout == Some(Duration::ZERO) {
+            return Err(BuildError::new("The timeout should not be zero"));
+        }
+        if server.keep_alive_timeout == Some(Duration::ZERO) {
+            return Err(BuildError::new("The keep-alive timeout should not be zero"));
+        }
+        if server.max_num_thread == Some(0) {
+            return Err(BuildError::new(
+                "The maximal number of concurrent connections should not be zero",
+            ));
+        }
+        if server.max_connections_per_listener == Some(0) {
+            return Err(BuildError::new(
+                "The maximal number of concurrent connections per listener should not be zero",
+            ));
+        }
+        if server.max_connections_per_ip == Some(0) {
+            return Err(BuildError::new(
+                "The maximal number of connections per IP address should not be zero",
+            ));
+        }
+        if server.max_requests_per_connection == Some(0) {
+            return Err(BuildError::new(
+                "The maximal number of requests per connection should not be zero",
+            ));
+        }
+        if let Some(backlog) = server.tcp_backlog {
+            if backlog == 0 || i32::try_from(backlog).is_err() {
+                return Err(BuildError::new(format!(
+                    "The TCP backlog should be between 1 and {}",
+                    i32::MAX
+                )));
+            }
+        }
+        if server.thread_pool_size == Some(0) {
+            return Err(BuildError::new("The thread pool size should not be zero"));
+        }
+        if server.max_header_size == 0 {
+            return Err(BuildError::new(
+                "The maximal header size should not be zero",
+            ));
+        }
+        if server.body_budget == Some(0) {
+            return Err(BuildError::new("The global body budget should not be zero"));
+        }
+        if server.chunk_size == 0 {
+            return Err(BuildError::new("The chunk size should not be zero"));
+        }
+        if server.buffer_capacity < MIN_BUFFER_CAPACITY {
+            return Err(BuildError::new(format!(
+                "The buffer capacity should be at least {MIN_BUFFER_CAPACITY} bytes"
+            )));
+        }
+        if let Some((soft_limit, _)) = &server.header_size_warning {
+            if *soft_limit >= server.max_header_size {
+                return Err(BuildError::new(format!(
+                    "The header size warning threshold should be lower than the maximal header size of {} bytes",
+                    server.max_header_size
+                )));
+            }
+        }
+        Ok(server)
+    }
+
+    fn set_error(&mut self, message: String) {
+        // We keep the first error
+        if self.error.is_none() {
+            self.error = Some(BuildError::new(message));
+        }
+    }
+}
+
+/// Handle to a running server created by [`Server::spawn`].
+pub struct ListeningServer {
+    threads: Vec<JoinHandle<()>>,
+    listener_addrs: Vec<SocketAddr>,
+    settings: Arc<ConnectionSettings>,
+}
+
+impl ListeningServer {
+    /// The addresses the server is listening to.
+    ///
+    /// The actual ports are returned for the addresses bound with port 0.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listener_addrs.clone()
+    }
+
+    /// Join the server threads and wait for them indefinitely except in case of crash.
+    pub fn join(self) -> Result<()> {
+        for thread in self.threads {
+            join_thread(thread)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the server gracefully.
+    ///
+    /// The server stops accepting new connections and closes the idle ones.
+    /// The connections processing a request are closed after sending their current response, with a `Connection: close` header.
+    /// The connections that are still open after `deadline` are forcibly closed.
+    ///
+    /// ```no_run
+    /// use oxhttp::Server;
+    /// use oxhttp::model::{Response, Status};
+    /// use std::net::Ipv4Addr;
+    /// use std::time::Duration;
+    ///
+    /// let server = Server::new(|_| Response::builder(Status::OK).build())
+    ///     .bind((Ipv4Addr::LOCALHOST, 8080))
+    ///     .spawn()?;
+    /// let summary = server.shutdown_graceful(Duration::from_secs(30))?;
+    /// println!("{} connections drained, {} forcibly closed", summary.drained(), summary.forced());
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn shutdown_graceful(self, deadline: Duration) -> Result<ShutdownSummary> {
+        let deadline = Instant::now() + deadline;
+        let connections = &self.settings.connections;
+        connections.start_shutdown();
+        // We wake up the listener threads blocked on accept so that they see the shutdown
+        for addr in &self.listener_addrs {
+            let ip = match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+                IpAddr::V6(ip) if ip.is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+                ip => ip,
+            };
+            if let Err(error) = TcpStream::connect((ip, addr.port())) {
+                eprintln!("OxHTTP error when waking up the listener {addr}: {error}");
+            }
+        }
+        let (drained, forced) = connections.wait_until_closed(deadline);
+        for thread in self.threads {
+            join_thread(thread)?;
+        }
+        Ok(ShutdownSummary { drained, forced })
+    }
+}
+
+/// Binds a listener to the address using the given backlog or the OS default one
+fn bind_listener(addr: SocketAddr, backlog: Option<u32>) -> Result<TcpListener> {
+    let Some(backlog) = backlog else {
+        return TcpListener::bind(addr);
+    };
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // Same as the standard library TcpListener::bind
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(i32::try_from(backlog).unwrap_or(i32::MAX))?;
+    Ok(socket.into())
+}
+
+/// How the accepted connections are given to the threads serving them
+#[derive(Clone)]
+enum ConnectionDispatcher {
+    /// A new thread is spawned for each connection, optionally bounded by semaphores
+    SpawnThread {
+        /// Shared by all listeners
+        global_limit: Option<Semaphore>,
+        /// Specific to the listener
+        listener_limit: Option<Semaphore>,
+    },
+    /// The connections are sent to a fixed set of worker threads
+    ThreadPool(SyncSender<TcpStream>),
+}
+
+impl ConnectionDispatcher {
+    /// Returns the dispatcher to be used by a listener, with its own connection limit if set
+    fn for_listener(&self, max_connections: Option<usize>) -> Self {
+        match self {
+            Self::SpawnThread { global_limit, .. } => Self::SpawnThread {
+                global_limit: global_limit.clone(),
+                listener_limit: max_connections.map(Semaphore::new),
+            },
+            Self::ThreadPool(sender) => Self::ThreadPool(sender.clone()),
+        }
+    }
+
+    fn dispatch(&self, stream: TcpStream, settings: &Arc<ConnectionSettings>) {
+        match self {
+            Self::SpawnThread {
+                global_limit,
+                listener_limit,
+            } => {
+                let peer_addr = match stream.peer_addr() {
+                    Ok(peer) => peer,
+                    Err(error) => {
+                        eprintln!(
+                            "OxHTTP TCP error when attempting to get the peer address: {error}"
+                        );
+                        return;
+                    }
+                };
+                let thread_name = format!("{peer_addr}: responding thread of OxHTTP");
+                // The listener slot is taken first to not hold a global slot while waiting for it
+                let listener_guard = listener_limit.as_ref().map(Semaphore::lock);
+                let thread_guard = global_limit.as_ref().map(Semaphore::lock);
+                let settings = Arc::clone(settings);
+                if let Err(error) = settings.thread_builder(thread_name).spawn(move || {
+                    serve_connection(stream, &settings);
+                    drop(thread_guard);
+                    drop(listener_guard);
+                }) {
+                    eprintln!("OxHTTP thread spawn error: {error}");
+                }
+            }
+            Self::ThreadPool(sender) => {
+                if sender.send(stream).is_err() {
+                    eprintln!("OxHTTP error: the worker threads are not running anymore");
+                }
+            }
+        }
+    }
+}
+
+fn serve_connection(stream: TcpStream, settings: &ConnectionSettings) {
+    let peer_addr = stream.peer_addr();
+    if let Err(error) = accept_request(stream, settings) {
+        match peer_addr {
+            Ok(peer_addr) => {
+                eprintln!("OxHTTP TCP error when writing response to {peer_addr}: {error}")
+            }
+            Err(_) => eprintln!("OxHTTP TCP error when writing response: {error}"),
+        }
+    }
+}
+
+fn join_thread(thread: JoinHandle<()>) -> Result<()> {
+    thread.join().map_err(|e| {
+        Error::other(if let Ok(e) = e.downcast::<&dyn fmt::Display>() {
+            format!("The server thread panicked with error: {e}")
+        } else {
+            "The server thread panicked with an unknown error".into()
+        })
+    })
+}
+
+/// Outcome of [`ListeningServer::shutdown_graceful`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    drained: usize,
+    forced: usize,
+}
+
+impl ShutdownSummary {
+    /// The number of connections that have been closed before the deadline.
+    #[inline]
+    pub fn drained(&self) -> usize {
+        self.drained
+    }
+
+    /// The number of connections that have been forcibly closed at the deadline.
+    #[inline]
+    pub fn forced(&self) -> usize {
+        self.forced
+    }
+}
+
+/// A response sent by the server, given to the function set with [`ServerBuilder::with_access_log`].
+#[derive(Debug, Clone)]
+pub struct AccessLogEntry {
+    peer_addr: SocketAddr,
+    method: Option<Method>,
+    url: Option<Url>,
+    status: Status,
+    bytes_written: u64,
+}
+
+impl AccessLogEntry {
+    /// The client address as given by [`ConnectionContext::peer_addr`].
+    #[inline]
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// The request method, if the request head has been successfully parsed.
+    #[inline]
+    pub fn method(&self) -> Option<&Method> {
+        self.method.as_ref()
+    }
+
+    /// The request URL, if the request head has been successfully parsed.
+    #[inline]
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
+    /// The response status.
+    #[inline]
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// The number of bytes written to the connection for the response, including the headers and the chunked encoding framing.
+    #[inline]
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+/// [CORS](https://fetch.spec.whatwg.org/#http-cors-protocol) configuration of a [`Server`].
+///
+/// By default, all origins, methods and headers are allowed and credentials are not.
+#[derive(Debug, Clone, Default)]
+pub struct CorsConfig {
+    allowed_origins: Vec<HeaderValue>,
+    allowed_methods: Vec<Method>,
+    allowed_headers: Vec<HeaderName>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl CorsConfig {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an origin (e.g. `https://example.com`) to the allowed ones.
+    ///
+    /// If this function is never called, all origins are allowed.
+    #[inline]
+    pub fn with_allowed_origin(
+        mut self,
+        origin: impl Into<String>,
+    ) -> std::result::Result<Self, InvalidHeader> {
+        self.allowed_origins
+            .push(HeaderValue::try_from(origin.into())?);
+        Ok(self)
+    }
+
+    /// Adds a method to the allowed ones.
+    ///
+    /// If this function is never called, all methods are allowed.
+    #[inline]
+    pub fn with_allowed_method(mut self, method: Method) -> Self {
+        self.allowed_methods.push(method);
+        self
+    }
+
+    /// Adds a request header to the allowed ones.
+    ///
+    /// If this function is never called, all headers are allowed.
+    #[inline]
+    pub fn with_allowed_header(mut self, header: HeaderName) -> Self {
+        self.allowed_headers.push(header);
+        self
+    }
+
+    /// Allows requests with credentials (cookies, authorization headers...).
+    #[inline]
+    pub fn with_credentials_allowed(mut self) -> Self {
+        self.allow_credentials = true;
+        self
+    }
+
+    /// Sets how long the answers to the preflight requests can be cached by the clients.
+    #[inline]
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn handle(
+        &self,
+        request: &mut Request,
+        on_request: &dyn Fn(&mut Request) -> Response,
+    ) -> Response {
+        let Some(origin) = request
+            .header(&HeaderName::new_unchecked("origin"))
+            .cloned()
+        else {
+            return on_request(request); // Not a CORS request
+        };
+        if *request.method() == Method::OPTIONS {
+            if let Some(requested_method) = request
+                .header(&HeaderName::new_unchecked("access-control-request-method"))
+                .cloned()
+            {
+                return self.preflight(request, origin, &requested_method);
+            }
+        }
+        let mut response = on_request(request);
+        if self.is_allowed_origin(&origin)
+            && !response
+                .headers()
+                .contains(&HeaderName::new_unchecked("access-control-allow-origin"))
+        {
+            self.add_origin_headers(response.headers_mut(), origin);
+        }
+        response
+    }
+
+    fn preflight(
+        &self,
+        request: &Request,
+        origin: HeaderValue,
+        requested_method: &HeaderValue,
+    ) -> Response {
+        if !self.is_allowed_origin(&origin) {
+            return build_text_response(
+                Status::FORBIDDEN,
+                format!(
+                    "The origin '{}' is not allowed",
+                    String::from_utf8_lossy(origin.as_ref())
+                ),
+            );
+        }
+        if !self.allowed_methods.is_empty()
+            && !self
+                .allowed_methods
+                .iter()
+                .any(|m| m.as_ref().as_bytes() == requested_method.as_ref())
+        {
+            return build_text_response(
+                Status::FORBIDDEN,
+                format!(
+                    "The method '{}' is not allowed",
+                    String::from_utf8_lossy(requested_method.as_ref())
+                ),
+            );
+        }
+        let mut response = Response::builder(Status::NO_CONTENT).build();
+        let headers = response.headers_mut();
+        self.add_origin_headers(headers, origin);
+        headers.set(
+            HeaderName::new_unchecked("access-control-allow-methods"),
+            if self.allowed_methods.is_empty() {
+                requested_method.clone()
+            } else {
+                join_header_values(self.allowed_methods.iter().map(|m| m.as_ref()))
+            },
+        );
+        let allowed_headers = if self.allowed_headers.is_empty() {
+            request
+                .header(&HeaderName::new_unchecked("access-control-request-headers"))
+                .cloned()
+        } else {
+            Some(join_header_values(
+                self.allowed_headers.iter().map(|h| h.as_ref()),
+            ))
+        };
+        if let Some(allowed_headers) = allowed_headers {
+            headers.set(
+                HeaderName::new_unchecked("access-control-allow-headers"),
+                allowed_headers,
+            );
+        }
+        if let Some(max_age) = self.max_age {
+            headers.set(
+                HeaderName::new_unchecked("access-control-max-age"),
+                HeaderValue::new_unchecked(max_age.as_secs().to_string().into_bytes()),
+            );
+        }
+        response
+    }
+
+    fn is_allowed_origin(&self, origin: &HeaderValue) -> bool {
+        self.allowed_origins.is_empty() || self.allowed_origins.contains(origin)
+    }
+
+    fn add_origin_headers(&self, headers: &mut Headers, origin: HeaderValue) {
+        if self.allowed_origins.is_empty() && !self.allow_credentials {
+            headers.set(
+                HeaderName::new_unchecked("access-control-allow-origin"),
+                HeaderValue::new_unchecked("*".as_bytes()),
+            );
+        } else {
+            // The response depends on the origin
+            headers.set(
+                HeaderName::new_unchecked("access-control-allow-origin"),
+                origin,
+            );
+            headers.append(
+                HeaderName::VARY,
+                HeaderValue::new_unchecked("origin".as_bytes()),
+            );
+        }
+        if self.allow_credentials {
+            headers.set(
+                HeaderName::new_unchecked("access-control-allow-credentials"),
+                HeaderValue::new_unchecked("true".as_bytes()),
+            );
+        }
+    }
+}
+
+fn join_header_values<'a>(values: impl IntoIterator<Item = &'a str>) -> HeaderValue {
+    HeaderValue::new_unchecked(
+        values
+            .into_iter()
+            .collect::<Vec<_>>()
+            .join(", ")
+            .into_bytes(),
+    )
+}
+
+type ConnectionInit = dyn Fn(&mut TcpStream) -> Result<ConnectionContext> + Send + Sync;
+type AccessLog = dyn Fn(&AccessLogEntry) + Send + Sync;
+type OnResponse = dyn Fn(&Request, &Response, Duration) + Send + Sync;
+type InterimResponses = dyn Fn(&Request) -> Vec<Response> + Send + Sync;
+type RequestHandler = dyn Fn(&mut Request) -> Response + Send + Sync;
+type HeaderSizeWarning = dyn Fn(u64, SocketAddr) + Send + Sync;
+type ErrorRenderer = dyn Fn(Status, &str, Option<&Headers>) -> Response + Send + Sync;
+
+/// TLS implementation used to secure the connections
+#[derive(Clone)]
+enum ServerTls {
+    #[cfg(feature = "rustls")]
+    Rustls(Arc<ServerConfig>),
+    #[cfg(feature = "native-tls")]
+    NativeTls(TlsAcceptor),
+}
+
+impl ServerTls {
+    /// Performs the TLS handshake and returns a reader and a writer for the secured stream
+    #[cfg_attr(
+        not(any(feature = "native-tls", feature = "rustls")),
+        allow(unused_variables)
+    )]
+    fn accept(&self, stream: TcpStream) -> Result<(Box<dyn Read>, Box<dyn Write>)> {
+        match *self {
+            #[cfg(feature = "rustls")]
+            Self::Rustls(ref config) => {
+                let mut stream = stream;
+                let mut connection =
+                    ServerConnection::new(Arc::clone(config)).map_err(Error::other)?;
+                while connection.is_handshaking() {
+                    connection.complete_io(&mut stream)?;
+                }
+                Ok(split_tls_stream(StreamOwned::new(connection, stream)))
+            }
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref acceptor) => Ok(split_tls_stream(
+                acceptor.accept(stream).map_err(Error::other)?,
+            )),
+        }
+    }
+}
+
+/// A TLS stream that notifies the client when closed
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+trait TlsStream: Read + Write {
+    fn close(&mut self) -> Result<()>;
+}
+
+#[cfg(feature = "rustls")]
+impl TlsStream for StreamOwned<ServerConnection, TcpStream> {
+    fn close(&mut self) -> Result<()> {
+        self.conn.send_close_notify();
+        self.flush()
+    }
+}
+
+#[cfg(feature = "native-tls")]
+impl TlsStream for native_tls::TlsStream<TcpStream> {
+    fn close(&mut self) -> Result<()> {
+        self.shutdown()
+    }
+}
+
+/// Splits a TLS stream into a reader and a writer
+///
+/// TLS streams can't be cloned like [`TcpStream`] so both halves share the stream.
+/// It is fine because the connection is only used by a single thread.
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+fn split_tls_stream(stream: impl TlsStream + 'static) -> (Box<dyn Read>, Box<dyn Write>) {
+    let stream = Rc::new(RefCell::new(stream));
+    (
+        Box::new(TlsReader(Rc::clone(&stream))),
+        Box::new(TlsWriter(stream)),
+    )
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+struct TlsReader<S: TlsStream>(Rc<RefCell<S>>);
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl<S: TlsStream> Read for TlsReader<S> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+/// Writer half of a TLS stream, closing the TLS session when dropped
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+struct TlsWriter<S: TlsStream>(Rc<RefCell<S>>);
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl<S: TlsStream> Write for TlsWriter<S> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl<S: TlsStream> Drop for TlsWriter<S> {
+    fn drop(&mut self) {
+        let _ = self.0.borrow_mut().close();
+    }
+}
+
+/// Settings shared by all the connections of a server.
+struct ConnectionSettings {
+    on_request: Arc<dyn Fn(&mut Request) -> Response + Send + Sync + 'static>,
+    timeout: Option<Duration>,
+    server: Option<HeaderValue>,
+    keep_alive_timeout: Option<Duration>,
+    max_requests_per_connection: Option<usize>,
+    connections: ConnectionTracker,
+    framing_header_position: FramingHeaderPosition,
+    max_connections_per_ip: Option<usize>,
+    thread_stack_size: Option<usize>,
+    thread_name_prefix: Option<String>,
+    max_header_size: u64,
+    chunk_size: usize,
+    buffer_capacity: usize,
+    body_budget: Option<Arc<BodyBudget>>,
+    connection_init: Option<Arc<ConnectionInit>>,
+    tls: Option<ServerTls>,
+    access_log: Option<Arc<AccessLog>>,
+    on_response: Option<Arc<OnResponse>>,
+    interim_responses: Option<Arc<InterimResponses>>,
+    header_size_warning: Option<(u64, Arc<HeaderSizeWarning>)>,
+    error_renderer: Option<Arc<ErrorRenderer>>,
+    catch_panics: bool,
+    normalize_path: bool,
+}
+
+impl ConnectionSettings {
+    /// Builds the response to an error raised by the server itself
+    fn build_error(&self, error: Error, request_headers: Option<&Headers>) -> Response {
+        self.build_error_response(error_status(&error), error.to_string(), request_headers)
+    }
+
+    fn build_error_response(
+        &self,
+        status: Status,
+        message: String,
+        request_headers: Option<&Headers>,
+    ) -> Response {
+        match &self.error_renderer {
+            Some(error_renderer) => error_renderer(status, &message, request_headers),
+            None => build_text_response(status, message),
+        }
+    }
+
+    fn thread_builder(&self, name: String) -> Builder {
+        let mut builder = Builder::new().name(match &self.thread_name_prefix {
+            Some(prefix) => format!("{prefix}{name}"),
+            None => name,
+        });
+        if let Some(stack_size) = self.thread_stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        builder
+    }
+}
+
+/// Keeps track of the open connections to allow a graceful shutdown.
+#[derive(Default)]
+struct ConnectionTracker {
+    state: Mutex<ConnectionTrackerState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct ConnectionTrackerState {
+    is_shutting_down: bool,
+    next_id: u64,
+    connections: HashMap<u64, TrackedConnection>,
+    connections_per_ip: HashMap<IpAddr, usize>,
+    /// Number of connections closed since the shutdown started
+    closed_during_shutdown: usize,
+}
+
+struct TrackedConnection {
+    stream: TcpStream,
+    ip: IpAddr,
+    is_idle: bool,
+}
+
+impl ConnectionTracker {
+    /// Registers a new connection
+    fn register(&self, stream: &TcpStream) -> Result<Registration<'_>> {
+        let ip = stream.peer_addr()?.ip();
+        let mut state = self.state.lock().unwrap();
+        if state.is_shutting_down {
+            return Ok(Registration::ShuttingDown);
+        }
+        *state.connections_per_ip.entry(ip).or_default() += 1;
+        let id = state.next_id;
+        state.next_id += 1;
+        state.connections.insert(
+            id,
+            TrackedConnection {
+                stream: stream.try_clone()?,
+                ip,
+                is_idle: true,
+            },
+        );
+        Ok(Registration::Registered(ConnectionGuard {
+            tracker: self,
+            id,
+        }))
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.state.lock().unwrap().is_shutting_down
+    }
+
+    /// Sets the shutdown flag and closes the idle connections
+    fn start_shutdown(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.is_shutting_down = true;
+        for connection in state.connections.values() {
+            if connection.is_idle {
+                let _ = connection.stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Waits for the connections to close until the deadline and then forcibly closes the remaining ones.
+    ///
+    /// Returns the number of drained and forcibly closed connections.
+    fn wait_until_closed(&self, deadline: Instant) -> (usize, usize) {
+        let mut state = self.state.lock().unwrap();
+        while !state.connections.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self.condvar.wait_timeout(state, deadline - now).unwrap().0;
+        }
+        for connection in state.connections.values() {
+            let _ = connection.stream.shutdown(Shutdown::Both);
+        }
+        (state.closed_during_shutdown, state.connections.len())
+    }
+}
+
+enum Registration<'a> {
+    Registered(ConnectionGuard<'a>),
+    ShuttingDown,
+}
+
+/// Unregisters its connection from the [`ConnectionTracker`] when dropped.
+struct ConnectionGuard<'a> {
+    tracker: &'a ConnectionTracker,
+    id: u64,
+}
+
+impl ConnectionGuard<'_> {
+    /// Sets the IP address the connection is counted for, returns `false` if there are already too many connections from it
+    fn set_ip(&self, ip: IpAddr, max_connections_per_ip: Option<usize>) -> bool {
+        let mut state = self.tracker.state.lock().unwrap();
+        let Some(connection) = state.connections.get_mut(&self.id) else {
+            return true;
+        };
+        let previous_ip = std::mem::replace(&mut connection.ip, ip);
+        if previous_ip != ip {
+            if let Some(count) = state.connections_per_ip.get_mut(&previous_ip) {
+                *count -= 1;
+                if *count == 0 {
+                    state.connections_per_ip.remove(&previous_ip);
+                }
+            }
+            *state.connections_per_ip.entry(ip).or_default() += 1;
+        }
+        max_connections_per_ip.map_or(true, |max| state.connections_per_ip[&ip] <= max)
+    }
+
+    /// Marks the connection as waiting for a new request, returns `false` if the connection should be closed instead
+    fn set_idle(&self) -> bool {
+        let mut state = self.tracker.state.lock().unwrap();
+        if state.is_shutting_down {
+            return false;
+        }
+        if let Some(connection) = state.connections.get_mut(&self.id) {
+            connection.is_idle = true;
+        }
+        true
+    }
+
+    fn set_busy(&self) {
+        if let Some(connection) = self
+            .tracker
+            .state
+            .lock()
+            .unwrap()
+            .connections
+            .get_mut(&self.id)
+        {
+            connection.is_idle = false;
+        }
+    }
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.tracker.state.lock().unwrap();
+        if let Some(connection) = state.connections.remove(&self.id) {
+            if let Some(count) = state.connections_per_ip.get_mut(&connection.ip) {
+                *count -= 1;
+                if *count == 0 {
+                    state.connections_per_ip.remove(&connection.ip);
+                }
+            }
+            if state.is_shutting_down {
+                state.closed_during_shutdown += 1;
+            }
+        }
+        self.tracker.condvar.notify_all();
+    }
+}
+
+fn accept_request(mut stream: TcpStream, settings: &ConnectionSettings) -> Result<()> {
+    let connection = match settings.connections.register(&stream)? {
+        Registration::Registered(connection) => connection,
+        Registration::ShuttingDown => return Ok(()),
+    };
+    stream.set_read_timeout(settings.timeout)?;
+    stream.set_write_timeout(settings.timeout)?;
+    let context = if let Some(init) = &settings.connection_init {
+        init(&mut stream)?
+    } else {
+        ConnectionContext::new(stream.peer_addr()?)
+    };
+    // The limit applies to the client address given by the connection initialization, e.g. by the PROXY protocol header
+    if !connection.set_ip(context.peer_addr().ip(), settings.max_connections_per_ip) {
+        if settings.tls.is_some() {
+            return Ok(()); // We can't answer before the TLS handshake
+        }
+        let mut connection_headers = Headers::new();
+        connection_headers.set(
+            HeaderName::CONNECTION,
+            HeaderValue::new_unchecked("close".as_bytes()),
+        );
+        encode_response_with_options(
+            &mut settings.build_error_response(
+                Status::TOO_MANY_REQUESTS,
+                "Too many concurrent connections from the same IP address".into(),
+                None,
+            ),
+            &connection_headers,
+            settings.framing_header_position,
+            false,
+            settings.chunk_size,
+            None,
+            BufWriter::with_capacity(settings.buffer_capacity, &stream),
+        )?
+        .flush()?;
+        return stream.shutdown(Shutdown::Write);
+    }
+    let (reader, writer): (Box<dyn Read>, Box<dyn Write>) = match &settings.tls {
+        Some(tls) => tls.accept(stream.try_clone()?)?,
+        None => (Box::new(stream.try_clone()?), Box::new(stream.try_clone()?)),
+    };
+    let mut writer = CountingWriter {
+        inner: writer,
+        count: 0,
+    };
+    let mut connection_state = ConnectionState::KeepAlive;
+    let mut served_requests = 0;
+    // The reader is kept between requests to not lose the pipelined requests it has already buffered
+    let mut next_reader = Some(BufReader::with_capacity(settings.buffer_capacity, reader));
+    while connection_state == ConnectionState::KeepAlive {
+        if !connection.set_idle() {
+            return Ok(()); // The server is shutting down
+        }
+        let Some(mut reader) = next_reader.take() else {
+            return Ok(());
+        };
+        let is_idle = served_requests > 0 && settings.keep_alive_timeout.is_some();
+        if is_idle {
+            stream.set_read_timeout(settings.keep_alive_timeout)?;
+        }
+        let request = if let Some((soft_limit, on_warning)) = &settings.header_size_warning {
+            let mut counting_reader = CountingBufReader {
+                inner: &mut reader,
+                count: 0,
+            };
+            let request = decode_request_head(
+                &mut counting_reader,
+                settings.tls.is_some(),
+                settings.max_header_size,
+                settings.normalize_path,
+            );
+            if request.is_ok() && counting_reader.count > *soft_limit {
+                on_warning(counting_reader.count, context.peer_addr());
+            }
+            request
+        } else {
+            decode_request_head(
+                &mut reader,
+                settings.tls.is_some(),
+                settings.max_header_size,
+                settings.normalize_path,
+            )
+        };
+        connection.set_busy();
+        let start = Instant::now();
+        if is_idle {
+            stream.set_read_timeout(settings.timeout)?;
+        }
+        let request_line = settings.access_log.as_ref().and_then(|_| {
+            let request = request.as_ref().ok()?;
+            Some((request.method().clone(), request.url().clone()))
+        });
+        // The request head is kept for the response hook, the body is consumed by on_request
+        let request_head = settings
+            .on_response
+            .as_ref()
+            .and_then(|_| Some(request_head(request.as_ref().ok()?, &context)));
+        // HTTP/1.0 clients need to be told that the connection is kept alive
+        let asks_keep_alive = request.as_ref().is_ok_and(|request| {
+            request
+                .header(&HeaderName::CONNECTION)
+                .is_some_and(|connection| connection.contains_token(b"keep-alive"))
+        });
+        let send_trailers = request.as_ref().is_ok_and(|request| {
+            request
+                .header(&HeaderName::TE)
+                .is_some_and(does_accept_trailers)
+        });
+        let (mut response, new_connection_state) = match request {
+            Ok(request) => {
+                // Handles Expect header
+                if let Some(expect) = request.header(&HeaderName::EXPECT).cloned() {
+                    if expect.contains_token(b"100-continue") {
+                        if let Err(error) =
+                            write_interim_responses(&request, settings, &context, &mut writer)
+                        {
+                            if error.kind() != ErrorKind::InvalidInput {
+                                return Err(error);
+                            }
+                            eprintln!("OxHTTP invalid interim response: {error}");
+                            (
+                                settings.build_error_response(
+                                    Status::INTERNAL_SERVER_ERROR,
+                                    "The server failed to handle the request".into(),
+                                    Some(request.headers()),
+                                ),
+                                ConnectionState::Close,
+                            )
+                        } else {
+                            writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+                            read_body_and_build_response(
+                                request,
+                                reader,
+                                settings,
+                                &context,
+                                &mut next_reader,
+                            )
+                        }
+                    } else {
+                        (
+                            settings.build_error_response(
+                                Status::EXPECTATION_FAILED,
+                                format!(
+                                    "Expect header value '{}' is not supported.",
+                                    String::from_utf8_lossy(expect.as_ref())
+                                ),
+                                Some(request.headers()),
+                            ),
+                            ConnectionState::Close,
+                        )
+                    }
+                } else {
+                    read_body_and_build_response(
+                        request,
+                        reader,
+                        settings,
+                        &context,
+                        &mut next_reader,
+                    )
+                }
+            }
+            Err(error) => {
+                if error.kind() == ErrorKind::ConnectionAborted {
+                    return Ok(()); // The client is disconnected. Let's ignore this error and do not try to write an answer that won't be received.
+                } else if is_idle
+                    && matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+                {
+                    return Ok(()); // The keep-alive timeout is reached, we close the connection
+                } else {
+                    (settings.build_error(error, None), ConnectionState::Close)
+                }
+            }
+        };
+        connection_state = new_connection_state;
+        served_requests += 1;
+        if settings
+            .max_requests_per_connection
+            .is_some_and(|max| served_requests >= max)
+        {
+            connection_state = ConnectionState::Close;
+        }
+
+        // Additional headers
+        if let Some(server) = &settings.server {
+            if !response.headers().contains(&HeaderName::SERVER) {
+                response
+                    .headers_mut()
+                    .set(HeaderName::SERVER, server.clone())
+            }
+        }
+
+        let mut connection_headers = Headers::new();
+        if connection_state == ConnectionState::KeepAlive && settings.connections.is_shutting_down()
+        {
+            connection_state = ConnectionState::Close;
+            connection_headers.set(
+                HeaderName::CONNECTION,
+                HeaderValue::new_unchecked("close".as_bytes()),
+            );
+        }
+        if connection_state == ConnectionState::KeepAlive {
+            if asks_keep_alive {
+                connection_headers.set(
+                    HeaderName::CONNECTION,
+                    HeaderValue::new_unchecked("keep-alive".as_bytes()),
+                );
+            }
+            if let Some(keep_alive) = keep_alive_header_value(settings, served_requests) {
+                connection_headers.set(HeaderName::new_unchecked("keep-alive"), keep_alive);
+            }
+        }
+
+        writer.count = 0;
+        // File bodies are sent without copy to plain TCP connections
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let mut sent_from_file = 0;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let mut send_file = |file: &File, len: u64| {
+            let sent = sendfile(&stream, file, len)?;
+            sent_from_file += sent;
+            Ok(sent)
+        };
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let send_file: Option<&mut SendFile<'_>> = if settings.tls.is_none() {
+            Some(&mut send_file)
+        } else {
+            None
+        };
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let send_file = None;
+        writer = encode_response_with_options(
+            &mut response,
+            &connection_headers,
+            settings.framing_header_position,
+            send_trailers,
+            settings.chunk_size,
+            send_file,
+            BufWriter::with_capacity(settings.buffer_capacity, writer),
+        )?
+        .into_inner()
+        .map_err(|e| e.into_error())?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            writer.count += sent_from_file;
+        }
+        if let Some(access_log) = &settings.access_log {
+            let (method, url) = request_line.unzip();
+            access_log(&AccessLogEntry {
+                peer_addr: context.peer_addr(),
+                method,
+                url,
+                status: response.status(),
+                bytes_written: writer.count,
+            });
+        }
+        if let (Some(on_response), Some(request)) = (&settings.on_response, &request_head) {
+            on_response(request, &response, start.elapsed());
+        }
+    }
+    Ok(())
+}
+
+/// Copies up to `len` bytes from the current position of `file` to `socket`
+/// with [`sendfile(2)`](https://man7.org/linux/man-pages/man2/sendfile.2.html), without going through userspace.
+///
+/// Returns `0` if the file can't be sent this way.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[allow(unsafe_code)]
+fn sendfile(socket: &TcpStream, file: &File, len: u64) -> Result<u64> {
+    // Linux transfers at most 0x7ffff000 bytes per call
+    let count = usize::try_from(len).unwrap_or(usize::MAX).min(0x7fff_f000);
+    loop {
+        // SAFETY: the file descriptors are owned by `socket` and `file` that outlive the call.
+        // The offset is null: the file position is used and updated.
+        let sent =
+            unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), ptr::null_mut(), count) };
+        if let Ok(sent) = u64::try_from(sent) {
+            return Ok(sent);
+        }
+        let error = Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::EINTR) => (),
+            Some(libc::EINVAL | libc::ENOSYS) => return Ok(0),
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Reader counting the number of consumed bytes
+struct CountingBufReader<R: BufRead> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: BufRead> Read for CountingBufReader<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += u64::try_from(read).unwrap();
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingBufReader<R> {
+    #[inline]
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.count += u64::try_from(amt).unwrap();
+        self.inner.consume(amt);
+    }
+}
+
+/// Writer counting the number of written bytes
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += u64::try_from(written).unwrap();
+        Ok(written)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Checks if the [`TE`](https://httpwg.org/http-core/draft-ietf-httpbis-semantics-latest.html#field.te) header value contains `trailers`
+fn does_accept_trailers(te: &HeaderValue) -> bool {
+    te.to_str().is_ok_and(|te| {
+        te.split(',')
+            .any(|coding| coding.trim().eq_ignore_ascii_case("trailers"))
+    })
+}
+
+/// Builds the [`Keep-Alive`](https://datatracker.ietf.org/doc/html/rfc2068#section-19.7.1.1) header value advertising the connection limits
+fn keep_alive_header_value(
+    settings: &ConnectionSettings,
+    served_requests: usize,
+) -> Option<HeaderValue> {
+    let mut parameters = Vec::new();
+    // The timeout is rounded down to not announce a longer one and is omitted if shorter than a second
+    if let Some(timeout) = settings
+        .keep_alive_timeout
+        .filter(|timeout| timeout.as_secs() > 0)
+    {
+        parameters.push(format!("timeout={}", timeout.as_secs()));
+    }
+    if let Some(max_requests) = settings.max_requests_per_connection {
+        parameters.push(format!("max={}", max_requests - served_requests));
+    }
+    if parameters.is_empty() {
+        return None;
+    }
+    Some(HeaderValue::new_unchecked(
+        parameters.join(", ").into_bytes(),
+    ))
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum ConnectionState {
+    Close,
+    KeepAlive,
+}
+
+/// Gives back the reader in `next_reader` if the request body has been fully read and dropped
+fn read_body_and_build_response(
+    request: RequestBuilder,
+    reader: BufReader<Box<dyn Read>>,
+    settings: &ConnectionSettings,
+    context: &ConnectionContext,
+    next_reader: &mut Option<BufReader<Box<dyn Read>>>,
+) -> (Response, ConnectionState) {
+    let (slot, reader) = ReaderSlot::lend(reader);
+    // The headers are needed to render the body decoding errors
+    let request_headers = settings
+        .error_renderer
+        .as_ref()
+        .map(|_| request.headers().clone());
+    match decode_request_body(request, reader, settings.max_header_size) {
+        Ok(mut request) => {
+            request.set_connection(context.clone());
+            if let Some(budget) = &settings.body_budget {
+                if let Err(error) = apply_body_budget(&mut request, budget) {
+                    return (
+                        settings.build_error(error, Some(request.headers())),
+                        ConnectionState::Close,
+                    );
+                }
+            }
+            let response = if settings.catch_panics {
+                match catch_unwind(AssertUnwindSafe(|| (settings.on_request)(&mut request))) {
+                    Ok(response) => response,
+                    Err(panic) => {
+                        eprintln!(
+                            "OxHTTP request handler panicked: {}",
+                            panic_message(&*panic)
+                        );
+                        return (
+                            settings.build_error_response(
+                                Status::INTERNAL_SERVER_ERROR,
+                                "The server failed to handle the request".into(),
+                                Some(request.headers()),
+                            ),
+                            ConnectionState::Close,
+                        );
+                    }
+                }
+            } else {
+                (settings.on_request)(&mut request)
+            };
+            // We make sure to finish reading the body if it is not too large
+            match copy(
+                &mut request.body_mut().take(MAX_DRAINED_BODY_SIZE + 1),
+                &mut sink(),
+            ) {
+                Err(error) => (
+                    settings.build_error(error, Some(request.headers())),
+                    ConnectionState::Close,
+                ), //TODO: ignore?
+                Ok(drained) if drained > MAX_DRAINED_BODY_SIZE => {
+                    // The remaining body is too large, we close the connection instead of reading it
+                    (response, ConnectionState::Close)
+                }
+                Ok(_) => {
+                    let mut connection_state = request
+                        .header(&HeaderName::CONNECTION)
+                        .and_then(|v| v.contains_token(b"close").then_some(ConnectionState::Close))
+                        .unwrap_or(ConnectionState::KeepAlive);
+                    drop(request);
+                    *next_reader = slot.take();
+                    if next_reader.is_none() {
+                        // The request body has been kept by on_request, we can't read the next request
+                        connection_state = ConnectionState::Close;
+                    }
+                    (response, connection_state)
+                }
+            }
+        }
+        Err(error) => (
+            settings.build_error(error, request_headers.as_ref()),
+            ConnectionState::Close,
+        ),
+    }
+}
+
+/// Copy of the request head with an empty body
+fn request_head(request: &RequestBuilder, context: &ConnectionContext) -> Request {
+    let mut head = Request::builder(request.method().clone(), request.url().clone());
+    if request.is_asterisk_form() {
+        head = head.with_asterisk_form();
+    }
+    let mut head = head.build();
+    *head.headers_mut() = request.headers().clone();
+    head.set_connection(context.clone());
+    head
+}
+
+/// Writes the interim responses returned by the function set with [`ServerBuilder::with_interim_responses`]
+///
+/// Fails with an [`ErrorKind::InvalidInput`] error if one of them does not have an allowed status.
+fn write_interim_responses(
+    request: &RequestBuilder,
+    settings: &ConnectionSettings,
+    context: &ConnectionContext,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let Some(interim_responses) = &settings.interim_responses else {
+        return Ok(());
+    };
+    for response in interim_responses(&request_head(request, context)) {
+        encode_interim_response(&response, &mut *writer)?;
+    }
+    Ok(())
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown error"
+    }
+}
+
+fn error_status(error: &Error) -> Status {
+    match error.kind() {
+        ErrorKind::TimedOut => Status::REQUEST_TIMEOUT,
+        ErrorKind::InvalidData => Status::BAD_REQUEST,
+        ErrorKind::Unsupported => Status::HTTP_VERSION_NOT_SUPPORTED,
+        ErrorKind::OutOfMemory => Status::SERVICE_UNAVAILABLE,
+        _ => Status::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn build_text_response(status: Status, text: String) -> Response {
+    Response::builder(status)
+        .with_header(HeaderName::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .unwrap()
+        .with_body(text)
+}
+
+/// Wraps the request body to account for it in the global body budget.
+///
+/// Fails with an [`OutOfMemory`](ErrorKind::OutOfMemory) error if the body does not fit in the budget.
+fn apply_body_budget(request: &mut Request, budget: &Arc<BodyBudget>) -> Result<()> {
+    let len = request.body().len();
+    if len == Some(0) {
+        return Ok(());
+    }
+    let Some(reservation) = budget.try_reserve(len.unwrap_or(0)) else {
+        return Err(Error::new(
+            ErrorKind::OutOfMemory,
+            "The server is already receiving too much data, please retry later",
+        ));
+    };
+    let body = BudgetedBody {
+        inner: std::mem::take(request.body_mut()),
+        reservation,
+        is_reserved: len.is_some(),
+    };
+    *request.body_mut() = match len {
+        Some(len) => Body::from_read_and_len(body, len),
+        None => Body::from_chunked_transfer_payload(body),
+    };
+    Ok(())
+}
+
+/// Number of request body bytes that can be received at the same time by the server
+struct BodyBudget {
+    capacity: u64,
+    used: Mutex<u64>,
+}
+
+impl BodyBudget {
+    fn new(capacity: u64) -> Arc<Self> {
+        Arc::new(Self {
+            capacity,
+            used: Mutex::new(0),
+        })
+    }
+
+    fn try_reserve(self: &Arc<Self>, amount: u64) -> Option<BodyBudgetReservation> {
+        let mut reservation = BodyBudgetReservation {
+            budget: Arc::clone(self),
+            amount: 0,
+        };
+        reservation.try_grow(amount).then_some(reservation)
+    }
+}
+
+/// Part of the [`BodyBudget`] released when dropped
+struct BodyBudgetReservation {
+    budget: Arc<BodyBudget>,
+    amount: u64,
+}
+
+impl BodyBudgetReservation {
+    fn try_grow(&mut self, amount: u64) -> bool {
+        let mut used = self.budget.used.lock().unwrap();
+        if used.saturating_add(amount) > self.budget.capacity {
+            return false;
+        }
+        *used += amount;
+        self.amount += amount;
+        true
+    }
+}
+
+impl Drop for BodyBudgetReservation {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.amount;
+    }
+}
+
+/// Request body accounted for in the [`BodyBudget`]
+struct BudgetedBody {
+    inner: Body,
+    reservation: BodyBudgetReservation,
+    /// If the full body size is already reserved
+    is_reserved: bool,
+}
+
+impl BudgetedBody {
+    fn account(&mut self, len: usize) -> Result<()> {
+        if !self.is_reserved
+            && !self
+                .reservation
+                .try_grow(u64::try_from(len).unwrap_or(u64::MAX))
+        {
+            return Err(Error::new(
+                ErrorKind::OutOfMemory,
+                "The server is already receiving too much data",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Read for BudgetedBody {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.account(read)?;
+        Ok(read)
+    }
+}
+
+impl ChunkedTransferPayload for BudgetedBody {
+    fn trailers(&self) -> Option<&Headers> {
+        self.inner.trailers()
+    }
+
+    fn read_chunk(&mut self, max_size: usize) -> Result<Option<Vec<u8>>> {
+        // We keep the chunk boundaries of the inner body
+        let chunk = self.inner.read_chunk(max_size)?;
+        if let Some(chunk) = &chunk {
+            self.account(chunk.len())?;
+        }
+        Ok(chunk)
+    }
+}
+
+/// Dumb semaphore allowing to overflow capacity
+#[derive(Clone)]
+struct Semaphore {
+    inner: Arc<InnerSemaphore>,
+}
+
+struct InnerSemaphore {
+    count: Mutex<usize>,
+    capacity: usize,
+    condvar: Condvar,
+}
+
+impl Semaphore {
+    fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(InnerSemaphore {
+                count: Mutex::new(0),
+                capacity,
+                condvar: Condvar::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> SemaphoreGuard {
+        let data = &self.inner;
+        *data
+            .condvar
+            .wait_while(data.count.lock().unwrap(), |count| *count >= data.capacity)
+            .unwrap() += 1;
+        SemaphoreGuard {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+struct SemaphoreGuard {
+    inner: Arc<InnerSemaphore>,
+}
+
+impl Drop for SemaphoreGuard {
+    fn drop(&mut self) {
+        let data = &self.inner;
+        *data.count.lock().unwrap() -= 1;
+        data.condvar.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Body, ChunkedTransferPayload, Status};
+    use std::io::Read;
+    use std::thread::{sleep, spawn};
+
+    #[test]
+    fn test_regular_http_operations() -> Result<()> {
+        test_server("localhost", 9999, [
+            "GET / HTTP/1.1\nhost: localhost:9999\n\n",
+            "POST /foo HTTP/1.1\nhost: localhost:9999\nexpect: 100-continue\nconnection:close\ncontent-length:4\n\nabcd",
+        ], [
+            "HTTP/1.1 200 OK\r\nserver: OxHTTP/1.0\r\ncontent-length: 4\r\n\r\nhome",
+            "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 404 Not Found\r\nserver: OxHTTP/1.0\r\ncontent-length: 0\r\n\r\n"
+        ])
+    }
+
+    #[test]
+    fn test_bad_request() -> Result<()> {
+        test_server(
+            "::1", 9998,
+            ["GET / HTTP/1.1\nhost: localhost:9999\nfoo\n\n"],
+            ["HTTP/1.1 400 Bad Request\r\ncontent-type: text/plain; charset=utf-8\r\nserver: OxHTTP/1.0\r\ncontent-length: 19\r\n\r\ninvalid header name"],
+        )
+    }
+
+    #[test]
+    fn test_bad_expect() -> Result<()> {
+        test_server(
+            "127.0.0.1", 9997,
+            ["GET / HTTP/1.1\nhost: localhost:9999\nexpect: bad\n\n"],
+            ["HTTP/1.1 417 Expectation Failed\r\ncontent-type: text/plain; charset=utf-8\r\nserver: OxHTTP/1.0\r\ncontent-length: 43\r\n\r\nExpect header value 'bad' is not supported."],
+        )
+    }
+
+    #[test]
+    fn test_keep_alive_header() -> Result<()> {
+        let server_port = 9994;
+        let request = b"GET / HTTP/1.1\nhost: localhost:9994\n\n";
+        Server::builder(|_| Response::builder(Status::OK).with_body("home"))
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_keep_alive_timeout(Duration::from_secs(5))
+            .with_max_requests_per_connection(2)
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        for expected in [
+            "HTTP/1.1 200 OK\r\nkeep-alive: timeout=5, max=1\r\ncontent-length: 4\r\n\r\nhome",
+            "HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nhome",
+        ] {
+            stream.write_all(request)?;
+            let mut output = vec![b'\0'; expected.len()];
+            stream.read_exact(&mut output)?;
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+        // The maximal number of requests is reached, the server closes the connection
+        assert_eq!(stream.read(&mut [0; 1])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_http_1_0_keep_alive() -> Result<()> {
+        let server = Server::builder(|_| Response::builder(Status::OK).with_body("home"))
+            .listen(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?)
+            .build()?
+            .spawn()?;
+        let server_addr = server.local_addrs()[0];
+
+        // The connection is kept alive when asked
+        let mut stream = TcpStream::connect(server_addr)?;
+        for _ in 0..2 {
+            stream.write_all(b"GET / HTTP/1.0\nhost: localhost\nconnection: Keep-Alive\n\n")?;
+            let expected =
+                "HTTP/1.1 200 OK\r\nconnection: keep-alive\r\ncontent-length: 4\r\n\r\nhome";
+            let mut output = vec![b'\0'; expected.len()];
+            stream.read_exact(&mut output)?;
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+
+        // It is closed by default
+        for request in [
+            "GET / HTTP/1.0\nhost: localhost\n\n",
+            "GET / HTTP/1.0\nhost: localhost\nconnection: te\n\n",
+        ] {
+            let mut stream = TcpStream::connect(server_addr)?;
+            stream.write_all(request.as_bytes())?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert_eq!(response, "HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nhome");
+        }
+        server.shutdown_graceful(Duration::from_secs(1))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_alive_timeout() -> Result<()> {
+        let server_port = 9993;
+        let request = b"GET / HTTP/1.1\nhost: localhost:9993\n\n";
+        // A zero second timeout is not announced
+        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nhome";
+        Server::builder(|_| Response::builder(Status::OK).with_body("home"))
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_keep_alive_timeout(Duration::from_millis(100))
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(request)?;
+        let mut output = vec![b'\0'; response.len()];
+        stream.read_exact(&mut output)?;
+        assert_eq!(output, response);
+        // The connection is closed without response once idle for too long
+        assert_eq!(stream.read(&mut [0; 1])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cors_preflight() -> Result<()> {
+        test_cors_server(
+            9992,
+            [
+                "OPTIONS /foo HTTP/1.1\nhost: localhost:9992\norigin: https://example.com\naccess-control-request-method: PUT\naccess-control-request-headers: content-type\n\n",
+                "OPTIONS /foo HTTP/1.1\nhost: localhost:9992\norigin: https://example.org\naccess-control-request-method: PUT\n\n",
+            ],
+            [
+                "HTTP/1.1 204 No Content\r\naccess-control-allow-credentials: true\r\naccess-control-allow-headers: content-type\r\naccess-control-allow-methods: GET, PUT\r\naccess-control-allow-origin: https://example.com\r\naccess-control-max-age: 60\r\nvary: origin\r\n\r\n",
+                "HTTP/1.1 403 Forbidden\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: 47\r\n\r\nThe origin 'https://example.org' is not allowed",
+            ],
+        )
+    }
+
+    #[test]
+    fn test_cors_simple_request() -> Result<()> {
+        test_cors_server(
+            9991,
+            [
+                "GET / HTTP/1.1\nhost: localhost:9991\norigin: https://example.com\n\n",
+                "GET / HTTP/1.1\nhost: localhost:9991\n\n",
+            ],
+            [
+                "HTTP/1.1 200 OK\r\naccess-control-allow-credentials: true\r\naccess-control-allow-origin: https://example.com\r\nvary: origin\r\ncontent-length: 4\r\n\r\nhome",
+                "HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nhome",
+            ],
+        )
+    }
+
+    #[test]
+    fn test_framing_header_first() -> Result<()> {
+        let server_port = 9988;
+        Server::builder(|_| {
+            Response::builder(Status::OK)
+                .with_header(HeaderName::CONTENT_TYPE, "text/plain")
+                .unwrap()
+                .with_body("home")
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
+        .with_framing_header_first()
+        .build()?
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(b"GET / HTTP/1.1\nhost: localhost:9988\nconnection: close\n\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\ncontent-length: 4\r\ncontent-type: text/plain\r\n\r\nhome"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_connections_per_ip() -> Result<()> {
+        let server_port = 9987;
+        let request = b"GET / HTTP/1.1\nhost: localhost:9987\n\n";
+        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nhome";
+        Server::builder(|_| Response::builder(Status::OK).with_body("home"))
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_max_connections_per_ip(2)
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let connect = || -> Result<TcpStream> {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+            stream.write_all(request)?;
+            let mut output = vec![b'\0'; response.len()];
+            stream.read_exact(&mut output)?;
+            assert_eq!(output, response);
+            Ok(stream)
+        };
+        let first = connect()?;
+        let _second = connect()?;
+        let mut rejected = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        let mut output = String::new();
+        rejected.read_to_string(&mut output)?;
+        assert_eq!(output, "HTTP/1.1 429 Too Many Requests\r\ncontent-type: text/plain; charset=utf-8\r\nconnection: close\r\ncontent-length: 56\r\n\r\nToo many concurrent connections from the same IP address");
+        // A slot is freed when a connection is closed
+        drop(first);
+        sleep(Duration::from_millis(100));
+        connect()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_shutdown_graceful() -> Result<()> {
+        let server_port = 9990;
+        let server = Server::new(|_| {
+            sleep(Duration::from_millis(300));
+            Response::builder(Status::OK).with_body("done")
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let client = spawn(move || -> Result<String> {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+            stream.write_all(b"GET / HTTP/1.1\nhost: localhost:9990\n\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        });
+        sleep(Duration::from_millis(100)); // Makes sure the request is being processed
+        let summary = server.shutdown_graceful(Duration::from_secs(5))?;
+        assert_eq!(summary.drained(), 1);
+        assert_eq!(summary.forced(), 0);
+        assert_eq!(
+            client.join().unwrap()?,
+            "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 4\r\n\r\ndone"
+        );
+        assert!(TcpStream::connect((Ipv4Addr::LOCALHOST, server_port)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_shutdown_graceful_deadline() -> Result<()> {
+        let server_port = 9989;
+        let server = Server::new(|_| {
+            sleep(Duration::from_secs(1));
+            Response::builder(Status::OK).with_body("done")
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(b"GET / HTTP/1.1\nhost: localhost:9989\n\n")?;
+        sleep(Duration::from_millis(100)); // Makes sure the request is being processed
+        let summary = server.shutdown_graceful(Duration::from_millis(100))?;
+        assert_eq!(summary.drained(), 0);
+        assert_eq!(summary.forced(), 1);
+        assert_eq!(stream.read(&mut [0; 1])?, 0);
+        Ok(())
+    }
+
+    fn test_cors_server(
         server_port: u16,
         requests: impl IntoIterator<Item = &'static str>,
         responses: impl IntoIterator<Item = &'static str>,
     ) -> Result<()> {
-        Server::new(|request| {
+        Server::builder(|_| Response::builder(Status::OK).with_body("home"))
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_cors(
+                CorsConfig::new()
+                    .with_allowed_origin("https://example.com")
+                    .unwrap()
+                    .with_allowed_method(Method::GET)
+                    .with_allowed_method(Method::PUT)
+                    .with_allowed_header(HeaderName::CONTENT_TYPE)
+                    .with_credentials_allowed()
+                    .with_max_age(Duration::from_secs(60)),
+            )
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        for (request, response) in requests.into_iter().zip(responses) {
+            stream.write_all(request.as_bytes())?;
+            let mut output = vec![b'\0'; response.len()];
+            stream.read_exact(&mut output)?;
+            assert_eq!(String::from_utf8(output).unwrap(), response);
+        }
+        Ok(())
+    }
+
+    fn test_server(
+        request_host: &'static str,
+        server_port: u16,
+        requests: impl IntoIterator<Item = &'static str>,
+        responses: impl IntoIterator<Item = &'static str>,
+    ) -> Result<()> {
+        Server::builder(|request| {
             if request.url().path() == "/" {
                 Response::builder(Status::OK).with_body("home")
             } else {
@@ -392,14 +2744,177 @@ mod tests {
             }
         })
         .bind((Ipv4Addr::LOCALHOST, server_port))
-        .bind((Ipv6Addr::LOCALHOST, server_port))
-        .with_server_name("OxHTTP/1.0")
-        .unwrap()
-        .with_global_timeout(Duration::from_secs(1))
+        .bind((Ipv6Addr::LOCALHOST, server_port))
+        .with_server_name("OxHTTP/1.0")
+        .with_global_timeout(Duration::from_secs(1))
+        .build()?
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((request_host, server_port))?;
+        for (request, response) in requests.into_iter().zip(responses) {
+            stream.write_all(request.as_bytes())?;
+            let mut output = vec![b'\0'; response.len()];
+            stream.read_exact(&mut output)?;
+            assert_eq!(String::from_utf8(output).unwrap(), response);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_limit() -> Result<()> {
+        let server_port = 9996;
+        let request = b"GET / HTTP/1.1\nhost: localhost:9999\n\n";
+        let response = b"HTTP/1.1 200 OK\r\nserver: OxHTTP/1.0\r\ncontent-length: 4\r\n\r\nhome";
+        Server::builder(|_| Response::builder(Status::OK).with_body("home"))
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .bind((Ipv6Addr::LOCALHOST, server_port))
+            .with_server_name("OxHTTP/1.0")
+            .with_global_timeout(Duration::from_secs(1))
+            .with_max_concurrent_connections(2)
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let streams = (0..128)
+            .map(|_| {
+                let mut stream = TcpStream::connect(("localhost", server_port))?;
+                stream.write_all(request)?;
+                Ok(stream)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for mut stream in streams {
+            let mut output = vec![b'\0'; response.len()];
+            stream.read_exact(&mut output)?;
+            assert_eq!(output, response);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_limit_per_listener() -> Result<()> {
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        let receiver = Mutex::new(receiver);
+        let server = Server::builder(move |request| {
+            if request.url().path() == "/block" {
+                receiver.lock().unwrap().recv().unwrap();
+            }
+            Response::builder(Status::OK).with_body("home")
+        })
+        .listen(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?)
+        .listen(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?)
+        .with_global_timeout(Duration::from_secs(5))
+        .with_max_concurrent_connections_per_listener(1)
+        .build()?
+        .spawn()?;
+        let (first_addr, second_addr) = (server.local_addrs()[0], server.local_addrs()[1]);
+        let send = |addr, path| -> Result<TcpStream> {
+            let mut stream = TcpStream::connect(addr)?;
+            write!(
+                stream,
+                "GET {path} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n"
+            )?;
+            Ok(stream)
+        };
+        let read = |mut stream: TcpStream| -> Result<String> {
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        // The first listener is saturated
+        let blocked = send(first_addr, "/block")?;
+        sleep(Duration::from_millis(100));
+        let waiting = send(first_addr, "/")?;
+        waiting.set_read_timeout(Some(Duration::from_millis(200)))?;
+        assert!((&waiting).read(&mut [0]).is_err());
+
+        // The second one is still usable
+        assert!(read(send(second_addr, "/")?)?.starts_with("HTTP/1.1 200 OK"));
+
+        sender.send(()).unwrap();
+        assert!(read(blocked)?.starts_with("HTTP/1.1 200 OK"));
+        waiting.set_read_timeout(None)?;
+        assert!(read(waiting)?.starts_with("HTTP/1.1 200 OK"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let on_request = |_: &mut Request| Response::builder(Status::OK).build();
+        assert!(Server::builder(on_request)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .build()
+            .is_ok());
+        assert!(Server::builder(on_request).build().is_err());
+        assert!(Server::builder(on_request)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_max_concurrent_connections(0)
+            .build()
+            .is_err());
+        assert!(Server::builder(on_request)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_max_concurrent_connections_per_listener(0)
+            .build()
+            .is_err());
+        assert!(Server::builder(on_request)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_buffer_capacity(1024)
+            .build()
+            .is_err());
+        assert!(Server::builder(on_request)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_server_name("foo\nbar")
+            .with_global_timeout(Duration::from_secs(1))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_trailers_only_if_requested() -> Result<()> {
+        struct WithTrailers {
+            body: &'static [u8],
+            trailers: Headers,
+        }
+
+        impl Read for WithTrailers {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+                self.body.read(buf)
+            }
+        }
+
+        impl ChunkedTransferPayload for WithTrailers {
+            fn trailers(&self) -> Option<&Headers> {
+                Some(&self.trailers)
+            }
+        }
+
+        let server_port = 9986;
+        Server::new(|_| {
+            let mut trailers = Headers::new();
+            trailers.append(
+                HeaderName::CONTENT_LANGUAGE,
+                HeaderValue::new_unchecked("en".as_bytes()),
+            );
+            Response::builder(Status::OK).with_body(Body::from_chunked_transfer_payload(
+                WithTrailers {
+                    body: b"home",
+                    trailers,
+                },
+            ))
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
         .spawn()?;
         sleep(Duration::from_millis(100)); // Makes sure the server is up
-        let mut stream = TcpStream::connect((request_host, server_port))?;
-        for (request, response) in requests.into_iter().zip(responses) {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        for (request, response) in [
+            (
+                "GET / HTTP/1.1\nhost: localhost:9986\nte: gzip, trailers\n\n",
+                "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n4\r\nhome\r\n0\r\ncontent-language: en\r\n\r\n",
+            ),
+            (
+                "GET / HTTP/1.1\nhost: localhost:9986\n\n",
+                "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n4\r\nhome\r\n0\r\n\r\n",
+            ),
+        ] {
             stream.write_all(request.as_bytes())?;
             let mut output = vec![b'\0'; response.len()];
             stream.read_exact(&mut output)?;
@@ -409,31 +2924,894 @@ mod tests {
     }
 
     #[test]
-    fn test_thread_limit() -> Result<()> {
-        let server_port = 9996;
-        let request = b"GET / HTTP/1.1\nhost: localhost:9999\n\n";
-        let response = b"HTTP/1.1 200 OK\r\nserver: OxHTTP/1.0\r\ncontent-length: 4\r\n\r\nhome";
-        Server::new(|_| Response::builder(Status::OK).with_body("home"))
+    fn test_pipelined_requests() -> Result<()> {
+        let server_port = 9984;
+        Server::new(|request| {
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).unwrap();
+            Response::builder(Status::OK).with_body(format!("{}:{body}", request.url().path()))
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        // Both requests are sent at once so the second one is buffered with the first one
+        stream.write_all(b"POST /a HTTP/1.1\nhost: localhost:9984\ncontent-length: 3\n\nfooPOST /b HTTP/1.1\nhost: localhost:9984\ncontent-length: 3\nconnection: close\n\nbar")?;
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+        assert_eq!(
+            output,
+            "HTTP/1.1 200 OK\r\ncontent-length: 6\r\n\r\n/a:fooHTTP/1.1 200 OK\r\ncontent-length: 6\r\n\r\n/b:bar"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_returning_string() -> Result<()> {
+        let server_port = 9983;
+        Server::new(|request| format!("Hello {}", request.url().path()))
             .bind((Ipv4Addr::LOCALHOST, server_port))
-            .bind((Ipv6Addr::LOCALHOST, server_port))
-            .with_server_name("OxHTTP/1.0")
-            .unwrap()
-            .with_global_timeout(Duration::from_secs(1))
-            .with_max_concurrent_connections(2)
             .spawn()?;
         sleep(Duration::from_millis(100)); // Makes sure the server is up
-        let streams = (0..128)
-            .map(|_| {
-                let mut stream = TcpStream::connect(("localhost", server_port))?;
-                stream.write_all(request)?;
-                Ok(stream)
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(b"GET /world HTTP/1.1\nhost: localhost:9983\nconnection: close\n\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: 12\r\n\r\nHello /world"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_100_continue_for_http_1_0() -> Result<()> {
+        let server_port = 9981;
+        Server::new(|request| {
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).unwrap();
+            body
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(b"POST / HTTP/1.0\nhost: localhost:9981\nexpect: 100-continue\ncontent-length: 3\n\nfoo")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: 3\r\n\r\nfoo"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_settings() -> Result<()> {
+        let server_port = 9980;
+        Server::builder(|_| std::thread::current().name().unwrap_or_default().to_owned())
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_thread_stack_size(128 * 1024)
+            .with_thread_name_prefix("test-")
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(b"GET / HTTP/1.1\nhost: localhost:9980\nconnection: close\n\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(body.starts_with("test-127.0.0.1:"), "{body}");
+        assert!(body.ends_with(": responding thread of OxHTTP"), "{body}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_header_size() -> Result<()> {
+        let server_port = 9978;
+        Server::builder(|request| {
+            request
+                .header(&HeaderName::USER_AGENT)
+                .map_or(0, |v| v.as_ref().len())
+                .to_string()
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
+        .with_max_header_size(32 * 1024)
+        .build()?
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        for (user_agent_len, expected_status) in
+            [(16 * 1024, "200 OK"), (40 * 1024, "400 Bad Request")]
+        {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+            stream.write_all(
+                format!(
+                    "GET / HTTP/1.1\nhost: localhost:9978\nuser-agent: {}\nconnection: close\n\n",
+                    "a".repeat(user_agent_len)
+                )
+                .as_bytes(),
+            )?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {expected_status}\r\n")),
+                "{response}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_unread_body_closes_connection() -> Result<()> {
+        let server_port = 9977;
+        Server::builder(|_| "ignored")
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        // We only send a part of the announced body: the server would hang if it tried to drain all of it
+        stream
+            .write_all(b"POST / HTTP/1.1\nhost: localhost:9977\ncontent-length: 1000000000\n\n")?;
+        stream.write_all(&vec![
+            b'a';
+            usize::try_from(MAX_DRAINED_BODY_SIZE).unwrap() + 1
+        ])?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: 7\r\n\r\nignored"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_connection_init() -> Result<()> {
+        let server_port = 9975;
+        Server::builder(|request| request.connection().unwrap().peer_addr().to_string())
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_connection_init(|stream| {
+                // We parse a PROXY protocol v1 line like "PROXY TCP4 <source> <destination> <source port> <destination port>"
+                let mut line = Vec::new();
+                while !line.ends_with(b"\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte)?;
+                    line.push(byte[0]);
+                }
+                let line =
+                    String::from_utf8(line).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                let parts = line.trim_end().split(' ').collect::<Vec<_>>();
+                let [_, _, source, _, source_port, _] = parts.as_slice() else {
+                    return Err(Error::new(ErrorKind::InvalidData, "Invalid PROXY line"));
+                };
+                let source = format!("{source}:{source_port}")
+                    .parse()
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                Ok(ConnectionContext::new(source))
             })
-            .collect::<Result<Vec<_>>>()?;
-        for mut stream in streams {
-            let mut output = vec![b'\0'; response.len()];
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(b"PROXY TCP4 192.0.2.1 127.0.0.1 56324 9975\r\nGET / HTTP/1.1\nhost: localhost:9975\nconnection: close\n\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert!(response.ends_with("\r\n\r\n192.0.2.1:56324"), "{response}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy_protocol() -> Result<()> {
+        let server_port = 9974;
+        Server::builder(|request| request.connection().unwrap().peer_addr().to_string())
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_proxy_protocol(ProxyProtocolVersion::V2)
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(
+            b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c\xc0\x00\x02\x01\x7f\x00\x00\x01\xdc\x04\x26\xb6",
+        )?;
+        stream.write_all(b"GET / HTTP/1.1\nhost: localhost\nconnection: close\n\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert!(response.ends_with("\r\n\r\n192.0.2.1:56324"), "{response}");
+
+        // Connections without the header are rejected
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+        stream.write_all(b"GET / HTTP/1.1\nhost: localhost\nconnection: close\n\n")?;
+        let mut response = String::new();
+        if let Err(e) = stream.read_to_string(&mut response) {
+            assert_eq!(e.kind(), ErrorKind::ConnectionReset);
+        }
+        assert_eq!(response, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy_protocol_max_connections_per_ip() -> Result<()> {
+        let server = Server::builder(|_| Response::builder(Status::OK).with_body("home"))
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_proxy_protocol(ProxyProtocolVersion::V2)
+            .with_max_connections_per_ip(1)
+            .build()?
+            .spawn()?;
+        let server_addr = server.local_addrs()[0];
+        let connect = |client_ip: u8| -> Result<(TcpStream, String)> {
+            let mut stream = TcpStream::connect(server_addr)?;
+            stream.write_all(b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c\xc0\x00\x02")?;
+            stream.write_all(&[client_ip])?;
+            stream.write_all(b"\x7f\x00\x00\x01\xdc\x04\x26\xb6")?;
+            stream.write_all(b"GET / HTTP/1.1\nhost: localhost\n\n")?;
+            let mut output = vec![0; 17];
             stream.read_exact(&mut output)?;
-            assert_eq!(output, response);
+            Ok((stream, String::from_utf8(output).unwrap()))
+        };
+        // The limit applies to the client addresses from the header and not to the proxy address
+        let (_first, response) = connect(1)?;
+        assert_eq!(response, "HTTP/1.1 200 OK\r\n");
+        let (_second, response) = connect(2)?;
+        assert_eq!(response, "HTTP/1.1 200 OK\r\n");
+        let (_third, response) = connect(1)?;
+        assert_eq!(response, "HTTP/1.1 429 Too ");
+        server.shutdown_graceful(Duration::from_secs(1))?;
+        Ok(())
+    }
+
+    #[cfg(all(feature = "client", feature = "rustls"))]
+    #[test]
+    fn test_rustls() -> Result<()> {
+        use crate::{Client, TofuTrustStore};
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+        #[cfg(any(
+            feature = "rustls-ring-platform-verifier",
+            feature = "rustls-ring-native",
+            feature = "rustls-ring-webpki"
+        ))]
+        let provider = rustls::crypto::ring::default_provider();
+        #[cfg(not(any(
+            feature = "rustls-ring-platform-verifier",
+            feature = "rustls-ring-native",
+            feature = "rustls-ring-webpki"
+        )))]
+        let provider = rustls::crypto::aws_lc_rs::default_provider();
+        let config = ServerConfig::builder_with_provider(Arc::new(provider))
+            .with_safe_default_protocol_versions()
+            .map_err(Error::other)?
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![
+                    CertificateDer::from_pem_slice(include_bytes!("../tests/data/localhost.crt"))
+                        .map_err(Error::other)?,
+                ],
+                PrivateKeyDer::from_pem_slice(include_bytes!("../tests/data/localhost.key"))
+                    .map_err(Error::other)?,
+            )
+            .map_err(Error::other)?;
+        Server::builder(|request| request.url().to_string())
+            .bind((Ipv4Addr::LOCALHOST, 9972))
+            .with_rustls_config(Arc::new(config))
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let client = Client::builder()
+            .with_certificate_trust_store(TofuTrustStore::new())
+            .with_connection_pool(1)
+            .build()?;
+        for _ in 0..2 {
+            let response = client.request(
+                Request::builder(Method::GET, "https://localhost:9972/foo".parse().unwrap())
+                    .build(),
+            )?;
+            assert_eq!(response.status(), Status::OK);
+            assert_eq!(
+                response.into_body().to_string()?,
+                "https://localhost:9972/foo"
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(all(feature = "client", feature = "native-tls"))]
+    #[test]
+    fn test_native_tls() -> Result<()> {
+        use crate::Client;
+        use native_tls::{Certificate, Identity, TlsConnector};
+
+        let certificate = include_bytes!("../tests/data/localhost.crt");
+        let acceptor = TlsAcceptor::new(
+            Identity::from_pkcs8(certificate, include_bytes!("../tests/data/localhost.key"))
+                .map_err(Error::other)?,
+        )
+        .map_err(Error::other)?;
+        Server::builder(|request| request.url().to_string())
+            .bind((Ipv4Addr::LOCALHOST, 9971))
+            .with_native_tls_acceptor(acceptor)
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let connector = TlsConnector::builder()
+            .add_root_certificate(Certificate::from_pem(certificate).map_err(Error::other)?)
+            .build()
+            .map_err(Error::other)?;
+        let response = Client::builder()
+            .with_native_tls_connector(connector)
+            .build()?
+            .request(
+                Request::builder(Method::GET, "https://localhost:9971/foo".parse().unwrap())
+                    .build(),
+            )?;
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(
+            response.into_body().to_string()?,
+            "https://localhost:9971/foo"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_access_log() -> Result<()> {
+        use std::sync::mpsc::channel;
+
+        let server_port = 9969;
+        let (sender, receiver) = channel();
+        Server::builder(|request| {
+            if request.url().path() == "/chunked" {
+                Response::builder(Status::OK).with_body(Body::from_read(b"hello".as_slice()))
+            } else {
+                Response::builder(Status::OK).with_body("hello")
+            }
+        })
+        .bind((Ipv4Addr::LOCALHOST, server_port))
+        .with_access_log(move |entry| sender.send(entry.clone()).unwrap())
+        .build()?
+        .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        for path in ["/fixed", "/chunked"] {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+            write!(
+                stream,
+                "GET {path} HTTP/1.1\nhost: localhost:{server_port}\nconnection: close\n\n"
+            )?;
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response)?;
+            let entry = receiver
+                .recv_timeout(Duration::from_secs(1))
+                .map_err(Error::other)?;
+            assert_eq!(entry.method(), Some(&Method::GET));
+            assert_eq!(
+                entry.url().unwrap().as_str(),
+                format!("http://localhost:{server_port}{path}")
+            );
+            assert_eq!(entry.status(), Status::OK);
+            assert_eq!(entry.peer_addr(), stream.local_addr()?);
+            assert_eq!(
+                entry.bytes_written(),
+                u64::try_from(response.len()).unwrap()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_interim_responses() -> Result<()> {
+        let server =
+            Server::builder(|request| std::mem::take(request.body_mut()).to_string().unwrap())
+                .bind((Ipv4Addr::LOCALHOST, 0))
+                .with_interim_responses(|request| {
+                    let status = if request.url().path() == "/invalid" {
+                        Status::OK
+                    } else {
+                        Status::EARLY_HINTS
+                    };
+                    vec![Response::builder(status)
+                        .with_header("link", "</style.css>; rel=preload")
+                        .unwrap()
+                        .build()]
+                })
+                .build()?
+                .spawn()?;
+        for (path, expected) in [
+            ("/", "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload\r\n\r\nHTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: 3\r\n\r\nfoo"),
+            ("/invalid", "HTTP/1.1 500 Internal Server Error\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: 39\r\n\r\nThe server failed to handle the request"),
+        ] {
+            let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+            write!(
+                stream,
+                "POST {path} HTTP/1.1\r\nhost: localhost\r\nexpect: 100-continue\r\nconnection: close\r\ncontent-length: 3\r\n\r\nfoo"
+            )?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert_eq!(response, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_on_response() -> Result<()> {
+        use std::sync::mpsc::channel;
+
+        let (sender, receiver) = channel();
+        let server = Server::builder(|_| {
+            sleep(Duration::from_millis(10));
+            Response::builder(Status::OK).with_body("hello")
+        })
+        .bind((Ipv4Addr::LOCALHOST, 0))
+        .with_on_response(move |request, response, duration| {
+            sender
+                .send((
+                    request.url().path().to_owned(),
+                    request.connection().map(ConnectionContext::peer_addr),
+                    response.status(),
+                    duration,
+                ))
+                .unwrap()
+        })
+        .build()?
+        .spawn()?;
+        for (path, body, status) in [
+            ("/ok", "3\r\nfoo\r\n0\r\n\r\n", Status::OK),
+            ("/invalid", "foo\r\n", Status::BAD_REQUEST),
+        ] {
+            let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+            write!(
+                stream,
+                "POST {path} HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n{body}"
+            )?;
+            stream.read_to_end(&mut Vec::new())?;
+            let (received_path, peer_addr, received_status, duration) = receiver
+                .recv_timeout(Duration::from_secs(1))
+                .map_err(Error::other)?;
+            assert_eq!(received_path, path);
+            assert_eq!(peer_addr, Some(stream.local_addr()?));
+            assert_eq!(received_status, status);
+            if status == Status::OK {
+                assert!(duration >= Duration::from_millis(10));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_body() -> Result<()> {
+        use std::fs::File;
+        use std::sync::mpsc::channel;
+
+        let path = std::env::temp_dir().join(format!("oxhttp-server-file-{}", std::process::id()));
+        let content = (0..10_000_000_u32)
+            .map(|i| u8::try_from(i.wrapping_mul(2_654_435_761) >> 24).unwrap())
+            .collect::<Vec<_>>();
+        std::fs::write(&path, &content)?;
+        let (sender, receiver) = channel();
+        let server = Server::builder({
+            let path = path.clone();
+            move |_| {
+                Response::builder(Status::OK)
+                    .with_body(Body::from_file(File::open(&path).unwrap()).unwrap())
+            }
+        })
+        .bind((Ipv4Addr::LOCALHOST, 0))
+        .with_access_log(move |entry| sender.send(entry.bytes_written()).unwrap())
+        .build()?
+        .spawn()?;
+        let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+        stream.write_all(b"GET / HTTP/1.1\nhost: localhost\nconnection: close\n\n")?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        std::fs::remove_file(&path)?;
+        let body_start = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        assert!(String::from_utf8_lossy(&response[..body_start])
+            .contains("content-length: 10000000\r\n"));
+        assert!(response[body_start..] == content);
+        assert_eq!(
+            receiver
+                .recv_timeout(Duration::from_secs(1))
+                .map_err(Error::other)?,
+            u64::try_from(response.len()).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_pool() -> Result<()> {
+        use std::collections::HashSet;
+        use std::thread::current;
+
+        let server_port = 9965;
+        let server = Server::builder(|_| current().name().unwrap_or_default().to_owned())
+            .bind((Ipv4Addr::LOCALHOST, server_port))
+            .with_thread_pool(2)
+            .build()?
+            .spawn()?;
+        sleep(Duration::from_millis(100)); // Makes sure the server is up
+        let mut thread_names = HashSet::new();
+        for _ in 0..10 {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port))?;
+            stream.write_all(b"GET / HTTP/1.1\nhost: localhost\nconnection: close\n\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            let (_, body) = response.split_once("\r\n\r\n").unwrap();
+            thread_names.insert(body.to_owned());
+        }
+        assert!(thread_names.len() <= 2, "{thread_names:?}");
+        assert!(thread_names
+            .iter()
+            .all(|name| name.starts_with("worker thread")));
+        server.shutdown_graceful(Duration::from_secs(1))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_listen() -> Result<()> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let listener_addr = listener.local_addr()?;
+        let server = Server::builder(|_| Status::OK)
+            .listen(listener)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .build()?
+            .spawn()?;
+        let local_addrs = server.local_addrs();
+        assert_eq!(local_addrs.len(), 2);
+        assert!(local_addrs.contains(&listener_addr));
+        for addr in local_addrs {
+            assert_ne!(addr.port(), 0);
+            let mut stream = TcpStream::connect(addr)?;
+            stream.write_all(b"GET / HTTP/1.1\nhost: localhost\nconnection: close\n\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        }
+        server.shutdown_graceful(Duration::from_secs(1))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_size_warning() -> Result<()> {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let server = Server::builder(|_| Status::OK)
+            .listen(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?)
+            .with_max_header_size(4 * 1024)
+            .with_header_size_warning(1024, {
+                let warnings = Arc::clone(&warnings);
+                move |size, peer_addr| warnings.lock().unwrap().push((size, peer_addr))
+            })
+            .build()?
+            .spawn()?;
+        let server_addr = server.local_addrs()[0];
+        for user_agent_len in [10, 2 * 1024] {
+            let mut stream = TcpStream::connect(server_addr)?;
+            stream.write_all(
+                format!(
+                    "GET / HTTP/1.1\nhost: localhost\nuser-agent: {}\nconnection: close\n\n",
+                    "a".repeat(user_agent_len)
+                )
+                .as_bytes(),
+            )?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        }
+        server.shutdown_graceful(Duration::from_secs(1))?;
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].0 > 2 * 1024 && warnings[0].0 < 4 * 1024);
+        assert_eq!(warnings[0].1.ip(), Ipv4Addr::LOCALHOST);
+
+        assert!(Server::builder(|_| Status::OK)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_max_header_size(1024)
+            .with_header_size_warning(1024, |_, _| ())
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_virtual_hosts() -> Result<()> {
+        let server = Server::builder(|_| Status::NOT_FOUND)
+            .listen(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?)
+            .with_virtual_host("a.example", |_| "a")
+            .with_virtual_host("B.Example", |_| "b")
+            .build()?
+            .spawn()?;
+        let server_addr = server.local_addrs()[0];
+        for (host, expected_response) in [
+            ("a.example", "200 OK"),
+            ("A.EXAMPLE:8080", "200 OK"),
+            ("b.example", "200 OK"),
+            ("c.example", "404 Not Found"),
+        ] {
+            let mut stream = TcpStream::connect(server_addr)?;
+            stream.write_all(
+                format!("GET / HTTP/1.1\nhost: {host}\nconnection: close\n\n").as_bytes(),
+            )?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert!(
+                response.starts_with(&format!("HTTP/1.1 {expected_response}\r\n")),
+                "{response}"
+            );
+            if expected_response == "200 OK" {
+                assert!(
+                    response.ends_with(&host[..1].to_ascii_lowercase()),
+                    "{response}"
+                );
+            }
+        }
+        server.shutdown_graceful(Duration::from_secs(1))?;
+
+        assert!(Server::builder(|_| Status::OK)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_virtual_host("a.example:80", |_| Status::OK)
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tcp_backlog() -> Result<()> {
+        let server = Server::builder(|_| Status::OK)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_tcp_backlog(4096)
+            .build()?
+            .spawn()?;
+        let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+        stream.write_all(b"GET / HTTP/1.1\nhost: localhost\nconnection: close\n\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        server.shutdown_graceful(Duration::from_secs(1))?;
+
+        assert!(Server::builder(|_| Status::OK)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_tcp_backlog(0)
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_global_body_budget() -> Result<()> {
+        let (entered_sender, entered_receiver) = std::sync::mpsc::channel();
+        let (release_sender, release_receiver) = std::sync::mpsc::channel::<()>();
+        let release_receiver = Mutex::new(release_receiver);
+        let server = Server::builder(move |request| {
+            let mut body = Vec::new();
+            if request.body_mut().read_to_end(&mut body).is_err() {
+                return Status::CONTENT_TOO_LARGE;
+            }
+            if request.url().path() == "/slow" {
+                entered_sender.send(()).unwrap();
+                release_receiver.lock().unwrap().recv().unwrap();
+            }
+            Status::OK
+        })
+        .bind((Ipv4Addr::LOCALHOST, 0))
+        .with_global_body_budget(10)
+        .build()?
+        .spawn()?;
+        let addr = server.local_addrs()[0];
+        let upload = |path: &str| -> Result<TcpStream> {
+            let mut stream = TcpStream::connect(addr)?;
+            write!(
+                stream,
+                "POST {path} HTTP/1.1\nhost: localhost\ncontent-length: 8\nconnection: close\n\n12345678"
+            )?;
+            Ok(stream)
+        };
+        let read_response = |mut stream: TcpStream| -> Result<String> {
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        // The first upload holds 8 bytes of the budget while its handler is blocked
+        let slow = upload("/slow")?;
+        entered_receiver.recv().unwrap();
+        let response = read_response(upload("/")?)?;
+        assert!(
+            response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{response}"
+        );
+
+        // The budget is released when the first request is done
+        release_sender.send(()).unwrap();
+        let response = read_response(slow)?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        let response = read_response(upload("/")?)?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+
+        // Chunked bodies are accounted for while being read
+        let mut stream = TcpStream::connect(addr)?;
+        stream.write_all(b"POST / HTTP/1.1\nhost: localhost\ntransfer-encoding: chunked\nconnection: close\n\n8\r\n12345678\r\n8\r\n12345678\r\n0\r\n\r\n")?;
+        let response = read_response(stream)?;
+        assert!(
+            response.starts_with("HTTP/1.1 413 Content Too Large\r\n"),
+            "{response}"
+        );
+        server.shutdown_graceful(Duration::from_secs(1))?;
+
+        assert!(Server::builder(|_| Status::OK)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_global_body_budget(0)
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_renderer() -> Result<()> {
+        let server = Server::builder(|_| Status::OK)
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_error_renderer(|status, message, request_headers| {
+                let accepts_json = request_headers
+                    .and_then(|headers| headers.get(&HeaderName::ACCEPT))
+                    .is_some_and(|accept| accept.as_ref() == b"application/json");
+                if !accepts_json {
+                    return build_text_response(status, message.into());
+                }
+                Response::builder(status)
+                    .with_header(HeaderName::CONTENT_TYPE, "application/json")
+                    .unwrap()
+                    .with_body(format!(
+                        "{{\"status\":{},\"error\":{message:?}}}",
+                        u16::from(status)
+                    ))
+            })
+            .build()?
+            .spawn()?;
+        let addr = server.local_addrs()[0];
+        let request = |request: &[u8]| -> Result<String> {
+            let mut stream = TcpStream::connect(addr)?;
+            stream.write_all(request)?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+
+        let response = request(
+            b"POST / HTTP/1.1\nhost: localhost\naccept: application/json\ncontent-length: foo\n\n",
+        )?;
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\ncontent-type: application/json\r\n"),
+            "{response}"
+        );
+        assert!(
+            response
+                .ends_with("\r\n\r\n{\"status\":400,\"error\":\"invalid digit found in string\"}"),
+            "{response}"
+        );
+
+        // Without parsed headers there is nothing to negotiate with
+        let response = request(b"GET / HTTP/1.1\nfoo\n\n")?;
+        assert!(
+            response.starts_with(
+                "HTTP/1.1 400 Bad Request\r\ncontent-type: text/plain; charset=utf-8\r\n"
+            ),
+            "{response}"
+        );
+        server.shutdown_graceful(Duration::from_secs(1))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_path() -> Result<()> {
+        let server =
+            Server::builder(|request| request.url()[url::Position::BeforePath..].to_owned())
+                .bind((Ipv4Addr::LOCALHOST, 0))
+                .with_normalize_path(true)
+                .build()?
+                .spawn()?;
+        for (target, expected) in [
+            ("/a/./b/../c?x=/../y", "HTTP/1.1 200 OK"),
+            ("/a/%2e%2E/b", "HTTP/1.1 200 OK"),
+            ("/../etc/passwd", "HTTP/1.1 400 Bad Request"),
+            ("/a/%2E./../b", "HTTP/1.1 400 Bad Request"),
+            ("/a/..%2F..%2Fb", "HTTP/1.1 400 Bad Request"),
+            ("/a%zz", "HTTP/1.1 400 Bad Request"),
+        ] {
+            let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+            write!(
+                stream,
+                "GET {target} HTTP/1.1\nhost: localhost\nconnection: close\n\n"
+            )?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert!(response.starts_with(expected), "{target}: {response}");
+            if target == "/a/./b/../c?x=/../y" {
+                assert!(response.ends_with("\r\n\r\n/a/c?x=/../y"), "{response}");
+            }
+        }
+        server.shutdown_graceful(Duration::from_secs(1))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_catch_panics() -> Result<()> {
+        for catch_panics in [true, false] {
+            let server = Server::builder(|request| {
+                assert_ne!(request.url().path(), "/panic", "Panic requested");
+                Status::OK
+            })
+            .bind((Ipv4Addr::LOCALHOST, 0))
+            .with_catch_panics(catch_panics)
+            .build()?
+            .spawn()?;
+            let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+            stream.write_all(
+                b"GET /panic HTTP/1.1\nhost: localhost\n\nGET / HTTP/1.1\nhost: localhost\n\n",
+            )?;
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response); // The connection might be reset
+            if catch_panics {
+                // The connection is closed after the error
+                assert!(
+                    response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+                    "{response}"
+                );
+                assert!(
+                    response.ends_with("\r\n\r\nThe server failed to handle the request"),
+                    "{response}"
+                );
+            } else {
+                assert_eq!(response, "");
+            }
+
+            // The server is still working
+            let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+            stream.write_all(b"GET / HTTP/1.1\nhost: localhost\nconnection: close\n\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+            server.shutdown_graceful(Duration::from_secs(1))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_trailers() -> Result<()> {
+        // The body budget wraps the request body, the trailers must still be reachable
+        for body_budget in [None, Some(1024)] {
+            let mut builder = Server::builder(|request| {
+                let mut body = String::new();
+                request.body_mut().read_to_string(&mut body).unwrap();
+                let checksum = request
+                    .body()
+                    .trailers()
+                    .and_then(|trailers| trailers.get(&HeaderName::new_unchecked("x-checksum")))
+                    .and_then(|checksum| checksum.to_str().ok())
+                    .unwrap_or_default()
+                    .to_owned();
+                Response::builder(Status::OK).with_body(format!("{body} {checksum}"))
+            })
+            .bind((Ipv4Addr::LOCALHOST, 0));
+            if let Some(body_budget) = body_budget {
+                builder = builder.with_global_body_budget(body_budget);
+            }
+            let server = builder.build()?.spawn()?;
+            let mut stream = TcpStream::connect(server.local_addrs()[0])?;
+            stream.write_all(b"POST / HTTP/1.1\nhost: localhost\ntransfer-encoding: chunked\nconnection: close\n\n3\r\nfoo\r\n0\r\nx-checksum: 2c26b46b\r\n\r\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            assert!(response.ends_with("\r\n\r\nfoo 2c26b46b"), "{response}");
+            server.shutdown_graceful(Duration::from_secs(1))?;
         }
         Ok(())
     }
+
+    #[test]
+    fn test_http2_preface() -> Result<()> {
+        test_server(
+            "localhost",
+            9979,
+            ["PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"],
+            ["HTTP/1.1 505 HTTP Version Not Supported\r\ncontent-type: text/plain; charset=utf-8\r\nserver: OxHTTP/1.0\r\ncontent-length: 86\r\n\r\nThe HTTP/2 connection preface has been received but this server only supports HTTP/1.1"],
+        )
+    }
 }
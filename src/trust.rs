@@ -0,0 +1,154 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Decides if the certificate presented by a server should be trusted.
+///
+/// It replaces the validation against root certificates when set with [`ClientBuilder::with_certificate_trust_store`](crate::ClientBuilder::with_certificate_trust_store).
+/// The TLS handshake signatures are still checked against the certificate.
+pub trait CertTrustStore: Send + Sync {
+    /// Returns if the `certificate` (DER encoded end-entity certificate) presented by the server `server_name` is trusted.
+    fn is_trusted(&self, server_name: &str, certificate: &[u8]) -> bool;
+}
+
+/// In-memory [trust on first use](https://en.wikipedia.org/wiki/Trust_on_first_use) [`CertTrustStore`].
+///
+/// The first certificate presented by a server is recorded and trusted.
+/// The later connections to the same server are only trusted if they present the same certificate.
+///
+/// ```
+/// use oxhttp::{Client, TofuTrustStore};
+///
+/// let client = Client::builder()
+///     .with_certificate_trust_store(TofuTrustStore::new())
+///     .build()?;
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+#[derive(Default)]
+pub struct TofuTrustStore {
+    certificates: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl TofuTrustStore {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CertTrustStore for TofuTrustStore {
+    fn is_trusted(&self, server_name: &str, certificate: &[u8]) -> bool {
+        self.certificates
+            .lock()
+            .unwrap()
+            .entry(server_name.into())
+            .or_insert_with(|| certificate.into())
+            == certificate
+    }
+}
+
+/// [`CertTrustStore`] trusting all certificates.
+///
+/// See [`ClientBuilder::with_dangerous_accept_invalid_certs`](crate::ClientBuilder::with_dangerous_accept_invalid_certs).
+pub struct AcceptAllTrustStore;
+
+impl CertTrustStore for AcceptAllTrustStore {
+    fn is_trusted(&self, _server_name: &str, _certificate: &[u8]) -> bool {
+        true
+    }
+}
+
+/// Rustls verifier delegating the certificate validation to a [`CertTrustStore`]
+pub struct TrustStoreVerifier {
+    store: Arc<dyn CertTrustStore>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl TrustStoreVerifier {
+    pub fn new(store: Arc<dyn CertTrustStore>, provider: Arc<CryptoProvider>) -> Self {
+        Self { store, provider }
+    }
+}
+
+impl fmt::Debug for TrustStoreVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrustStoreVerifier").finish_non_exhaustive()
+    }
+}
+
+impl ServerCertVerifier for TrustStoreVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let server_name = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_owned(),
+            ServerName::IpAddress(ip) => IpAddr::from(*ip).to_string(),
+            _ => return Err(rustls::Error::UnsupportedNameType),
+        };
+        if self.store.is_trusted(&server_name, end_entity) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tofu_records_first_certificate() {
+        let store = TofuTrustStore::new();
+        assert!(store.is_trusted("example.com", b"first"));
+        assert!(store.is_trusted("example.com", b"first"));
+        assert!(!store.is_trusted("example.com", b"second"));
+        assert!(store.is_trusted("example.org", b"second"));
+    }
+}
@@ -10,3 +10,50 @@ pub fn invalid_data_error(error: impl Into<Box<dyn Error + Send + Sync>>) -> io:
 pub fn invalid_input_error(error: impl Into<Box<dyn Error + Send + Sync>>) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidInput, error)
 }
+
+/// Encodes the bytes using [base64](https://www.rfc-editor.org/rfc/rfc4648#section-4) with padding
+#[cfg(feature = "client")]
+pub fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let indices = [
+            bytes[0] >> 2,
+            ((bytes[0] & 0b11) << 4) | (bytes[1] >> 4),
+            ((bytes[1] & 0b1111) << 2) | (bytes[2] >> 6),
+            bytes[2] & 0b11_1111,
+        ];
+        for (i, index) in indices.into_iter().enumerate() {
+            output.push(if i <= chunk.len() {
+                char::from(ALPHABET[usize::from(index)])
+            } else {
+                '='
+            });
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(
+            base64_encode(b"Aladdin:open sesame"),
+            "QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+}